@@ -0,0 +1,165 @@
+use std::f32::consts::PI;
+
+use super::FilterKind;
+
+/// Highest filter frequency relative to the Nyquist frequency, keeps filters stable.
+const MAX_FREQ_RATIO: f32 = 0.95;
+
+/// Normalized biquad filter coefficients.
+///
+/// Calculated as described in the Audio EQ Cookbook by Robert Bristow-Johnson.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    /// Calculate coefficients for filter at given sample rate.
+    pub fn new(kind: FilterKind, sample_rate: u32, freq: f32, gain: f32, q: f32) -> Self {
+        let freq = freq.min(sample_rate as f32 / 2.0 * MAX_FREQ_RATIO).max(1.0);
+        let a = 10f32.powf(gain / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            FilterKind::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            FilterKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+            FilterKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Get filter gain at given frequency, as linear factor.
+    pub fn magnitude(&self, sample_rate: u32, freq: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate as f32;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+        let num =
+            (self.b0 + self.b1 * cos1 + self.b2 * cos2).hypot(self.b1 * sin1 + self.b2 * sin2);
+        let den = (1.0 + self.a1 * cos1 + self.a2 * cos2).hypot(self.a1 * sin1 + self.a2 * sin2);
+        num / den
+    }
+}
+
+/// Biquad filter for a single channel.
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    coefficients: Coefficients,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// Construct filter with given coefficients.
+    pub fn new(coefficients: Coefficients) -> Self {
+        Self {
+            coefficients,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// Change coefficients, keeping filter state to prevent clicks.
+    pub fn set_coefficients(&mut self, coefficients: Coefficients) {
+        self.coefficients = coefficients;
+    }
+
+    /// Process a single sample.
+    #[inline]
+    pub fn process(&mut self, x: f32) -> f32 {
+        // Transposed direct form II
+        let c = &self.coefficients;
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn db(gain: f32) -> f32 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn zero_gain_is_unity() {
+        for kind in &[
+            FilterKind::Peak,
+            FilterKind::LowShelf,
+            FilterKind::HighShelf,
+        ] {
+            let c = Coefficients::new(*kind, SAMPLE_RATE, 1000.0, 0.0, 0.707);
+            for freq in &[20.0, 100.0, 1000.0, 10000.0, 20000.0] {
+                assert!((c.magnitude(SAMPLE_RATE, *freq) - 1.0).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_gain_passes_samples() {
+        let c = Coefficients::new(FilterKind::Peak, SAMPLE_RATE, 1000.0, 0.0, 1.41);
+        let mut filter = Biquad::new(c);
+        for i in 0..100 {
+            let x = (i as f32 * 0.1).sin();
+            assert!((filter.process(x) - x).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn peak_gain_at_center() {
+        let c = Coefficients::new(FilterKind::Peak, SAMPLE_RATE, 1000.0, 6.0, 1.41);
+        assert!((db(c.magnitude(SAMPLE_RATE, 1000.0)) - 6.0).abs() < 0.05);
+        assert!(db(c.magnitude(SAMPLE_RATE, 20.0)).abs() < 0.1);
+        assert!(db(c.magnitude(SAMPLE_RATE, 20000.0)).abs() < 0.1);
+    }
+
+    #[test]
+    fn shelf_gain() {
+        let low = Coefficients::new(FilterKind::LowShelf, SAMPLE_RATE, 100.0, 6.0, 0.707);
+        assert!((db(low.magnitude(SAMPLE_RATE, 20.0)) - 6.0).abs() < 0.5);
+        assert!(db(low.magnitude(SAMPLE_RATE, 10000.0)).abs() < 0.05);
+
+        let high = Coefficients::new(FilterKind::HighShelf, SAMPLE_RATE, 8000.0, -4.0, 0.707);
+        assert!((db(high.magnitude(SAMPLE_RATE, 20000.0)) + 4.0).abs() < 0.5);
+        assert!(db(high.magnitude(SAMPLE_RATE, 100.0)).abs() < 0.05);
+    }
+}
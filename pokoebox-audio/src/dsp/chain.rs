@@ -0,0 +1,419 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::biquad::{Biquad, Coefficients};
+use super::{Config, FilterKind, Preset, SHELF_Q};
+
+/// Corner frequency of the bass boost filter in Hz.
+const BASS_BOOST_FREQ: f32 = 100.0;
+
+/// Loudness compensation bass corner frequency in Hz.
+const LOUDNESS_BASS_FREQ: f32 = 100.0;
+
+/// Loudness compensation treble corner frequency in Hz.
+const LOUDNESS_TREBLE_FREQ: f32 = 8000.0;
+
+/// Loudness compensation bass boost at lowest volume in dB.
+const LOUDNESS_BASS_GAIN: f32 = 10.0;
+
+/// Loudness compensation treble boost at lowest volume in dB.
+const LOUDNESS_TREBLE_GAIN: f32 = 4.0;
+
+/// Smallest volume level change to update loudness compensation for.
+const VOLUME_LEVEL_STEP: f32 = 0.01;
+
+/// Audible frequency range in Hz, to find the peak of the combined filter response in.
+const RESPONSE_FREQ_RANGE: (f32, f32) = (20.0, 20000.0);
+
+/// Number of frequencies to check to find the peak of the combined filter response.
+const RESPONSE_POINTS: usize = 128;
+
+/// Time to ramp to a new pre-gain in, prevents clicks when settings change.
+const PRE_GAIN_RAMP: Duration = Duration::from_millis(50);
+
+/// DSP settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Whether to process audio.
+    pub enabled: bool,
+
+    /// Name of the preset the current settings are based on.
+    pub preset_name: String,
+
+    /// Current preset, may be edited.
+    pub preset: Preset,
+
+    /// Current volume level in `[0, 1]`, for loudness compensation.
+    pub volume_level: f32,
+}
+
+impl Settings {
+    /// Get filters to apply, as filter kind, frequency, gain and quality factor.
+    ///
+    /// Filters with zero gain are included, so editing gains doesn't change the filter layout.
+    fn filters(&self) -> Vec<(FilterKind, f32, f32, f32)> {
+        let preset = &self.preset;
+        let mut filters: Vec<_> = preset
+            .bands
+            .iter()
+            .map(|band| (band.kind, band.freq, band.gain, band.q))
+            .collect();
+        filters.push((
+            FilterKind::LowShelf,
+            BASS_BOOST_FREQ,
+            preset.bass_boost,
+            SHELF_Q,
+        ));
+
+        // Quiet playback sounds thin, boost bass and treble more the lower the volume
+        if preset.loudness {
+            let quietness = 1.0 - self.volume_level.clamp(0.0, 1.0);
+            filters.push((
+                FilterKind::LowShelf,
+                LOUDNESS_BASS_FREQ,
+                LOUDNESS_BASS_GAIN * quietness,
+                SHELF_Q,
+            ));
+            filters.push((
+                FilterKind::HighShelf,
+                LOUDNESS_TREBLE_FREQ,
+                LOUDNESS_TREBLE_GAIN * quietness,
+                SHELF_Q,
+            ));
+        }
+
+        filters
+    }
+}
+
+/// Shared DSP settings handle.
+///
+/// Cheap to clone, all clones share the same settings. Changes apply live to all `Chain`s.
+#[derive(Clone, Debug)]
+pub struct Dsp {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Current settings.
+    settings: RwLock<Settings>,
+
+    /// Incremented on each settings change.
+    generation: AtomicUsize,
+}
+
+impl Dsp {
+    /// Construct DSP with preset from config.
+    ///
+    /// Falls back to the default preset if the configured preset is unknown.
+    pub fn new(config: &Config) -> Self {
+        let presets = config.presets();
+        let (preset_name, preset) = match presets.get(&config.preset) {
+            Some(preset) => (config.preset.clone(), preset.clone()),
+            None => {
+                error!(
+                    "Unknown DSP preset '{}', using '{}'",
+                    config.preset,
+                    super::DEFAULT_PRESET
+                );
+                (super::DEFAULT_PRESET.into(), Preset::default())
+            }
+        };
+
+        Self {
+            inner: Arc::new(Inner {
+                settings: RwLock::new(Settings {
+                    enabled: config.enabled,
+                    preset_name,
+                    preset,
+                    volume_level: 1.0,
+                }),
+                generation: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Get a snapshot of the current settings.
+    pub fn settings(&self) -> Settings {
+        self.inner
+            .settings
+            .read()
+            .expect("failed to lock DSP settings")
+            .clone()
+    }
+
+    /// Change settings, applied live.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Settings),
+    {
+        f(&mut self
+            .inner
+            .settings
+            .write()
+            .expect("failed to lock DSP settings"));
+        self.inner.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Set current volume level in `[0, 1]`, for loudness compensation.
+    pub fn set_volume_level(&self, level: f32) {
+        let current = self.settings();
+        if (current.volume_level - level).abs() < VOLUME_LEVEL_STEP {
+            return;
+        }
+        self.update(|settings| settings.volume_level = level);
+    }
+
+    fn generation(&self) -> usize {
+        self.inner.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// DSP filter chain processing a single stream.
+pub struct Chain {
+    dsp: Dsp,
+
+    /// Settings generation filters are built for.
+    generation: Option<usize>,
+
+    /// Stream format, as channel count and sample rate.
+    format: (u16, u32),
+
+    /// Whether processing is enabled.
+    enabled: bool,
+
+    /// Gain applied before filtering, to leave headroom for boosts.
+    pre_gain: f32,
+
+    /// Pre-gain to ramp to, and step for each frame.
+    pre_gain_ramp: (f32, f32),
+
+    /// Filters for each channel.
+    filters: Vec<Vec<Biquad>>,
+}
+
+impl Chain {
+    /// Construct chain for stream with given format.
+    pub fn new(dsp: Dsp, channels: u16, sample_rate: u32) -> Self {
+        Self {
+            dsp,
+            generation: None,
+            format: (channels, sample_rate),
+            enabled: false,
+            pre_gain: 1.0,
+            pre_gain_ramp: (1.0, 0.0),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Set stream format, filters are rebuilt if it changed.
+    pub fn set_format(&mut self, channels: u16, sample_rate: u32) {
+        if self.format != (channels, sample_rate) {
+            self.format = (channels, sample_rate);
+            self.generation = None;
+        }
+    }
+
+    /// Process a sample for given channel.
+    ///
+    /// Settings changes are picked up at the start of each frame, when processing channel 0.
+    #[inline]
+    pub fn process(&mut self, channel: usize, sample: f32) -> f32 {
+        if channel == 0 {
+            self.sync();
+            self.step_pre_gain();
+        }
+        if !self.enabled {
+            return sample;
+        }
+
+        match self.filters.get_mut(channel) {
+            Some(filters) => filters
+                .iter_mut()
+                .fold(sample * self.pre_gain, |sample, filter| {
+                    filter.process(sample)
+                }),
+            None => sample,
+        }
+    }
+
+    /// Rebuild filters if settings changed.
+    fn sync(&mut self) {
+        let generation = self.dsp.generation();
+        if self.generation == Some(generation) {
+            return;
+        }
+        self.generation = Some(generation);
+
+        let settings = self.dsp.settings();
+        let (channels, sample_rate) = self.format;
+        let filters = settings.filters();
+        let coefficients: Vec<_> = filters
+            .iter()
+            .map(|(kind, freq, gain, q)| Coefficients::new(*kind, sample_rate, *freq, *gain, *q))
+            .collect();
+
+        // Leave headroom for the peak of the combined response, overlapping boosts add up
+        self.enabled = settings.enabled;
+        let freqs: Vec<_> = filters.iter().map(|(_, freq, _, _)| *freq).collect();
+        let pre_gain = 1.0 / peak_gain(&coefficients, &freqs, sample_rate).max(1.0);
+        self.set_pre_gain(pre_gain, self.filters.is_empty());
+
+        // Update coefficients in place if filter layout is unchanged, keeps state to prevent clicks
+        self.filters.resize_with(channels as usize, Vec::new);
+        for filters in self.filters.iter_mut() {
+            if filters.len() == coefficients.len() {
+                filters
+                    .iter_mut()
+                    .zip(&coefficients)
+                    .for_each(|(filter, c)| filter.set_coefficients(*c));
+            } else {
+                *filters = coefficients.iter().copied().map(Biquad::new).collect();
+            }
+        }
+    }
+
+    /// Set pre-gain, ramped over `PRE_GAIN_RAMP` unless `instant`.
+    fn set_pre_gain(&mut self, pre_gain: f32, instant: bool) {
+        let frames = self.format.1 as f32 * PRE_GAIN_RAMP.as_secs_f32();
+        if instant || frames < 1.0 {
+            self.pre_gain = pre_gain;
+            self.pre_gain_ramp = (pre_gain, 0.0);
+        } else {
+            self.pre_gain_ramp = (pre_gain, (pre_gain - self.pre_gain) / frames);
+        }
+    }
+
+    /// Step pre-gain towards its ramp target, once each frame.
+    #[inline]
+    fn step_pre_gain(&mut self) {
+        let (target, step) = self.pre_gain_ramp;
+        if self.pre_gain == target {
+            return;
+        }
+        self.pre_gain += step;
+        if (step >= 0.0 && self.pre_gain >= target) || (step < 0.0 && self.pre_gain <= target) {
+            self.pre_gain = target;
+        }
+    }
+}
+
+/// Get peak gain of filters applied in series, as linear factor.
+///
+/// Checks log-spaced frequencies over the audible range, and the filter frequencies themselves.
+fn peak_gain(coefficients: &[Coefficients], freqs: &[f32], sample_rate: u32) -> f32 {
+    let (low, high) = RESPONSE_FREQ_RANGE;
+    let high = high.min(sample_rate as f32 / 2.0);
+    let step = (high / low).powf(1.0 / (RESPONSE_POINTS - 1) as f32);
+    (0..RESPONSE_POINTS)
+        .map(|i| low * step.powi(i as i32))
+        .chain(freqs.iter().copied())
+        .map(|freq| {
+            coefficients
+                .iter()
+                .map(|c| c.magnitude(sample_rate, freq))
+                .product::<f32>()
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Band;
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Construct DSP with given preset.
+    fn dsp(preset: Preset) -> Dsp {
+        let dsp = Dsp::new(&Config::default());
+        dsp.update(|settings| settings.preset = preset);
+        dsp
+    }
+
+    /// Get peak gain of the combined filter response for given settings.
+    fn peak(settings: &Settings) -> f32 {
+        let filters = settings.filters();
+        let coefficients: Vec<_> = filters
+            .iter()
+            .map(|(kind, freq, gain, q)| Coefficients::new(*kind, SAMPLE_RATE, *freq, *gain, *q))
+            .collect();
+        let freqs: Vec<_> = filters.iter().map(|(_, freq, _, _)| *freq).collect();
+        peak_gain(&coefficients, &freqs, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn flat_is_unity() {
+        let dsp = dsp(Preset::flat());
+        assert!((peak(&dsp.settings()) - 1.0).abs() < 1e-3);
+
+        let mut chain = Chain::new(dsp, 2, SAMPLE_RATE);
+        for i in 0..1000 {
+            let x = (i as f32 * 0.05).sin() * 0.9;
+            for channel in 0..2 {
+                assert!((chain.process(channel, x) - x).abs() < 1e-3);
+            }
+        }
+        assert_eq!(chain.pre_gain, 1.0);
+    }
+
+    #[test]
+    fn boosts_leave_headroom() {
+        let mut presets: Vec<_> = Preset::builtin().into_values().collect();
+        presets.push(Preset {
+            bands: vec![
+                Band {
+                    kind: FilterKind::Peak,
+                    freq: 80.0,
+                    gain: 9.0,
+                    q: 1.0,
+                },
+                Band {
+                    kind: FilterKind::LowShelf,
+                    freq: 120.0,
+                    gain: 6.0,
+                    q: SHELF_Q,
+                },
+            ],
+            bass_boost: 6.0,
+            loudness: true,
+        });
+
+        for preset in presets {
+            for volume_level in &[0.0, 0.5, 1.0] {
+                let dsp = dsp(preset.clone());
+                dsp.update(|settings| settings.volume_level = *volume_level);
+                let mut chain = Chain::new(dsp.clone(), 1, SAMPLE_RATE);
+                chain.process(0, 0.0);
+
+                let peak = peak(&dsp.settings());
+                assert!(chain.pre_gain <= 1.0);
+                assert!(20.0 * (peak * chain.pre_gain).log10() <= 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn pre_gain_ramps() {
+        let dsp = dsp(Preset::flat());
+        let mut chain = Chain::new(dsp.clone(), 1, SAMPLE_RATE);
+        chain.process(0, 0.0);
+        assert_eq!(chain.pre_gain, 1.0);
+
+        // Boost, pre-gain must ramp down without jumping
+        dsp.update(|settings| settings.preset.bass_boost = 12.0);
+        let target = 1.0 / peak(&dsp.settings());
+        let frames = (SAMPLE_RATE as f32 * PRE_GAIN_RAMP.as_secs_f32()) as usize;
+        let max_step = (1.0 - target) / frames as f32 * 1.01;
+        let mut last = chain.pre_gain;
+        for _ in 0..frames + 2 {
+            chain.process(0, 0.0);
+            assert!(chain.pre_gain <= last);
+            assert!(last - chain.pre_gain <= max_step);
+            last = chain.pre_gain;
+        }
+        assert_eq!(chain.pre_gain, target);
+    }
+}
@@ -0,0 +1,164 @@
+pub mod biquad;
+pub mod chain;
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+// Re-export
+pub use biquad::{Biquad, Coefficients};
+pub use chain::{Chain, Dsp, Settings};
+
+/// Name of the default preset.
+pub const DEFAULT_PRESET: &str = "flat";
+
+/// Center frequencies of the built-in graphic equalizer bands in Hz.
+pub const GRAPHIC_BANDS: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Quality factor for graphic equalizer bands, one octave wide.
+const GRAPHIC_Q: f32 = 1.41;
+
+/// Default quality factor for shelving filters.
+const SHELF_Q: f32 = 0.707;
+
+/// DSP configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Whether to process audio.
+    pub enabled: bool,
+
+    /// Name of preset to use at startup.
+    pub preset: String,
+
+    /// Custom presets by name, override built-in presets with the same name.
+    pub presets: HashMap<String, Preset>,
+}
+
+impl Config {
+    /// Get built-in and custom presets by name.
+    pub fn presets(&self) -> BTreeMap<String, Preset> {
+        let mut presets = Preset::builtin();
+        presets.extend(self.presets.clone());
+        presets
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            preset: DEFAULT_PRESET.into(),
+            presets: HashMap::new(),
+        }
+    }
+}
+
+/// DSP preset.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    /// Equalizer bands.
+    pub bands: Vec<Band>,
+
+    /// Bass boost in dB.
+    pub bass_boost: f32,
+
+    /// Whether to compensate loudness at low volume.
+    pub loudness: bool,
+}
+
+impl Preset {
+    /// Flat graphic equalizer preset.
+    pub fn flat() -> Self {
+        Self::graphic(&[0.0; GRAPHIC_BANDS.len()], 0.0, false)
+    }
+
+    /// Graphic equalizer preset with given gain in dB for each of `GRAPHIC_BANDS`.
+    pub fn graphic(gains: &[f32; GRAPHIC_BANDS.len()], bass_boost: f32, loudness: bool) -> Self {
+        Self {
+            bands: GRAPHIC_BANDS
+                .iter()
+                .zip(gains)
+                .map(|(freq, gain)| Band {
+                    kind: FilterKind::Peak,
+                    freq: *freq,
+                    gain: *gain,
+                    q: GRAPHIC_Q,
+                })
+                .collect(),
+            bass_boost,
+            loudness,
+        }
+    }
+
+    /// Built-in presets by name.
+    pub fn builtin() -> BTreeMap<String, Preset> {
+        let mut presets = BTreeMap::new();
+        presets.insert(DEFAULT_PRESET.into(), Self::flat());
+        presets.insert(
+            "boombox".into(),
+            Self::graphic(
+                &[0.0, 4.0, 3.0, 1.0, -1.0, -1.0, 0.0, 1.0, 2.0, 1.0],
+                3.0,
+                true,
+            ),
+        );
+        presets.insert(
+            "voice".into(),
+            Self::graphic(
+                &[-6.0, -4.0, -2.0, 0.0, 2.0, 3.0, 3.0, 2.0, 0.0, -2.0],
+                0.0,
+                false,
+            ),
+        );
+        presets
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self::flat()
+    }
+}
+
+/// Equalizer band.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Band {
+    /// Filter type.
+    #[serde(default)]
+    pub kind: FilterKind,
+
+    /// Center or corner frequency in Hz.
+    pub freq: f32,
+
+    /// Gain in dB.
+    #[serde(default)]
+    pub gain: f32,
+
+    /// Quality factor, bandwidth of the filter.
+    #[serde(default = "default_q")]
+    pub q: f32,
+}
+
+fn default_q() -> f32 {
+    GRAPHIC_Q
+}
+
+/// Equalizer filter type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    /// Boost or cut around center frequency.
+    #[default]
+    Peak,
+
+    /// Boost or cut below corner frequency.
+    LowShelf,
+
+    /// Boost or cut above corner frequency.
+    HighShelf,
+}
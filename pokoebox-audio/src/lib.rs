@@ -1,4 +1,6 @@
 #[macro_use]
 extern crate log;
 
+pub mod dsp;
+pub mod meter;
 pub mod volume;
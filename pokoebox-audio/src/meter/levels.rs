@@ -0,0 +1,110 @@
+/// Lowest level in dB, used for silence.
+pub const SILENCE_DB: f32 = -96.0;
+
+/// Audio levels per channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Levels {
+    /// Peak level per channel, linear in `[0, 1]`.
+    pub peak: Vec<f32>,
+
+    /// RMS level per channel, linear in `[0, 1]`.
+    pub rms: Vec<f32>,
+}
+
+impl Levels {
+    /// Highest peak level of all channels.
+    pub fn max_peak(&self) -> f32 {
+        self.peak.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Highest RMS level of all channels.
+    pub fn max_rms(&self) -> f32 {
+        self.rms.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Whether all levels are zero.
+    pub fn is_silent(&self) -> bool {
+        self.max_peak() <= 0.0
+    }
+
+    /// Convert linear level to dB, clamped to `SILENCE_DB`.
+    pub fn to_db(level: f32) -> f32 {
+        if level <= 0.0 {
+            return SILENCE_DB;
+        }
+        (20.0 * level.log10()).max(SILENCE_DB)
+    }
+}
+
+/// Accumulates samples per channel to compute levels from.
+#[derive(Clone, Debug, Default)]
+pub struct Accumulator {
+    /// Peak absolute sample per channel.
+    peak: Vec<f32>,
+
+    /// Sum of squared samples per channel.
+    sum_squares: Vec<f64>,
+
+    /// Number of samples per channel.
+    count: Vec<u64>,
+}
+
+impl Accumulator {
+    /// Add sample in `[-1, 1]` for given channel.
+    #[inline]
+    pub fn add(&mut self, channel: usize, sample: f32) {
+        if channel >= self.count.len() {
+            self.peak.resize(channel + 1, 0.0);
+            self.sum_squares.resize(channel + 1, 0.0);
+            self.count.resize(channel + 1, 0);
+        }
+
+        self.peak[channel] = self.peak[channel].max(sample.abs());
+        self.sum_squares[channel] += (sample as f64) * (sample as f64);
+        self.count[channel] += 1;
+    }
+
+    /// Whether no samples were added.
+    pub fn is_empty(&self) -> bool {
+        self.count.iter().all(|c| *c == 0)
+    }
+
+    /// Merge samples from other accumulator into this one.
+    ///
+    /// Peaks of overlapping sounds are combined by maximum, not by sum.
+    pub fn merge(&mut self, other: &Accumulator) {
+        for channel in 0..other.count.len() {
+            if channel >= self.count.len() {
+                self.peak.push(0.0);
+                self.sum_squares.push(0.0);
+                self.count.push(0);
+            }
+            self.peak[channel] = self.peak[channel].max(other.peak[channel]);
+            self.sum_squares[channel] += other.sum_squares[channel];
+            self.count[channel] += other.count[channel];
+        }
+    }
+
+    /// Compute levels from accumulated samples.
+    pub fn levels(&self) -> Levels {
+        Levels {
+            peak: self.peak.iter().map(|p| p.min(1.0)).collect(),
+            rms: self
+                .sum_squares
+                .iter()
+                .zip(&self.count)
+                .map(|(sum, count)| match count {
+                    0 => 0.0,
+                    count => ((sum / *count as f64).sqrt() as f32).min(1.0),
+                })
+                .collect(),
+        }
+    }
+
+    /// Reset all channels.
+    pub fn clear(&mut self) {
+        self.peak.clear();
+        self.sum_squares.clear();
+        self.count.clear();
+    }
+}
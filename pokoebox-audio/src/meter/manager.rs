@@ -0,0 +1,147 @@
+use std::thread;
+use std::time::Duration;
+
+use alsa::pcm::{Access, Format, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+use pokoebox_common::pipe::Pipe;
+
+use super::levels::{Accumulator, Levels};
+use super::tap::Tap;
+use super::{Config, Event, Source};
+
+/// Interval to retry opening the capture device at.
+const CAPTURE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Channels to capture with.
+const CAPTURE_CHANNELS: u32 = 2;
+
+/// Sample rate to capture with.
+const CAPTURE_RATE: u32 = 48000;
+
+/// Level meter manager.
+///
+/// Meters the configured source in a background thread, emits levels at the configured rate.
+pub struct Manager {
+    /// Level events.
+    pub events: Pipe<Event>,
+
+    /// Tap to feed audio rendered by PokoeBox.
+    tap: Tap,
+}
+
+impl Manager {
+    /// Construct new manager, starts metering if enabled in config.
+    pub fn new(config: &Config) -> Self {
+        let manager = Self {
+            events: Pipe::default(),
+            tap: Tap::default(),
+        };
+
+        if !config.enabled {
+            return manager;
+        }
+
+        let interval = Duration::from_secs(1) / config.rate.max(1);
+        let rate = config.rate.max(1);
+        let events = manager.events.clone();
+        match config.source.clone() {
+            Source::Pipeline => {
+                let tap = manager.tap.clone();
+                thread::spawn(move || run_pipeline(tap, interval, events));
+            }
+            Source::Alsa(device) => {
+                thread::spawn(move || run_alsa(&device, rate, events));
+            }
+        }
+
+        manager
+    }
+
+    /// Get tap to feed audio rendered by PokoeBox into.
+    ///
+    /// Samples pushed into the tap are ignored unless metering the pipeline.
+    pub fn tap(&self) -> Tap {
+        self.tap.clone()
+    }
+}
+
+/// Meter audio pushed through tap.
+fn run_pipeline(tap: Tap, interval: Duration, events: Pipe<Event>) {
+    let mut silent = true;
+
+    loop {
+        thread::sleep(interval);
+
+        // Emit levels, only emit silence once
+        let accumulator = tap.take();
+        if accumulator.is_empty() && silent {
+            continue;
+        }
+        silent = accumulator.is_empty();
+        emit_levels(&events, accumulator.levels());
+    }
+}
+
+/// Meter audio captured from Alsa device, reopen device on failure.
+fn run_alsa(device: &str, rate: u32, events: Pipe<Event>) {
+    loop {
+        if let Err(err) = capture_alsa(device, rate, &events) {
+            error!(
+                "Failed to capture from '{}' for level meter, retrying: {:?}",
+                device, err
+            );
+        }
+
+        // Report silence while not capturing
+        emit_levels(&events, Levels::default());
+        thread::sleep(CAPTURE_RETRY_INTERVAL);
+    }
+}
+
+/// Capture from Alsa device and emit levels, until an unrecoverable error occurs.
+fn capture_alsa(device: &str, rate: u32, events: &Pipe<Event>) -> alsa::Result<()> {
+    let pcm = PCM::new(device, Direction::Capture, false)?;
+    {
+        let params = HwParams::any(&pcm)?;
+        params.set_channels(CAPTURE_CHANNELS)?;
+        params.set_rate(CAPTURE_RATE, ValueOr::Nearest)?;
+        params.set_format(Format::s16())?;
+        params.set_access(Access::RWInterleaved)?;
+        pcm.hw_params(&params)?;
+    }
+    let (channels, sample_rate) = {
+        let params = pcm.hw_params_current()?;
+        (params.get_channels()? as usize, params.get_rate()?)
+    };
+    info!(
+        "Capturing from '{}' for level meter ({} channels, {} Hz)",
+        device, channels, sample_rate
+    );
+
+    // Read blocks of samples, emit levels for each block
+    let io = pcm.io_i16()?;
+    let mut buf = vec![0i16; (sample_rate / rate).max(1) as usize * channels];
+    let mut accumulator = Accumulator::default();
+    loop {
+        let frames = match io.readi(&mut buf) {
+            Ok(frames) => frames,
+            Err(err) => {
+                pcm.try_recover(err, true)?;
+                continue;
+            }
+        };
+
+        for (i, sample) in buf[..frames * channels].iter().enumerate() {
+            accumulator.add(i % channels, *sample as f32 / i16::MAX as f32);
+        }
+        emit_levels(events, accumulator.levels());
+        accumulator.clear();
+    }
+}
+
+/// Emit levels event, dropped if nobody is listening.
+fn emit_levels(events: &Pipe<Event>, levels: Levels) {
+    if let Err(err) = events.send_or_drop(Event::Levels(levels)) {
+        error!("Failed to send event for output levels: {:?}", err);
+    }
+}
@@ -0,0 +1,57 @@
+pub mod levels;
+pub mod manager;
+pub mod tap;
+
+use serde::Deserialize;
+
+// Re-export
+pub use levels::{Accumulator, Levels};
+pub use manager::Manager;
+pub use tap::Tap;
+
+/// Default number of level events per second.
+const DEFAULT_RATE: u32 = 20;
+
+/// Level meter configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Whether to meter output levels.
+    pub enabled: bool,
+
+    /// Audio to meter.
+    pub source: Source,
+
+    /// Number of level events per second.
+    pub rate: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            source: Source::default(),
+            rate: DEFAULT_RATE,
+        }
+    }
+}
+
+/// Audio source to meter.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Audio rendered by PokoeBox itself, fed through a `Tap`.
+    #[default]
+    Pipeline,
+
+    /// Capture from Alsa device, such as a loopback or dsnoop device.
+    ///
+    /// This meters all playback, including audio from other applications.
+    Alsa(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// Current output levels.
+    Levels(Levels),
+}
@@ -0,0 +1,29 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use super::levels::Accumulator;
+
+/// Tap to feed audio rendered by PokoeBox into the level meter.
+///
+/// Cheap to clone, all clones feed the same meter. Sources should accumulate samples locally and
+/// flush them through `push` once in a while, to keep locking overhead low.
+#[derive(Clone, Debug, Default)]
+pub struct Tap {
+    accumulator: Arc<Mutex<Accumulator>>,
+}
+
+impl Tap {
+    /// Push locally accumulated samples into the meter, clearing the given accumulator.
+    pub fn push(&self, accumulator: &mut Accumulator) {
+        self.accumulator
+            .lock()
+            .expect("failed to lock meter tap")
+            .merge(accumulator);
+        accumulator.clear();
+    }
+
+    /// Take all samples pushed since last take.
+    pub(crate) fn take(&self) -> Accumulator {
+        mem::take(&mut *self.accumulator.lock().expect("failed to lock meter tap"))
+    }
+}
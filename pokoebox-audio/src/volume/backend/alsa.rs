@@ -0,0 +1,525 @@
+use alsa::mixer::{Mixer, Selem, SelemChannelId, SelemId};
+use alsa::poll::{self, PollDescriptors, POLLERR, POLLHUP, POLLNVAL};
+
+use super::super::control::{
+    ControlHandle, ControlKind, ControlProps, SettingKind, SettingProps, VolumeProps,
+};
+use super::super::soft::{SoftVolume, SOFT_CONTROL_NAME, SOFT_VOLUME_DEFAULT, SOFT_VOLUME_RANGE};
+use super::super::uevent::Monitor;
+use super::super::util;
+use super::Error;
+
+const DEFAULT_CHANNEL: SelemChannelId = SelemChannelId::FrontLeft;
+
+/// Alsa mixer backend.
+///
+/// Controls the hardware mixer of the selected sound card. Falls back to a software volume
+/// control if there's no sound card or it has no hardware volume.
+///
+/// Sound cards may be plugged in or removed at runtime, see `poll_changed`.
+pub struct Backend {
+    /// Selected sound card, `None` if there is none.
+    card: Option<String>,
+
+    /// Alsa mixer, `None` if there is no sound card.
+    mixer: Option<Mixer>,
+
+    /// List of Alsa controls.
+    controls: Vec<Control>,
+
+    /// List of Alsa settings, elements without volume.
+    settings: Vec<Setting>,
+
+    /// Software volume, used as fallback.
+    soft_volume: SoftVolume,
+
+    /// Sound device hotplug monitor, sound cards are checked on every poll if `None`.
+    monitor: Option<Monitor>,
+}
+
+impl Backend {
+    /// Construct new Alsa backend.
+    pub fn new(soft_volume: SoftVolume) -> Result<Self, Error> {
+        let mut backend = Self {
+            card: None,
+            mixer: None,
+            controls: Vec::new(),
+            settings: Vec::new(),
+            soft_volume,
+            monitor: Monitor::new()
+                .map_err(|err| {
+                    warn!(
+                        "Failed to monitor sound card hotplug, checking periodically: {}",
+                        err
+                    )
+                })
+                .ok(),
+        };
+        backend.open(util::select_card());
+        Ok(backend)
+    }
+
+    /// Open mixer for the given sound card, replacing the current one.
+    ///
+    /// Falls back to software volume if there's no card, if it has no hardware volume or if
+    /// opening fails. Opening is retried on the next sound card change.
+    fn open(&mut self, card: Option<String>) {
+        let was_soft = self.is_soft();
+        self.mixer = None;
+        self.controls.clear();
+        self.settings.clear();
+
+        // Open mixer, fall back to software volume on failure
+        let mixer = match &card {
+            Some(card) => Mixer::new(card, true)
+                .map_err(|err| {
+                    warn!(
+                        "Failed to open sound card mixer, using software volume: {}",
+                        err
+                    )
+                })
+                .ok(),
+            None => {
+                info!("No sound card available");
+                None
+            }
+        };
+        self.card = mixer.as_ref().and(card);
+
+        // List available controls
+        if let Some(mixer) = &mixer {
+            info!(
+                "Selected sound card: {}",
+                self.card.as_deref().unwrap_or("?")
+            );
+            self.controls = mixer
+                .iter()
+                .filter_map(Selem::new)
+                .filter(|e| e.has_playback_volume() || e.has_capture_volume())
+                .map(Control::from_selem)
+                .collect();
+            self.settings = mixer
+                .iter()
+                .filter_map(Selem::new)
+                .filter_map(Setting::from_selem)
+                .collect();
+        }
+
+        // Fall back to software volume if card has no hardware volume, disable it otherwise
+        if !self.controls.iter().any(|c| c.props.playback.is_some()) {
+            info!("Sound card has no hardware volume control, falling back to software volume");
+            if !was_soft {
+                self.soft_volume.set_volume(SOFT_VOLUME_DEFAULT);
+            }
+            self.controls
+                .push(Control::from_soft(self.soft_volume.clone()));
+        } else {
+            self.soft_volume.set_volume(SOFT_VOLUME_RANGE.1);
+            self.soft_volume.set_muted(false);
+        }
+
+        self.mixer = mixer;
+    }
+
+    /// Check whether the mixer device is gone, such as when a USB sound card is unplugged.
+    fn is_disconnected(&self) -> bool {
+        let mixer = match &self.mixer {
+            Some(mixer) => mixer,
+            None => return false,
+        };
+
+        let mut fds = match mixer.get() {
+            Ok(fds) => fds,
+            Err(_) => return true,
+        };
+        if poll::poll(&mut fds, 0).is_err() {
+            return true;
+        }
+        mixer
+            .revents(&fds)
+            .map(|flags| flags.intersects(POLLERR | POLLHUP | POLLNVAL))
+            .unwrap_or(true)
+    }
+
+    /// Whether the software volume fallback is in use.
+    fn is_soft(&self) -> bool {
+        self.controls
+            .iter()
+            .any(|c| matches!(c.element, Element::Soft(_)))
+    }
+
+    /// Find a setting for the given handle.
+    fn setting(&self, handle: &ControlHandle) -> Result<&Setting, Error> {
+        self.settings
+            .iter()
+            .find(|s| &s.handle == handle)
+            .ok_or_else(|| Error::UnknownControl(handle.clone()))
+    }
+
+    /// Find a control for the given handle.
+    fn control(&self, handle: &ControlHandle) -> Result<&Control, Error> {
+        self.controls
+            .iter()
+            .find(|c| c.handle() == handle)
+            .ok_or_else(|| Error::UnknownControl(handle.clone()))
+    }
+}
+
+impl super::Backend for Backend {
+    fn name(&self) -> &'static str {
+        "Alsa"
+    }
+
+    fn controls(&mut self) -> Result<Vec<(ControlHandle, ControlProps)>, Error> {
+        Ok(self
+            .controls
+            .iter()
+            .map(|c| (c.handle().clone(), c.props().clone()))
+            .collect())
+    }
+
+    fn get_volume(&mut self, control: &ControlHandle) -> Result<i64, Error> {
+        self.control(control)?.get_volume(self.mixer.as_ref())
+    }
+
+    fn set_volume(&mut self, control: &ControlHandle, volume: i64) -> Result<(), Error> {
+        self.control(control)?
+            .set_volume(self.mixer.as_ref(), volume)
+    }
+
+    fn get_capture_volume(&mut self, control: &ControlHandle) -> Result<i64, Error> {
+        self.control(control)?
+            .get_capture_volume(self.mixer.as_ref())
+    }
+
+    fn set_capture_volume(&mut self, control: &ControlHandle, volume: i64) -> Result<(), Error> {
+        self.control(control)?
+            .set_capture_volume(self.mixer.as_ref(), volume)
+    }
+
+    fn get_mute(&mut self, control: &ControlHandle) -> Result<bool, Error> {
+        self.control(control)?.get_mute(self.mixer.as_ref())
+    }
+
+    fn set_mute(&mut self, control: &ControlHandle, mute: bool) -> Result<(), Error> {
+        self.control(control)?.set_mute(self.mixer.as_ref(), mute)
+    }
+
+    fn settings(&mut self) -> Result<Vec<(ControlHandle, SettingProps)>, Error> {
+        Ok(self
+            .settings
+            .iter()
+            .map(|s| (s.handle.clone(), s.props.clone()))
+            .collect())
+    }
+
+    fn get_setting(&mut self, setting: &ControlHandle) -> Result<u32, Error> {
+        self.setting(setting)?.get(self.mixer.as_ref())
+    }
+
+    fn set_setting(&mut self, setting: &ControlHandle, value: u32) -> Result<(), Error> {
+        self.setting(setting)?.set(self.mixer.as_ref(), value)
+    }
+
+    fn poll_changed(&mut self) -> Result<bool, Error> {
+        // Only check sound cards if any was added or removed
+        if let Some(monitor) = &mut self.monitor {
+            if !monitor.changed() {
+                return Ok(false);
+            }
+        }
+
+        // Reopen if selected card changed or current card is gone
+        let card = util::select_card();
+        let disconnected = self.is_disconnected();
+        if card == self.card && !disconnected {
+            return Ok(false);
+        }
+
+        if disconnected {
+            info!("Sound card disconnected, reopening mixer");
+        } else {
+            info!("Sound cards changed, reopening mixer");
+        }
+        self.open(card);
+        Ok(true)
+    }
+}
+
+struct Control {
+    /// Handle.
+    handle: ControlHandle,
+
+    /// Element backing this control.
+    element: Element,
+
+    /// Control properties.
+    props: ControlProps,
+}
+
+/// Element backing a control.
+enum Element {
+    /// Alsa mixer element.
+    Alsa(SelemId),
+
+    /// Software volume.
+    Soft(SoftVolume),
+}
+
+impl Control {
+    /// Create control from Alsa Selem.
+    pub fn from_selem(selem: Selem) -> Self {
+        let id = selem.get_id();
+        let props = ControlProps {
+            name: id.get_name().ok().map(|n| n.into()),
+            playback: if selem.has_playback_volume() {
+                Some(VolumeProps {
+                    init_value: selem.get_playback_volume(DEFAULT_CHANNEL).unwrap_or(0),
+                    range: selem.get_playback_volume_range(),
+                })
+            } else {
+                None
+            },
+            capture: if selem.has_capture_volume() {
+                Some(VolumeProps {
+                    init_value: selem.get_capture_volume(DEFAULT_CHANNEL).unwrap_or(0),
+                    range: selem.get_capture_volume_range(),
+                })
+            } else {
+                None
+            },
+            kind: ControlKind::Hardware,
+        };
+
+        let handle = ControlHandle::new(id.get_index(), id.get_name().map(|n| n.into()).ok());
+
+        Self {
+            handle,
+            element: Element::Alsa(id),
+            props,
+        }
+    }
+
+    /// Create control from software volume.
+    pub fn from_soft(soft: SoftVolume) -> Self {
+        let props = ControlProps {
+            name: Some(SOFT_CONTROL_NAME.into()),
+            playback: Some(VolumeProps {
+                init_value: soft.volume(),
+                range: SOFT_VOLUME_RANGE,
+            }),
+            capture: None,
+            kind: ControlKind::Software,
+        };
+
+        Self {
+            handle: ControlHandle::new(0, Some(SOFT_CONTROL_NAME.into())),
+            element: Element::Soft(soft),
+            props,
+        }
+    }
+
+    /// Get handle to this control.
+    pub fn handle(&self) -> &ControlHandle {
+        &self.handle
+    }
+
+    /// Get control properties.
+    pub fn props(&self) -> &ControlProps {
+        &self.props
+    }
+
+    /// Get reference to Alsa Selem.
+    ///
+    /// Fails if the mixer or element is gone, such as when the sound card was unplugged.
+    pub fn selem<'a>(&self, mixer: Option<&'a Mixer>) -> Result<Selem<'a>, Error> {
+        let id = match &self.element {
+            Element::Alsa(id) => id,
+            Element::Soft(_) => return Err(Error::Unsupported),
+        };
+
+        mixer
+            .and_then(|mixer| mixer.find_selem(id))
+            .ok_or(Error::Disconnected)
+    }
+
+    /// Get current volume.
+    pub fn get_volume(&self, mixer: Option<&Mixer>) -> Result<i64, Error> {
+        match &self.element {
+            Element::Alsa(_) if self.props.playback.is_none() => Err(Error::Unsupported),
+            Element::Alsa(_) => self
+                .selem(mixer)?
+                .get_playback_volume(DEFAULT_CHANNEL)
+                .map_err(Error::Alsa),
+            Element::Soft(soft) => Ok(soft.volume()),
+        }
+    }
+
+    /// Set current volume.
+    pub fn set_volume(&self, mixer: Option<&Mixer>, volume: i64) -> Result<(), Error> {
+        match &self.element {
+            Element::Alsa(_) if self.props.playback.is_none() => Err(Error::Unsupported),
+            Element::Alsa(_) => self
+                .selem(mixer)?
+                .set_playback_volume_all(volume)
+                .map_err(Error::Alsa),
+            Element::Soft(soft) => {
+                soft.set_volume(volume);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get current capture volume.
+    pub fn get_capture_volume(&self, mixer: Option<&Mixer>) -> Result<i64, Error> {
+        if self.props.capture.is_none() {
+            return Err(Error::Unsupported);
+        }
+        self.selem(mixer)?
+            .get_capture_volume(DEFAULT_CHANNEL)
+            .map_err(Error::Alsa)
+    }
+
+    /// Set current capture volume.
+    pub fn set_capture_volume(&self, mixer: Option<&Mixer>, volume: i64) -> Result<(), Error> {
+        if self.props.capture.is_none() {
+            return Err(Error::Unsupported);
+        }
+        // Alsa has no capture equivalent of set_playback_volume_all
+        let selem = self.selem(mixer)?;
+        SelemChannelId::all()
+            .iter()
+            .filter(|c| selem.has_capture_channel(**c))
+            .try_for_each(|c| selem.set_capture_volume(*c, volume))
+            .map_err(Error::Alsa)
+    }
+
+    /// Get whether control is muted.
+    ///
+    /// Alsa controls without playback switch are unsupported.
+    pub fn get_mute(&self, mixer: Option<&Mixer>) -> Result<bool, Error> {
+        match &self.element {
+            Element::Alsa(_) => {
+                let selem = self.selem(mixer)?;
+                if !selem.has_playback_switch() {
+                    return Err(Error::Unsupported);
+                }
+                selem
+                    .get_playback_switch(DEFAULT_CHANNEL)
+                    .map(|on| on == 0)
+                    .map_err(Error::Alsa)
+            }
+            Element::Soft(soft) => Ok(soft.muted()),
+        }
+    }
+
+    /// Set whether control is muted.
+    ///
+    /// Alsa controls without playback switch are unsupported.
+    pub fn set_mute(&self, mixer: Option<&Mixer>, mute: bool) -> Result<(), Error> {
+        match &self.element {
+            Element::Alsa(_) => {
+                let selem = self.selem(mixer)?;
+                if !selem.has_playback_switch() {
+                    return Err(Error::Unsupported);
+                }
+                selem
+                    .set_playback_switch_all(!mute as i32)
+                    .map_err(Error::Alsa)
+            }
+            Element::Soft(soft) => {
+                soft.set_muted(mute);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Alsa setting, an element without volume such as a filter selection or switch.
+struct Setting {
+    /// Handle.
+    handle: ControlHandle,
+
+    /// Alsa element.
+    id: SelemId,
+
+    /// Setting properties.
+    props: SettingProps,
+}
+
+impl Setting {
+    /// Create setting from Alsa Selem.
+    ///
+    /// Returns `None` if this element has volume, switches on such elements are mute controls.
+    pub fn from_selem(selem: Selem) -> Option<Self> {
+        let kind = if selem.is_enumerated() {
+            SettingKind::Enum(selem.iter_enum().ok()?.filter_map(|i| i.ok()).collect())
+        } else if !selem.has_playback_volume()
+            && !selem.has_capture_volume()
+            && (selem.has_playback_switch() || selem.has_capture_switch())
+        {
+            SettingKind::Switch
+        } else {
+            return None;
+        };
+
+        let id = selem.get_id();
+        let mut setting = Self {
+            handle: ControlHandle::new(id.get_index(), id.get_name().map(|n| n.into()).ok()),
+            props: SettingProps {
+                name: id.get_name().ok().map(|n| n.into()),
+                kind,
+                init_value: 0,
+            },
+            id,
+        };
+        setting.props.init_value = setting.get_selem(&selem).unwrap_or(0);
+        Some(setting)
+    }
+
+    /// Get current value.
+    pub fn get(&self, mixer: Option<&Mixer>) -> Result<u32, Error> {
+        self.get_selem(&self.selem(mixer)?)
+    }
+
+    /// Set current value.
+    pub fn set(&self, mixer: Option<&Mixer>, value: u32) -> Result<(), Error> {
+        // Reject out of range enumeration items
+        if let SettingKind::Enum(items) = &self.props.kind {
+            if value as usize >= items.len() {
+                return Err(Error::Unsupported);
+            }
+        }
+
+        let selem = self.selem(mixer)?;
+        match &self.props.kind {
+            SettingKind::Enum(_) => selem.set_enum_item(DEFAULT_CHANNEL, value),
+            SettingKind::Switch if selem.has_playback_switch() => {
+                selem.set_playback_switch_all(value.min(1) as i32)
+            }
+            SettingKind::Switch => selem.set_capture_switch_all(value.min(1) as i32),
+        }
+        .map_err(Error::Alsa)
+    }
+
+    /// Get current value from given selem.
+    fn get_selem(&self, selem: &Selem) -> Result<u32, Error> {
+        match &self.props.kind {
+            SettingKind::Enum(_) => selem.get_enum_item(DEFAULT_CHANNEL),
+            SettingKind::Switch if selem.has_playback_switch() => {
+                selem.get_playback_switch(DEFAULT_CHANNEL).map(|v| v as u32)
+            }
+            SettingKind::Switch => selem.get_capture_switch(DEFAULT_CHANNEL).map(|v| v as u32),
+        }
+        .map_err(Error::Alsa)
+    }
+
+    /// Get reference to Alsa Selem.
+    ///
+    /// Fails if the mixer or element is gone, such as when the sound card was unplugged.
+    fn selem<'a>(&self, mixer: Option<&'a Mixer>) -> Result<Selem<'a>, Error> {
+        mixer
+            .and_then(|mixer| mixer.find_selem(&self.id))
+            .ok_or(Error::Disconnected)
+    }
+}
@@ -0,0 +1,69 @@
+mod alsa;
+mod pulse;
+mod traits;
+
+use serde::Deserialize;
+
+use super::control::ControlHandle;
+use super::soft::SoftVolume;
+
+// Re-export
+pub use self::alsa::Backend as AlsaBackend;
+pub use pulse::Backend as PulseBackend;
+pub use traits::Backend;
+
+/// Available mixer backends.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Use PulseAudio/PipeWire if a sound server is running, Alsa otherwise.
+    #[default]
+    Auto,
+
+    /// Control Alsa hardware mixer directly.
+    Alsa,
+
+    /// Control PulseAudio/PipeWire sound server.
+    Pulse,
+}
+
+/// Generic backend error.
+#[derive(Debug)]
+pub enum Error {
+    /// Backend selection error.
+    Select,
+
+    /// Control handle doesn't correspond to real control.
+    UnknownControl(ControlHandle),
+
+    /// Operation is not supported by control.
+    Unsupported,
+
+    /// Sound device is disconnected.
+    Disconnected,
+
+    /// An Alsa error.
+    Alsa(::alsa::Error),
+
+    /// A sound server error.
+    Pulse(String),
+}
+
+/// Select mixer backend to use at runtime.
+pub fn select_backend(
+    kind: BackendKind,
+    soft_volume: SoftVolume,
+) -> Result<Box<dyn Backend>, Error> {
+    // Use sound server if explicitly selected or available
+    match kind {
+        BackendKind::Pulse => return Ok(Box::new(PulseBackend::new()?)),
+        BackendKind::Auto if PulseBackend::is_available() => {
+            info!("Sound server is running, using PulseAudio mixer backend");
+            return Ok(Box::new(PulseBackend::new()?));
+        }
+        BackendKind::Auto | BackendKind::Alsa => {}
+    }
+
+    info!("Using Alsa mixer backend");
+    Ok(Box::new(AlsaBackend::new(soft_volume)?))
+}
@@ -0,0 +1,631 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use super::super::control::{ControlHandle, ControlKind, ControlProps, VolumeProps};
+use super::Error;
+
+/// Volume range for sound server controls, in percent.
+const VOLUME_RANGE: (i64, i64) = (0, 100);
+
+/// Interval between volume steps while fading, each step invokes `pactl`.
+const FADE_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Handle name prefix for sinks.
+const PREFIX_SINK: &str = "sink:";
+
+/// Handle name prefix for sink inputs (application streams).
+const PREFIX_STREAM: &str = "stream:";
+
+/// Handle name prefix for sources.
+const PREFIX_SOURCE: &str = "source:";
+
+/// All object types used as controls.
+const OBJECT_TYPES: [ObjectType; 3] = [ObjectType::Sink, ObjectType::SinkInput, ObjectType::Source];
+
+/// PulseAudio mixer backend.
+///
+/// Controls sink, source and per application stream volume through the `pactl` command-line.
+/// Works with PulseAudio and PipeWire (through `pipewire-pulse`).
+///
+/// Listed objects are cached, and only listed again after the sound server reports they changed
+/// through `pactl subscribe`.
+pub struct Backend {
+    /// Cached sinks, streams and sources.
+    objects: HashMap<ObjectType, Vec<Object>>,
+
+    /// Object types that changed since they were last listed.
+    stale: HashSet<ObjectType>,
+
+    /// Subscription to sound server changes.
+    subscription: Subscription,
+}
+
+impl Backend {
+    /// Construct new PulseAudio backend.
+    pub fn new() -> Result<Self, Error> {
+        if !Self::is_available() {
+            return Err(Error::Pulse(
+                "no PulseAudio compatible sound server running".into(),
+            ));
+        }
+        Ok(Self {
+            objects: HashMap::new(),
+            stale: OBJECT_TYPES.iter().copied().collect(),
+            subscription: Subscription::spawn()?,
+        })
+    }
+
+    /// Check whether a PulseAudio compatible sound server is running.
+    pub fn is_available() -> bool {
+        pactl(&["info"]).is_ok()
+    }
+
+    /// Handle changes reported by the sound server, marking changed object types as stale.
+    ///
+    /// Returns `true` if objects were added or removed.
+    fn sync(&mut self) -> Result<bool, Error> {
+        let mut changed = false;
+        loop {
+            match self.subscription.events.try_recv() {
+                Ok((kind, event)) => {
+                    self.stale.insert(kind);
+                    changed |= event != EventKind::Change;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // Sound server restarted, subscribe again and list everything
+                    warn!("Lost PulseAudio subscription, subscribing again");
+                    self.subscription = Subscription::spawn()?;
+                    self.stale.extend(OBJECT_TYPES.iter());
+                    changed = true;
+                    break;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Get sound server objects of given type, only lists them again if stale.
+    fn objects(&mut self, kind: ObjectType) -> Result<&[Object], Error> {
+        self.sync()?;
+        if self.stale.contains(&kind) || !self.objects.contains_key(&kind) {
+            self.objects.insert(kind, list(kind)?);
+            self.stale.remove(&kind);
+        }
+        Ok(&self.objects[&kind])
+    }
+
+    /// Set volume of sound server object, in percent.
+    ///
+    /// Doesn't list objects, the sound server reports an error if the object is gone. Marks the
+    /// object type stale, so the new volume is read back before the sound server reports it.
+    fn set_object_volume(
+        &mut self,
+        kind: ObjectType,
+        index: u32,
+        volume: i64,
+    ) -> Result<(), Error> {
+        let volume = volume.clamp(VOLUME_RANGE.0, VOLUME_RANGE.1);
+        pactl(&[
+            kind.set_volume_cmd(),
+            &index.to_string(),
+            &format!("{}%", volume),
+        ])?;
+        self.stale.insert(kind);
+        Ok(())
+    }
+
+    /// Find sound server object for given control handle.
+    fn object(&mut self, control: &ControlHandle) -> Result<(ObjectType, &Object), Error> {
+        let kind = object_type(control)?;
+        self.objects(kind)?
+            .iter()
+            .find(|o| o.index == control.index())
+            .map(|o| (kind, o))
+            .ok_or_else(|| Error::UnknownControl(control.clone()))
+    }
+}
+
+impl super::Backend for Backend {
+    fn name(&self) -> &'static str {
+        "PulseAudio"
+    }
+
+    fn controls(&mut self) -> Result<Vec<(ControlHandle, ControlProps)>, Error> {
+        let sinks = self.objects(ObjectType::Sink)?.iter().map(|o| {
+            let name = o.fields.get("Name").cloned().unwrap_or_default();
+            let props = ControlProps {
+                name: o
+                    .fields
+                    .get("Description")
+                    .cloned()
+                    .or_else(|| Some(name.clone())),
+                playback: Some(o.volume_props()),
+                capture: None,
+                kind: ControlKind::Sink,
+            };
+            (
+                ControlHandle::new(o.index, Some(format!("{}{}", PREFIX_SINK, name))),
+                props,
+            )
+        });
+        let mut controls: Vec<_> = sinks.collect();
+
+        let streams = self.objects(ObjectType::SinkInput)?.iter().map(|o| {
+            let name = o
+                .properties
+                .get("application.name")
+                .or_else(|| o.properties.get("media.name"))
+                .cloned();
+            let props = ControlProps {
+                name: name.clone(),
+                playback: Some(o.volume_props()),
+                capture: None,
+                kind: ControlKind::Stream,
+            };
+            (
+                ControlHandle::new(
+                    o.index,
+                    Some(format!("{}{}", PREFIX_STREAM, name.unwrap_or_default())),
+                ),
+                props,
+            )
+        });
+        controls.extend(streams);
+
+        // Skip monitor sources, these mirror sink output
+        let sources = self
+            .objects(ObjectType::Source)?
+            .iter()
+            .filter(|o| o.fields.get("Monitor of Sink").map(|m| m.as_str()) == Some("n/a"))
+            .map(|o| {
+                let name = o.fields.get("Name").cloned().unwrap_or_default();
+                let props = ControlProps {
+                    name: o
+                        .fields
+                        .get("Description")
+                        .cloned()
+                        .or_else(|| Some(name.clone())),
+                    playback: None,
+                    capture: Some(o.volume_props()),
+                    kind: ControlKind::Source,
+                };
+                (
+                    ControlHandle::new(o.index, Some(format!("{}{}", PREFIX_SOURCE, name))),
+                    props,
+                )
+            });
+
+        controls.extend(sources);
+
+        Ok(controls)
+    }
+
+    fn get_volume(&mut self, control: &ControlHandle) -> Result<i64, Error> {
+        match self.object(control)? {
+            (ObjectType::Source, _) => Err(Error::Unsupported),
+            (_, object) => object
+                .volume()
+                .ok_or_else(|| Error::Pulse("failed to parse volume".into())),
+        }
+    }
+
+    fn set_volume(&mut self, control: &ControlHandle, volume: i64) -> Result<(), Error> {
+        match object_type(control)? {
+            ObjectType::Source => Err(Error::Unsupported),
+            kind => self.set_object_volume(kind, control.index(), volume),
+        }
+    }
+
+    fn get_capture_volume(&mut self, control: &ControlHandle) -> Result<i64, Error> {
+        match self.object(control)? {
+            (ObjectType::Source, object) => object
+                .volume()
+                .ok_or_else(|| Error::Pulse("failed to parse volume".into())),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    fn set_capture_volume(&mut self, control: &ControlHandle, volume: i64) -> Result<(), Error> {
+        match object_type(control)? {
+            ObjectType::Source => {
+                self.set_object_volume(ObjectType::Source, control.index(), volume)
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    fn get_mute(&mut self, control: &ControlHandle) -> Result<bool, Error> {
+        Ok(self
+            .object(control)?
+            .1
+            .fields
+            .get("Mute")
+            .map(|m| m == "yes")
+            .unwrap_or(false))
+    }
+
+    fn set_mute(&mut self, control: &ControlHandle, mute: bool) -> Result<(), Error> {
+        let kind = object_type(control)?;
+        pactl(&[
+            kind.set_mute_cmd(),
+            &control.index().to_string(),
+            if mute { "1" } else { "0" },
+        ])?;
+        self.stale.insert(kind);
+        Ok(())
+    }
+
+    fn fade_step_interval(&self) -> Duration {
+        FADE_STEP_INTERVAL
+    }
+
+    fn poll_changed(&mut self) -> Result<bool, Error> {
+        self.sync()
+    }
+}
+
+/// Sound server object type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum ObjectType {
+    Sink,
+    SinkInput,
+    Source,
+}
+
+impl ObjectType {
+    /// Get object type from `pactl subscribe` facility name, such as `sink-input`.
+    fn from_facility(facility: &str) -> Option<Self> {
+        match facility {
+            "sink" => Some(ObjectType::Sink),
+            "sink-input" => Some(ObjectType::SinkInput),
+            "source" => Some(ObjectType::Source),
+            _ => None,
+        }
+    }
+
+    fn list_arg(self) -> &'static str {
+        match self {
+            ObjectType::Sink => "sinks",
+            ObjectType::SinkInput => "sink-inputs",
+            ObjectType::Source => "sources",
+        }
+    }
+
+    fn set_volume_cmd(self) -> &'static str {
+        match self {
+            ObjectType::Sink => "set-sink-volume",
+            ObjectType::SinkInput => "set-sink-input-volume",
+            ObjectType::Source => "set-source-volume",
+        }
+    }
+
+    fn set_mute_cmd(self) -> &'static str {
+        match self {
+            ObjectType::Sink => "set-sink-mute",
+            ObjectType::SinkInput => "set-sink-input-mute",
+            ObjectType::Source => "set-source-mute",
+        }
+    }
+}
+
+/// Sound server object as listed by `pactl list`.
+struct Object {
+    /// Object index.
+    index: u32,
+
+    /// Top level `Key: value` fields.
+    fields: HashMap<String, String>,
+
+    /// Object properties, `key = "value"`.
+    properties: HashMap<String, String>,
+}
+
+impl Object {
+    /// Get volume in percent of first channel.
+    fn volume(&self) -> Option<i64> {
+        self.fields
+            .get("Volume")?
+            .split('/')
+            .nth(1)?
+            .trim()
+            .trim_end_matches('%')
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Get volume properties.
+    fn volume_props(&self) -> VolumeProps {
+        VolumeProps {
+            init_value: self.volume().unwrap_or(0),
+            range: VOLUME_RANGE,
+        }
+    }
+}
+
+/// Get sound server object type for given control handle.
+fn object_type(control: &ControlHandle) -> Result<ObjectType, Error> {
+    let name = control.name().unwrap_or("");
+    if name.starts_with(PREFIX_SINK) {
+        Ok(ObjectType::Sink)
+    } else if name.starts_with(PREFIX_STREAM) {
+        Ok(ObjectType::SinkInput)
+    } else if name.starts_with(PREFIX_SOURCE) {
+        Ok(ObjectType::Source)
+    } else {
+        Err(Error::UnknownControl(control.clone()))
+    }
+}
+
+/// List sound server objects of given type.
+fn list(kind: ObjectType) -> Result<Vec<Object>, Error> {
+    pactl(&["list", kind.list_arg()]).map(|output| parse_list(&output))
+}
+
+/// Parse `pactl list` output.
+///
+/// Objects start with an unindented header, such as `Sink #0`. Indented `Key: value` lines are
+/// fields, `key = "value"` lines are properties. Indentation may be tabs or spaces.
+fn parse_list(output: &str) -> Vec<Object> {
+    let mut objects = Vec::new();
+    for line in output.lines() {
+        // New object header, such as `Sink #0`
+        if !line.starts_with(char::is_whitespace) {
+            if let Some(index) = line.rsplit('#').next().and_then(|i| i.trim().parse().ok()) {
+                objects.push(Object {
+                    index,
+                    fields: HashMap::new(),
+                    properties: HashMap::new(),
+                });
+            }
+            continue;
+        }
+
+        let object = match objects.last_mut() {
+            Some(object) => object,
+            None => continue,
+        };
+
+        // Properties have ` = ` before any colon, such as `device.string = "hw:0"`
+        let trimmed = line.trim();
+        let colon = trimmed.find(':');
+        match trimmed.find(" = ") {
+            Some(i) if colon.map(|c| i < c).unwrap_or(true) => {
+                object.properties.insert(
+                    trimmed[..i].trim().into(),
+                    trimmed[i + 3..].trim().trim_matches('"').into(),
+                );
+            }
+            _ => {
+                if let Some(i) = colon {
+                    object
+                        .fields
+                        .insert(trimmed[..i].trim().into(), trimmed[i + 1..].trim().into());
+                }
+            }
+        }
+    }
+    objects
+}
+
+/// Invoke `pactl` with given arguments, return stdout.
+fn pactl(args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("pactl")
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|err| Error::Pulse(format!("failed to invoke pactl: {}", err)))?;
+
+    if !output.status.success() {
+        return Err(Error::Pulse(format!(
+            "pactl had non-zero exit code ({}): {}",
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into())
+}
+
+/// Kind of event reported by `pactl subscribe`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EventKind {
+    New,
+    Change,
+    Remove,
+}
+
+/// Running `pactl subscribe` process, reporting changes to sinks, streams and sources.
+struct Subscription {
+    /// The `pactl subscribe` process.
+    child: Child,
+
+    /// Reported changes, disconnects when the process exits.
+    events: Receiver<(ObjectType, EventKind)>,
+}
+
+impl Subscription {
+    /// Spawn `pactl subscribe` process, and a thread to read its changes.
+    fn spawn() -> Result<Self, Error> {
+        let mut child = Command::new("pactl")
+            .arg("subscribe")
+            .env("LC_ALL", "C")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| Error::Pulse(format!("failed to invoke pactl: {}", err)))?;
+        let stdout = child.stdout.take().expect("pactl subscribe has no stdout");
+
+        let (tx, events) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if let Some(event) = parse_event(&line) {
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { child, events })
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Parse `pactl subscribe` line, such as `Event 'change' on sink #0`.
+///
+/// Returns `None` for other object types.
+fn parse_event(line: &str) -> Option<(ObjectType, EventKind)> {
+    let mut parts = line.trim().strip_prefix("Event '")?.splitn(2, "' on ");
+    let event = match parts.next()? {
+        "new" => EventKind::New,
+        "change" => EventKind::Change,
+        "remove" => EventKind::Remove,
+        _ => return None,
+    };
+    let facility = parts.next()?.split_whitespace().next()?;
+    Some((ObjectType::from_facility(facility)?, event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINKS: &str = "Sink #0
+\tState: SUSPENDED
+\tName: alsa_output.platform-soc_sound.stereo-fallback
+\tDescription: Built-in Audio Stereo
+\tDriver: module-alsa-card.c
+\tMute: no
+\tVolume: front-left: 42598 /  65% / -11.23 dB,   front-right: 42598 /  65% / -11.23 dB
+\t        balance 0.00
+\tBase Volume: 65536 / 100% / 0.00 dB
+\tMonitor Source: alsa_output.platform-soc_sound.stereo-fallback.monitor
+\tProperties:
+\t\talsa.card_name = \"snd_rpi_hifiberry_dacplus\"
+\t\tdevice.string = \"hw:0\"
+\t\tdevice.description = \"Built-in Audio Stereo\"
+
+Sink #3
+\tName: bluez_sink.00_11_22_33_44_55.a2dp_sink
+\tDescription: JBL Flip
+\tMute: yes
+\tVolume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+";
+
+    #[test]
+    fn list_sinks() {
+        let sinks = parse_list(SINKS);
+        assert_eq!(sinks.len(), 2);
+
+        assert_eq!(sinks[0].index, 0);
+        assert_eq!(
+            sinks[0].fields.get("Description").map(String::as_str),
+            Some("Built-in Audio Stereo")
+        );
+        assert_eq!(sinks[0].fields.get("Mute").map(String::as_str), Some("no"));
+        assert_eq!(sinks[0].volume(), Some(65));
+        assert_eq!(
+            sinks[0].properties.get("device.string").map(String::as_str),
+            Some("hw:0")
+        );
+        assert!(!sinks[0].fields.contains_key("device.string = \"hw"));
+
+        assert_eq!(sinks[1].index, 3);
+        assert_eq!(sinks[1].fields.get("Mute").map(String::as_str), Some("yes"));
+        assert_eq!(sinks[1].volume(), Some(100));
+    }
+
+    #[test]
+    fn list_sink_inputs_spaces() {
+        // Some pactl builds indent with spaces instead of tabs
+        let output = "Sink Input #12
+    Driver: protocol-native.c
+    Sink: 0
+    Volume: mono: 32768 /  50 % / -18.06 dB
+    Properties:
+        media.name = \"Playback\"
+        application.name = \"Spotify\"
+";
+        let inputs = parse_list(output);
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].index, 12);
+        assert_eq!(inputs[0].volume(), Some(50));
+        assert_eq!(
+            inputs[0]
+                .properties
+                .get("application.name")
+                .map(String::as_str),
+            Some("Spotify")
+        );
+        assert_eq!(inputs[0].fields.get("Sink").map(String::as_str), Some("0"));
+    }
+
+    #[test]
+    fn list_localized() {
+        // Localized output still yields objects and properties, unknown fields are absent
+        let output = "Ziel #1
+\tName: alsa_output.usb-DAC
+\tLautstärke: front-left: 65536 / 100 % / 0,00 dB
+\tEigenschaften:
+\t\tdevice.description = \"USB DAC\"
+";
+        let sinks = parse_list(output);
+        assert_eq!(sinks.len(), 1);
+        assert_eq!(sinks[0].index, 1);
+        assert_eq!(sinks[0].volume(), None);
+        assert_eq!(
+            sinks[0]
+                .properties
+                .get("device.description")
+                .map(String::as_str),
+            Some("USB DAC")
+        );
+    }
+
+    #[test]
+    fn list_garbage() {
+        assert!(parse_list("").is_empty());
+        assert!(parse_list("\tVolume: 100%\nNo objects here\n").is_empty());
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(
+            parse_event("Event 'change' on sink #0"),
+            Some((ObjectType::Sink, EventKind::Change))
+        );
+        assert_eq!(
+            parse_event("Event 'new' on sink-input #12"),
+            Some((ObjectType::SinkInput, EventKind::New))
+        );
+        assert_eq!(
+            parse_event("  Event 'remove' on source #3  "),
+            Some((ObjectType::Source, EventKind::Remove))
+        );
+    }
+
+    #[test]
+    fn events_ignored() {
+        assert_eq!(parse_event("Event 'change' on client #5"), None);
+        assert_eq!(parse_event("Event 'change' on source-output #7"), None);
+        assert_eq!(parse_event("Event 'unknown' on sink #0"), None);
+        assert_eq!(parse_event("Ereignis »change« auf Ziel #0"), None);
+        assert_eq!(parse_event(""), None);
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use super::super::control::{ControlHandle, ControlProps, SettingProps};
+use super::super::fade::FADE_STEP_INTERVAL;
+use super::Error;
+
+/// A generic volume mixer backend.
+pub trait Backend: Send {
+    /// Backend name.
+    fn name(&self) -> &'static str;
+
+    /// List all controls with their properties.
+    fn controls(&mut self) -> Result<Vec<(ControlHandle, ControlProps)>, Error>;
+
+    /// Get current volume of control.
+    fn get_volume(&mut self, control: &ControlHandle) -> Result<i64, Error>;
+
+    /// Set current volume of control.
+    fn set_volume(&mut self, control: &ControlHandle, volume: i64) -> Result<(), Error>;
+
+    /// Get current capture volume of control.
+    fn get_capture_volume(&mut self, _control: &ControlHandle) -> Result<i64, Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Set current capture volume of control.
+    fn set_capture_volume(&mut self, _control: &ControlHandle, _volume: i64) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Get whether control is muted.
+    fn get_mute(&mut self, control: &ControlHandle) -> Result<bool, Error>;
+
+    /// Set whether control is muted.
+    fn set_mute(&mut self, control: &ControlHandle, mute: bool) -> Result<(), Error>;
+
+    /// List all settings with their properties.
+    fn settings(&mut self) -> Result<Vec<(ControlHandle, SettingProps)>, Error> {
+        Ok(vec![])
+    }
+
+    /// Get current value of setting.
+    fn get_setting(&mut self, _setting: &ControlHandle) -> Result<u32, Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Set current value of setting.
+    fn set_setting(&mut self, _setting: &ControlHandle, _value: u32) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Minimum interval between volume steps while fading.
+    ///
+    /// Backends where setting volume is expensive use a longer interval, fades take fewer steps.
+    fn fade_step_interval(&self) -> Duration {
+        FADE_STEP_INTERVAL
+    }
+
+    /// Check whether the sound device or list of controls changed, reloading if needed.
+    ///
+    /// Called periodically. Returns `true` if the list of controls or settings changed.
+    fn poll_changed(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
@@ -1,81 +1,113 @@
-use alsa::mixer::{Mixer, Selem, SelemChannelId, SelemId};
-
-const DEFAULT_CHANNEL: SelemChannelId = SelemChannelId::FrontLeft;
-
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ControlHandle(u32, Option<String>);
 
+impl ControlHandle {
+    /// Construct handle from index and name.
+    pub(crate) fn new(index: u32, name: Option<String>) -> Self {
+        Self(index, name)
+    }
+
+    /// Control index.
+    pub(crate) fn index(&self) -> u32 {
+        self.0
+    }
+
+    /// Control name.
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.1.as_deref()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ControlProps {
     /// Control name.
     pub name: Option<String>,
 
+    /// Playback volume, if control has any.
+    pub playback: Option<VolumeProps>,
+
+    /// Capture volume, if control has any.
+    pub capture: Option<VolumeProps>,
+
+    /// Control kind.
+    pub kind: ControlKind,
+}
+
+impl ControlProps {
+    /// Get volume properties for given direction, if control has volume in that direction.
+    pub fn volume(&self, direction: Direction) -> Option<&VolumeProps> {
+        match direction {
+            Direction::Playback => self.playback.as_ref(),
+            Direction::Capture => self.capture.as_ref(),
+        }
+    }
+}
+
+/// Volume properties for one direction of a control.
+#[derive(Clone, Debug)]
+pub struct VolumeProps {
     /// The value at initialization.
     pub init_value: i64,
 
-    /// Control range.
+    /// Volume range.
     pub range: (i64, i64),
 }
 
-pub(crate) struct Control {
-    /// Handle.
-    handle: ControlHandle,
+/// Audio direction of a control.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    /// Output, such as speakers and headphones.
+    Playback,
 
-    /// Alsa element ID.
-    selem: SelemId,
+    /// Input, such as line-in and microphones.
+    Capture,
+}
 
-    /// Control properties.
-    props: ControlProps,
+impl Direction {
+    /// All directions.
+    pub const ALL: [Direction; 2] = [Direction::Playback, Direction::Capture];
 }
 
-impl Control {
-    /// Create control from Alsa Selem.
-    pub fn from_selem<'a>(selem: Selem<'a>) -> Self {
-        let id = selem.get_id();
-        let props = ControlProps {
-            name: id.get_name().ok().map(|n| n.into()),
-            init_value: selem
-                .get_playback_volume(DEFAULT_CHANNEL)
-                .expect("failed to query Alsa control volume"),
-            range: selem.get_playback_volume_range(),
-        };
-
-        let handle = ControlHandle(id.get_index(), id.get_name().map(|n| n.into()).ok());
-
-        Self {
-            handle,
-            selem: id,
-            props,
-        }
-    }
+/// Properties of a setting, a mixer element without volume such as a filter selection or switch.
+#[derive(Clone, Debug)]
+pub struct SettingProps {
+    /// Setting name.
+    pub name: Option<String>,
 
-    /// Get handle to this control.
-    pub fn handle(&self) -> &ControlHandle {
-        &self.handle
-    }
+    /// Setting kind.
+    pub kind: SettingKind,
 
-    /// Get control properties.
-    pub fn props(&self) -> &ControlProps {
-        &self.props
-    }
+    /// The value at initialization.
+    ///
+    /// The item index for enumerated settings, `0` or `1` for switches.
+    pub init_value: u32,
+}
 
-    /// Get reference to Alsa Selem.
-    pub fn selem<'a>(&self, mixer: &'a Mixer) -> Selem<'a> {
-        // TODO: do not unwrap here
-        mixer
-            .find_selem(&self.selem)
-            .expect("failed to get selem from Alsa mixer")
-    }
+/// Kind of setting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettingKind {
+    /// On/off switch.
+    Switch,
 
-    /// Get current volume.
-    pub fn get_volume(&self, mixer: &Mixer) -> i64 {
-        self.selem(mixer)
-            .get_playback_volume(DEFAULT_CHANNEL)
-            .expect("failed to set Alsa control volume")
-    }
+    /// Enumerated setting, with item names.
+    Enum(Vec<String>),
+}
 
-    /// Set current volume.
-    pub fn set_volume(&self, mixer: &Mixer, volume: i64) -> alsa::Result<()> {
-        self.selem(mixer).set_playback_volume_all(volume)
-    }
+/// Kind of control.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ControlKind {
+    /// Hardware mixer element.
+    Hardware,
+
+    /// Software volume applied by PokoeBox.
+    Software,
+
+    /// Sound server output sink.
+    Sink,
+
+    /// Sound server input source.
+    Source,
+
+    /// Sound server playback stream of a single application.
+    Stream,
 }
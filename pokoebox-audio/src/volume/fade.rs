@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+use super::control::ControlHandle;
+
+/// Interval between volume steps while fading.
+pub(crate) const FADE_STEP_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Volume fade curve.
+///
+/// Defines how the volume moves from the start to the target value over the fade duration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Curve {
+    /// Constant rate of change.
+    Linear,
+
+    /// Start slow, end fast.
+    EaseIn,
+
+    /// Start fast, end slow.
+    EaseOut,
+
+    /// Start and end slow, fast in the middle.
+    SCurve,
+}
+
+impl Curve {
+    /// Map linear progress in `[0, 1]` onto this curve.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::EaseIn => t * t,
+            Curve::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Curve::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// An active volume fade on a control.
+pub(crate) struct Fade {
+    /// Control this fade applies to.
+    pub control: ControlHandle,
+
+    /// Volume at the start of the fade.
+    from: i64,
+
+    /// Target volume.
+    to: i64,
+
+    /// Moment the fade started.
+    start: Instant,
+
+    /// Total fade duration.
+    duration: Duration,
+
+    /// Fade curve.
+    curve: Curve,
+
+    /// Last volume that was applied.
+    pub last: i64,
+}
+
+impl Fade {
+    /// Start a new fade from the given volume.
+    pub fn new(
+        control: ControlHandle,
+        from: i64,
+        to: i64,
+        duration: Duration,
+        curve: Curve,
+    ) -> Self {
+        Self {
+            control,
+            from,
+            to,
+            start: Instant::now(),
+            duration,
+            curve,
+            last: from,
+        }
+    }
+
+    /// Linear fade progress in `[0, 1]`.
+    pub fn progress(&self) -> f32 {
+        if self.duration.as_millis() == 0 {
+            return 1.0;
+        }
+        (self.start.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+
+    /// Volume the control should be at right now.
+    pub fn volume(&self) -> i64 {
+        let t = self.curve.apply(self.progress());
+        self.from + ((self.to - self.from) as f32 * t).round() as i64
+    }
+
+    /// Whether the fade has reached its target.
+    pub fn is_done(&self) -> bool {
+        self.progress() >= 1.0
+    }
+}
@@ -1,38 +1,76 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use pokoebox_common::pipe::Error as PipeError;
 
-use super::control::{ControlHandle, ControlProps};
+use super::backend::Error as BackendError;
+use super::control::{ControlHandle, ControlKind, ControlProps, SettingProps};
+use super::fade::Curve;
 use super::mixer::DeviceMixer;
-use super::{Cmd, Event};
+use super::role::{ControlSelector, Role, Roles};
+use super::{Cmd, Config, Event};
+
+/// Time to wait for a fade to end, in addition to its duration.
+const FADE_END_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time to wait for the mixer to handle a blocking command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Volume manager.
 pub struct Manager {
     /// Device mixer.
     pub mixer: DeviceMixer,
 
-    /// Control properties.
-    pub control_props: HashMap<ControlHandle, ControlProps>,
+    /// Current controls and assigned roles, updated when controls change.
+    state: Arc<RwLock<State>>,
 }
 
 impl Manager {
-    pub fn new() -> Self {
-        let mut mixer = Self {
-            mixer: DeviceMixer::new(),
-            control_props: HashMap::new(),
+    /// Construct new manager, selects mixer backend from config.
+    ///
+    /// Fails if a configured role matches multiple mixer controls, or if there is no control to
+    /// use as master. Roles matching no control are skipped, their control may be plugged in
+    /// later.
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let manager = Self {
+            mixer: DeviceMixer::new(config.backend).map_err(Error::Backend)?,
+            state: Arc::new(RwLock::new(State::default())),
         };
 
-        // List Alsa mixer controls
-        match mixer.query_controls() {
-            Ok(controls) => {
-                mixer.control_props = controls;
-            }
-            Err(err) => {
-                error!("Failed to query volume mixer controls: {:?}", err);
+        // List mixer controls, assign roles
+        let controls = manager.query_controls().map_err(Error::Query)?;
+        let (roles, errors) = resolve_roles(&config.roles, &controls);
+        for err in errors {
+            match err {
+                Error::RoleAmbiguous(..) | Error::NoControls => return Err(err),
+                err => warn!("Failed to assign mixer control role: {}", err),
             }
         }
+        manager
+            .state
+            .write()
+            .expect("failed to lock volume state")
+            .update(controls, roles);
 
-        mixer
+        // Reassign roles when controls change, such as when a sound card is plugged in
+        let state = manager.state.clone();
+        let roles_config = config.roles.clone();
+        manager.mixer.events.register_callback(move |event| {
+            if let Event::Controls(controls) = event {
+                let (roles, errors) = resolve_roles(&roles_config, &controls);
+                for err in errors {
+                    warn!("Failed to assign mixer control role: {}", err);
+                }
+                state
+                    .write()
+                    .expect("failed to lock volume state")
+                    .update(controls, roles);
+            }
+        });
+
+        Ok(manager)
     }
 
     /// Send command to the mixer.
@@ -45,20 +83,196 @@ impl Manager {
         let event_rx = self.mixer.events.listen();
         self.send_cmd(Cmd::GetControls)?;
         loop {
-            match event_rx
+            if let Event::Controls(controls) = event_rx
                 .recv()
                 .expect("couldn't receive device mixer event")
             {
-                Event::Controls(controls) => return Ok(controls),
-                _ => {}
+                return Ok(controls);
+            }
+        }
+    }
+
+    /// Query list of settings, this is blocking.
+    ///
+    /// Returns `None` if the mixer didn't reply in time.
+    pub fn query_settings(
+        &self,
+    ) -> Result<Option<HashMap<ControlHandle, SettingProps>>, PipeError> {
+        let event_rx = self.mixer.events.listen();
+        self.send_cmd(Cmd::GetSettings)?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            match event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Event::Settings(settings)) => return Ok(Some(settings)),
+                Ok(_) => {}
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+
+    /// Query current volume of given control, this is blocking.
+    ///
+    /// Commands are handled in order, so this also waits for earlier commands to be applied.
+    /// Returns `None` if the mixer didn't reply in time.
+    pub fn query_volume(&self, control: &ControlHandle) -> Result<Option<i64>, PipeError> {
+        let event_rx = self.mixer.events.listen();
+        self.send_cmd(Cmd::GetVolume(control.clone()))?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            match event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Event::Volume(c, volume)) if &c == control => return Ok(Some(volume)),
+                Ok(_) => {}
+                Err(_) => return Ok(None),
             }
         }
     }
 
-    /// Find the master control.
-    // TODO: propagate errors here
-    pub fn get_master_control(&self) -> (&ControlHandle, &ControlProps) {
-        self.control_props
+    /// Mute or unmute given control, this is blocking.
+    ///
+    /// Returns `false` if the control couldn't be muted, such as an Alsa control without playback
+    /// switch.
+    pub fn set_mute_blocking(
+        &self,
+        control: &ControlHandle,
+        mute: bool,
+    ) -> Result<bool, PipeError> {
+        // The mixer only reports mute changes on success, query volume to know when it's handled
+        let event_rx = self.mixer.events.listen();
+        self.send_cmd(Cmd::SetMute(control.clone(), mute))?;
+        self.send_cmd(Cmd::GetVolume(control.clone()))?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            match event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Event::Mute(c, _)) if &c == control => return Ok(true),
+                Ok(Event::Volume(c, _)) if &c == control => return Ok(false),
+                Ok(_) => {}
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+
+    /// Fade volume of given control to target, blocking until the fade ends.
+    ///
+    /// Returns `true` if the target was reached, `false` if the fade was cancelled or didn't end
+    /// in time.
+    pub fn fade_volume_blocking(
+        &self,
+        control: &ControlHandle,
+        target: i64,
+        duration: Duration,
+        curve: Curve,
+    ) -> Result<bool, PipeError> {
+        let event_rx = self.mixer.events.listen();
+        self.send_cmd(Cmd::FadeVolume(control.clone(), target, duration, curve))?;
+        let deadline = Instant::now() + duration + FADE_END_TIMEOUT;
+        loop {
+            match event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Event::FadeEnded(c, completed)) if &c == control => return Ok(completed),
+                Ok(_) => {}
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+
+    /// Get the control assigned to the given role, if any.
+    pub fn get_role_control(&self, role: Role) -> Option<(ControlHandle, ControlProps)> {
+        let state = self.state.read().expect("failed to lock volume state");
+        let handle = state.roles.get(&role)?;
+        state
+            .control_props
+            .get_key_value(handle)
+            .map(|(h, p)| (h.clone(), p.clone()))
+    }
+
+    /// Get the role assigned to the given control, if any.
+    ///
+    /// If a control has multiple roles, the first in `Role::ALL` order is returned.
+    pub fn get_control_role(&self, control: &ControlHandle) -> Option<Role> {
+        let state = self.state.read().expect("failed to lock volume state");
+        Role::ALL
+            .iter()
+            .copied()
+            .find(|role| state.roles.get(role) == Some(control))
+    }
+}
+
+/// Current controls and assigned roles.
+#[derive(Default)]
+struct State {
+    /// Control properties.
+    control_props: HashMap<ControlHandle, ControlProps>,
+
+    /// Controls assigned to roles.
+    roles: HashMap<Role, ControlHandle>,
+}
+
+impl State {
+    fn update(
+        &mut self,
+        control_props: HashMap<ControlHandle, ControlProps>,
+        roles: HashMap<Role, ControlHandle>,
+    ) {
+        for (role, control) in &roles {
+            if self.roles.get(role) != Some(control) {
+                info!(
+                    "Using mixer control {:?} as {} control",
+                    control.name().unwrap_or("?"),
+                    role
+                );
+            }
+        }
+        self.control_props = control_props;
+        self.roles = roles;
+    }
+}
+
+/// Resolve role config to mixer controls.
+///
+/// If no master control is configured, one is picked automatically. Roles that fail to resolve
+/// are skipped, and their errors are returned.
+fn resolve_roles(
+    roles: &Roles,
+    controls: &HashMap<ControlHandle, ControlProps>,
+) -> (HashMap<Role, ControlHandle>, Vec<Error>) {
+    // Roles are for output volume, application streams come and go so never assign them
+    let mut candidates: Vec<_> = controls
+        .iter()
+        .filter(|(_, p)| p.playback.is_some() && p.kind != ControlKind::Stream)
+        .collect();
+    candidates.sort_by_key(|(h, _)| (h.name().map(|n| n.to_owned()), h.index()));
+
+    let mut resolved = HashMap::new();
+    let mut errors = Vec::new();
+    for role in Role::ALL.iter().copied() {
+        let selector = match roles.get(role) {
+            Some(selector) => selector,
+            None => continue,
+        };
+
+        let matches: Vec<_> = candidates
+            .iter()
+            .filter(|(h, p)| selector.matches(h, p))
+            .collect();
+        match matches.as_slice() {
+            [(handle, _)] => {
+                resolved.insert(role, (*handle).clone());
+            }
+            [] => errors.push(Error::RoleNotFound(
+                role,
+                selector.clone(),
+                candidates.iter().map(|(h, p)| describe(h, p)).collect(),
+            )),
+            _ => errors.push(Error::RoleAmbiguous(
+                role,
+                selector.clone(),
+                matches.iter().map(|(h, p)| describe(h, p)).collect(),
+            )),
+        }
+    }
+
+    // Guess master control if not configured, prefer digital hardware volume
+    if roles.master.is_none() {
+        let guess = candidates
             .iter()
             .find(|(_, p)| {
                 p.name
@@ -66,11 +280,66 @@ impl Manager {
                     .map(|n| n.contains("Digital"))
                     .unwrap_or(false)
             })
-            .unwrap_or_else(|| {
-                self.control_props
-                    .iter()
-                    .next()
-                    .expect("Could not find master volume control")
-            })
+            .or_else(|| candidates.first());
+        match guess {
+            Some((handle, _)) => {
+                resolved.insert(Role::Master, (*handle).clone());
+            }
+            None => errors.push(Error::NoControls),
+        }
+    }
+
+    (resolved, errors)
+}
+
+/// Describe control for in error messages.
+fn describe(handle: &ControlHandle, props: &ControlProps) -> String {
+    format!(
+        "'{}' index {}",
+        props.name.as_deref().or(handle.name()).unwrap_or("?"),
+        handle.index()
+    )
+}
+
+/// Volume manager error.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to set up mixer backend.
+    Backend(BackendError),
+
+    /// Failed to query mixer controls.
+    Query(PipeError),
+
+    /// Mixer has no controls to use.
+    NoControls,
+
+    /// Configured role matches no control, with list of available controls.
+    RoleNotFound(Role, ControlSelector, Vec<String>),
+
+    /// Configured role matches multiple controls, with list of matching controls.
+    RoleAmbiguous(Role, ControlSelector, Vec<String>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Backend(err) => write!(f, "failed to set up mixer backend: {:?}", err),
+            Error::Query(err) => write!(f, "failed to query mixer controls: {:?}", err),
+            Error::NoControls => write!(f, "mixer has no volume controls"),
+            Error::RoleNotFound(role, selector, available) => write!(
+                f,
+                "no mixer control matches {} for {} role, available controls: {}",
+                selector,
+                role,
+                available.join(", "),
+            ),
+            Error::RoleAmbiguous(role, selector, matches) => write!(
+                f,
+                "multiple mixer controls match {} for {} role, specify name and index: {}",
+                selector,
+                role,
+                matches.join(", "),
+            ),
+        }
     }
 }
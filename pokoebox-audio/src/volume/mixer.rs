@@ -1,12 +1,18 @@
+use std::sync::mpsc::RecvTimeoutError;
 use std::thread;
+use std::time::{Duration, Instant};
 
-use alsa::mixer::{Mixer, Selem};
 use pokoebox_common::pipe::Pipe;
 
-use super::control::{Control, ControlHandle};
-use super::util;
+use super::backend::{self, Backend, BackendKind};
+use super::control::ControlHandle;
+use super::fade::Fade;
+use super::soft::SoftVolume;
 use super::{Cmd, Event};
 
+/// Interval to check for sound device changes, such as a sound card being plugged in.
+const POLL_CHANGED_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Device mixer.
 ///
 /// Provides command/event interface, internally spawns background thread to manage device mixer.
@@ -16,17 +22,23 @@ where
 {
     pub events: Pipe<Event>,
     pub(crate) cmds: Pipe<Cmd>,
+
+    /// Software volume, applies if sound card has no hardware volume.
+    pub soft_volume: SoftVolume,
 }
 
 impl DeviceMixer {
-    /// Construct new device mixer.
-    pub fn new() -> Self {
-        let (pipe_event, pipe_cmd) = InnerDeviceMixer::spawn_thread();
+    /// Construct new device mixer with the given backend.
+    pub fn new(backend: BackendKind) -> Result<Self, backend::Error> {
+        let soft_volume = SoftVolume::default();
+        let backend = backend::select_backend(backend, soft_volume.clone())?;
+        let (pipe_event, pipe_cmd) = InnerDeviceMixer::spawn_thread(backend);
 
-        Self {
+        Ok(Self {
             events: pipe_event,
             cmds: pipe_cmd,
-        }
+            soft_volume,
+        })
     }
 
     // TODO: fn set_master_volume(&self, volume: i64) {}
@@ -39,37 +51,34 @@ struct InnerDeviceMixer {
     /// Commands pipe, to device mixer.
     cmds: Pipe<Cmd>,
 
-    /// Alsa mixer.
-    mixer: Mixer,
+    /// Mixer backend.
+    backend: Box<dyn Backend>,
+
+    /// Active volume fades.
+    fades: Vec<Fade>,
 
-    /// List of Alsa controls.
-    controls: Vec<Control>,
+    /// Last time fades were stepped.
+    last_step: Instant,
+
+    /// Last time sound device changes were checked.
+    last_poll: Instant,
 }
 
 impl InnerDeviceMixer {
-    fn new() -> Self {
-        // TODO: propagate error
-        let mixer = Mixer::new(&util::select_card(), true).expect("failed to open mixer");
-
-        // List available controls
-        let controls = mixer
-            .iter()
-            .filter_map(|e| Selem::new(e))
-            .filter(|e| e.has_playback_volume())
-            .map(Control::from_selem)
-            .collect();
-
+    fn new(backend: Box<dyn Backend>) -> Self {
         Self {
             events: Pipe::default(),
             cmds: Pipe::default(),
-            mixer,
-            controls,
+            backend,
+            fades: Vec::new(),
+            last_step: Instant::now(),
+            last_poll: Instant::now(),
         }
     }
 
-    fn spawn_thread() -> (Pipe<Event>, Pipe<Cmd>) {
+    fn spawn_thread(backend: Box<dyn Backend>) -> (Pipe<Event>, Pipe<Cmd>) {
         // Construct inner device mixer
-        let mut inner = Self::new();
+        let mut inner = Self::new(backend);
         let out_events = inner.events.clone();
         let out_cmds = inner.cmds.clone();
 
@@ -85,59 +94,270 @@ impl InnerDeviceMixer {
         let cmd_rx = self.cmds.listen();
 
         loop {
-            // Get new command
-            let cmd = match cmd_rx.recv() {
-                Err(_) => break,
-                Ok(cmd) => cmd,
+            // Get new command, wake up for next step if fading or to check for device changes
+            let timeout = if self.fades.is_empty() {
+                POLL_CHANGED_INTERVAL
+            } else {
+                self.backend
+                    .fade_step_interval()
+                    .checked_sub(self.last_step.elapsed())
+                    .unwrap_or_default()
+            };
+            let cmd = match cmd_rx.recv_timeout(timeout) {
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => None,
+                Ok(cmd) => Some(cmd),
             };
 
-            // Handle command
-            match cmd {
-                Cmd::GetControls => {
-                    if let Err(err) = self.events.send(Event::Controls(
-                        self.controls
-                            .iter()
-                            .map(|c| (c.handle().clone(), c.props().clone()))
-                            .collect(),
-                    )) {
-                        error!("Failed to send event for control list: {:?}", err);
-                    }
-                }
-                Cmd::ResetVolume => {
-                    todo!("Reset volume");
+            // Handle command, step active fades
+            if let Some(cmd) = cmd {
+                self.handle_cmd(cmd);
+            }
+            self.step_fades();
+
+            if self.last_poll.elapsed() >= POLL_CHANGED_INTERVAL {
+                self.poll_changed();
+            }
+        }
+    }
+
+    fn handle_cmd(&mut self, cmd: Cmd) {
+        match cmd {
+            Cmd::GetControls => self.emit_controls(),
+            Cmd::ResetVolume => {
+                todo!("Reset volume");
+            }
+            Cmd::GetVolume(control) => match self.backend.get_volume(&control) {
+                Ok(volume) => self.emit_volume(control, volume),
+                Err(err) => error!("Failed to get playback volume: {:?}", err),
+            },
+            Cmd::SetVolume(control, volume) => {
+                self.cancel_fade(&control);
+
+                // TODO: use return value on set?
+                match self.backend.set_volume(&control, volume) {
+                    Ok(_) => self.emit_volume(control, volume),
+                    Err(err) => error!("Failed to set playback volume: {:?}", err),
                 }
-                Cmd::GetVolume(control) => {
-                    let volume = self.control(&control).get_volume(&self.mixer);
-                    if let Err(err) = self.events.send(Event::Volume(control, volume)) {
-                        error!("Failed to send event for volume change: {:?}", err);
+            }
+            Cmd::AdjustVolume(control, amount) => {
+                self.cancel_fade(&control);
+
+                // TODO: use return value on set?
+                let volume = match self.backend.get_volume(&control) {
+                    Ok(volume) => volume + amount,
+                    Err(err) => {
+                        error!("Failed to get playback volume: {:?}", err);
+                        return;
                     }
+                };
+                match self.backend.set_volume(&control, volume) {
+                    Ok(_) => self.emit_volume(control, volume),
+                    Err(err) => error!("Failed to set playback volume: {:?}", err),
                 }
-                Cmd::SetVolume(control, volume) => {
-                    // TODO: use return value on set?
-                    if let Err(err) = self.control(&control).set_volume(&self.mixer, volume) {
-                        error!("Failed to set playback volume: {:?}", err);
-                    } else if let Err(err) = self.events.send(Event::Volume(control, volume)) {
-                        error!("Failed to send event for volume change: {:?}", err);
+            }
+            Cmd::FadeVolume(control, target, duration, curve) => {
+                self.cancel_fade(&control);
+
+                // Clamp target to control range, start fading from current volume
+                let range = match self.backend.controls() {
+                    Ok(controls) => controls
+                        .into_iter()
+                        .find(|(c, _)| c == &control)
+                        .and_then(|(_, props)| props.playback.map(|v| v.range)),
+                    Err(err) => {
+                        error!("Failed to list mixer controls: {:?}", err);
+                        return;
                     }
-                }
-                Cmd::AdjustVolume(control, amount) => {
-                    // TODO: use return value on set?
-                    let volume = self.control(&control).get_volume(&self.mixer) + amount;
-                    if let Err(err) = self.control(&control).set_volume(&self.mixer, volume) {
-                        error!("Failed to set playback volume: {:?}", err);
-                    } else if let Err(err) = self.events.send(Event::Volume(control, volume)) {
-                        error!("Failed to send event for volume change: {:?}", err);
+                };
+                let target = match range {
+                    Some((min, max)) => target.clamp(min, max),
+                    None => {
+                        error!("Failed to fade volume, unknown control: {:?}", control);
+                        return;
                     }
+                };
+                match self.backend.get_volume(&control) {
+                    Ok(from) => self
+                        .fades
+                        .push(Fade::new(control, from, target, duration, curve)),
+                    Err(err) => error!("Failed to get playback volume: {:?}", err),
+                }
+            }
+            Cmd::CancelFade(control) => {
+                self.cancel_fade(&control);
+            }
+            Cmd::GetMute(control) => match self.backend.get_mute(&control) {
+                Ok(mute) => self.emit_mute(control, mute),
+                Err(err) => error!("Failed to get mute state: {:?}", err),
+            },
+            Cmd::SetMute(control, mute) => match self.backend.set_mute(&control, mute) {
+                Ok(_) => self.emit_mute(control, mute),
+                Err(err) => error!("Failed to set mute state: {:?}", err),
+            },
+            Cmd::GetSettings => self.emit_settings(),
+            Cmd::GetSetting(setting) => match self.backend.get_setting(&setting) {
+                Ok(value) => self.emit_setting(setting, value),
+                Err(err) => error!("Failed to get mixer setting: {:?}", err),
+            },
+            Cmd::SetSetting(setting, value) => match self.backend.set_setting(&setting, value) {
+                Ok(_) => self.emit_setting(setting, value),
+                Err(err) => error!("Failed to set mixer setting: {:?}", err),
+            },
+            Cmd::GetCaptureVolume(control) => match self.backend.get_capture_volume(&control) {
+                Ok(volume) => self.emit_capture_volume(control, volume),
+                Err(err) => error!("Failed to get capture volume: {:?}", err),
+            },
+            Cmd::SetCaptureVolume(control, volume) => {
+                match self.backend.set_capture_volume(&control, volume) {
+                    Ok(_) => self.emit_capture_volume(control, volume),
+                    Err(err) => error!("Failed to set capture volume: {:?}", err),
+                }
+            }
+        }
+    }
+
+    /// Check for sound device changes, emit new list of controls and settings if changed.
+    ///
+    /// Active fades are cancelled as their controls may be gone.
+    fn poll_changed(&mut self) {
+        self.last_poll = Instant::now();
+
+        match self.backend.poll_changed() {
+            Ok(false) => {}
+            Ok(true) => {
+                info!("Mixer controls changed");
+                for control in self
+                    .fades
+                    .iter()
+                    .map(|f| f.control.clone())
+                    .collect::<Vec<_>>()
+                {
+                    self.cancel_fade(&control);
                 }
+                self.emit_controls();
+                self.emit_settings();
             }
+            Err(err) => error!("Failed to check for sound device changes: {:?}", err),
         }
     }
 
-    /// Find a control for the given handle.
-    fn control(&self, handle: &ControlHandle) -> &Control {
-        self.controls
-            .iter()
-            .find(|c| c.handle() == handle)
-            .expect("invalid control handle, doesn't correspond to real control")
+    /// Emit list of controls.
+    fn emit_controls(&mut self) {
+        match self.backend.controls() {
+            Ok(controls) => {
+                if let Err(err) = self
+                    .events
+                    .send(Event::Controls(controls.into_iter().collect()))
+                {
+                    error!("Failed to send event for control list: {:?}", err);
+                }
+            }
+            Err(err) => error!("Failed to list mixer controls: {:?}", err),
+        }
+    }
+
+    /// Emit list of settings.
+    fn emit_settings(&mut self) {
+        match self.backend.settings() {
+            Ok(settings) => {
+                if let Err(err) = self
+                    .events
+                    .send(Event::Settings(settings.into_iter().collect()))
+                {
+                    error!("Failed to send event for setting list: {:?}", err);
+                }
+            }
+            Err(err) => error!("Failed to list mixer settings: {:?}", err),
+        }
+    }
+
+    /// Emit setting value event.
+    fn emit_setting(&self, setting: ControlHandle, value: u32) {
+        if let Err(err) = self.events.send(Event::Setting(setting, value)) {
+            error!("Failed to send event for setting change: {:?}", err);
+        }
+    }
+
+    /// Emit volume event for control.
+    fn emit_volume(&self, control: ControlHandle, volume: i64) {
+        if let Err(err) = self.events.send(Event::Volume(control, volume)) {
+            error!("Failed to send event for volume change: {:?}", err);
+        }
+    }
+
+    /// Emit capture volume event for control.
+    fn emit_capture_volume(&self, control: ControlHandle, volume: i64) {
+        if let Err(err) = self.events.send(Event::CaptureVolume(control, volume)) {
+            error!("Failed to send event for capture volume change: {:?}", err);
+        }
+    }
+
+    /// Emit mute event for control.
+    fn emit_mute(&self, control: ControlHandle, mute: bool) {
+        if let Err(err) = self.events.send(Event::Mute(control, mute)) {
+            error!("Failed to send event for mute change: {:?}", err);
+        }
+    }
+
+    /// Apply the next step for all active fades.
+    ///
+    /// Steps at most once per backend fade step interval, fades that are done are always applied.
+    fn step_fades(&mut self) {
+        let due = self.last_step.elapsed() >= self.backend.fade_step_interval();
+        if due {
+            self.last_step = Instant::now();
+        }
+        let mut ended = vec![];
+
+        for fade in self.fades.iter_mut().filter(|fade| due || fade.is_done()) {
+            // Set new volume if changed
+            let volume = fade.volume();
+            if volume != fade.last {
+                if let Err(err) = self.backend.set_volume(&fade.control, volume) {
+                    error!("Failed to set playback volume while fading: {:?}", err);
+                    ended.push((fade.control.clone(), false));
+                    continue;
+                }
+                fade.last = volume;
+
+                if let Err(err) = self
+                    .events
+                    .send(Event::Volume(fade.control.clone(), volume))
+                {
+                    error!("Failed to send event for volume change: {:?}", err);
+                }
+                if let Err(err) = self.events.send(Event::FadeProgress(
+                    fade.control.clone(),
+                    volume,
+                    fade.progress(),
+                )) {
+                    error!("Failed to send event for fade progress: {:?}", err);
+                }
+            }
+
+            if fade.is_done() {
+                ended.push((fade.control.clone(), true));
+            }
+        }
+
+        // Drop ended fades
+        for (control, completed) in ended {
+            self.fades.retain(|f| f.control != control);
+            if let Err(err) = self.events.send(Event::FadeEnded(control, completed)) {
+                error!("Failed to send event for fade end: {:?}", err);
+            }
+        }
+    }
+
+    /// Cancel the active fade on the given control, if any.
+    fn cancel_fade(&mut self, control: &ControlHandle) {
+        let count = self.fades.len();
+        self.fades.retain(|f| &f.control != control);
+        if self.fades.len() < count {
+            if let Err(err) = self.events.send(Event::FadeEnded(control.clone(), false)) {
+                error!("Failed to send event for fade end: {:?}", err);
+            }
+        }
     }
 }
@@ -1,13 +1,38 @@
+pub mod backend;
 pub mod control;
+pub mod fade;
 pub mod manager;
 mod mixer;
+pub mod role;
+pub mod soft;
+mod uevent;
 mod util;
 
 use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
 
 // Re-export
-pub use control::{ControlHandle, ControlProps};
+pub use backend::BackendKind;
+pub use control::{
+    ControlHandle, ControlKind, ControlProps, Direction, SettingKind, SettingProps, VolumeProps,
+};
+pub use fade::Curve;
 pub use manager::Manager;
+pub use role::{ControlSelector, Role, Roles};
+pub use soft::SoftVolume;
+
+/// Volume manager configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Mixer backend to use.
+    pub backend: BackendKind,
+
+    /// Mixer controls to use for each role.
+    pub roles: Roles,
+}
 
 #[derive(Clone, Debug)]
 pub enum Cmd {
@@ -25,6 +50,35 @@ pub enum Cmd {
 
     /// Adjust volume of given control.
     AdjustVolume(ControlHandle, i64),
+
+    /// Fade volume of given control to target over the given duration.
+    ///
+    /// Any newer command changing the volume of the same control cancels the fade.
+    FadeVolume(ControlHandle, i64, Duration, Curve),
+
+    /// Cancel active volume fade on given control, keeping its current volume.
+    CancelFade(ControlHandle),
+
+    /// Get whether given control is muted.
+    GetMute(ControlHandle),
+
+    /// Set whether given control is muted.
+    SetMute(ControlHandle, bool),
+
+    /// Get capture volume of given control.
+    GetCaptureVolume(ControlHandle),
+
+    /// Set capture volume of given control.
+    SetCaptureVolume(ControlHandle, i64),
+
+    /// Request list of settings.
+    GetSettings,
+
+    /// Get value of given setting.
+    GetSetting(ControlHandle),
+
+    /// Set value of given setting, see `SettingProps::init_value`.
+    SetSetting(ControlHandle, u32),
 }
 
 #[derive(Clone, Debug)]
@@ -34,4 +88,22 @@ pub enum Event {
 
     /// Current volume for control.
     Volume(ControlHandle, i64),
+
+    /// Current capture volume for control.
+    CaptureVolume(ControlHandle, i64),
+
+    /// List of all setting handles and properties.
+    Settings(HashMap<ControlHandle, SettingProps>),
+
+    /// Current value for setting.
+    Setting(ControlHandle, u32),
+
+    /// Current mute state for control.
+    Mute(ControlHandle, bool),
+
+    /// Volume fade progress for control, with current volume and progress in `[0, 1]`.
+    FadeProgress(ControlHandle, i64, f32),
+
+    /// Volume fade for control ended, `true` if target was reached, `false` if cancelled.
+    FadeEnded(ControlHandle, bool),
 }
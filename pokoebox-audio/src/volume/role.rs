@@ -0,0 +1,120 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use super::control::{ControlHandle, ControlProps};
+
+/// Role a mixer control fulfills.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Role {
+    /// Main output volume, controlled by the rotary encoder.
+    Master,
+
+    /// Headphone output volume.
+    Headphone,
+
+    /// Bass tone control.
+    Bass,
+
+    /// Treble tone control.
+    Treble,
+
+    /// Sound effects volume.
+    Effects,
+}
+
+impl Role {
+    /// All roles, in display order.
+    pub const ALL: [Role; 5] = [
+        Role::Master,
+        Role::Headphone,
+        Role::Bass,
+        Role::Treble,
+        Role::Effects,
+    ];
+
+    /// Get human readable role name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Master => "Master",
+            Role::Headphone => "Headphone",
+            Role::Bass => "Bass",
+            Role::Treble => "Treble",
+            Role::Effects => "Effects",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name().to_lowercase())
+    }
+}
+
+/// Selects a mixer control by index, name, or both.
+///
+/// In config, this is either a number, a string, or `{ name = "...", index = ... }`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ControlSelector {
+    /// Select by control index.
+    Index(u32),
+
+    /// Select by control name.
+    Name(String),
+
+    /// Select by control name and index, for controls sharing the same name.
+    Id { name: String, index: u32 },
+}
+
+impl ControlSelector {
+    /// Check whether this selects the given control.
+    ///
+    /// Names match both the handle name and the display name.
+    pub fn matches(&self, handle: &ControlHandle, props: &ControlProps) -> bool {
+        let name_matches =
+            |name: &str| handle.name() == Some(name) || props.name.as_deref() == Some(name);
+
+        match self {
+            ControlSelector::Index(index) => handle.index() == *index,
+            ControlSelector::Name(name) => name_matches(name),
+            ControlSelector::Id { name, index } => handle.index() == *index && name_matches(name),
+        }
+    }
+}
+
+impl fmt::Display for ControlSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlSelector::Index(index) => write!(f, "index {}", index),
+            ControlSelector::Name(name) => write!(f, "'{}'", name),
+            ControlSelector::Id { name, index } => write!(f, "'{}' index {}", name, index),
+        }
+    }
+}
+
+/// Mapping of roles to mixer controls.
+///
+/// Roles that aren't configured are unused, except for master which falls back to a guess.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Roles {
+    pub master: Option<ControlSelector>,
+    pub headphone: Option<ControlSelector>,
+    pub bass: Option<ControlSelector>,
+    pub treble: Option<ControlSelector>,
+    pub effects: Option<ControlSelector>,
+}
+
+impl Roles {
+    /// Get configured control selector for role.
+    pub fn get(&self, role: Role) -> Option<&ControlSelector> {
+        match role {
+            Role::Master => self.master.as_ref(),
+            Role::Headphone => self.headphone.as_ref(),
+            Role::Bass => self.bass.as_ref(),
+            Role::Treble => self.treble.as_ref(),
+            Role::Effects => self.effects.as_ref(),
+        }
+    }
+}
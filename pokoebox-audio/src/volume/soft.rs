@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// Name of the software volume control.
+pub const SOFT_CONTROL_NAME: &str = "PokoeBox Master";
+
+/// Software volume range.
+pub const SOFT_VOLUME_RANGE: (i64, i64) = (0, 100);
+
+/// Initial software volume when used as fallback.
+pub(crate) const SOFT_VOLUME_DEFAULT: i64 = 70;
+
+/// Software volume.
+///
+/// Used as fallback control if the sound card has no hardware volume. The volume is not applied
+/// to the device, audio rendered by PokoeBox itself must be scaled by `gain()` instead.
+///
+/// Cheap to clone, all clones share the same volume.
+#[derive(Clone, Debug)]
+pub struct SoftVolume {
+    volume: Arc<AtomicI64>,
+    muted: Arc<AtomicBool>,
+}
+
+impl SoftVolume {
+    /// Get current volume, within `SOFT_VOLUME_RANGE`.
+    pub fn volume(&self) -> i64 {
+        self.volume.load(Ordering::Relaxed)
+    }
+
+    /// Set current volume, clamped to `SOFT_VOLUME_RANGE`.
+    pub fn set_volume(&self, volume: i64) {
+        self.volume.store(
+            volume.clamp(SOFT_VOLUME_RANGE.0, SOFT_VOLUME_RANGE.1),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Get whether volume is muted.
+    pub fn muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// Set whether volume is muted.
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Get linear gain factor to scale samples with for the current volume.
+    ///
+    /// Uses a cubic curve to roughly match perceived loudness.
+    pub fn gain(&self) -> f32 {
+        if self.muted() {
+            return 0.0;
+        }
+
+        let (min, max) = SOFT_VOLUME_RANGE;
+        let level = (self.volume() - min) as f32 / (max - min) as f32;
+        level * level * level
+    }
+}
+
+impl Default for SoftVolume {
+    /// Construct soft volume at full level, so it has no effect when it isn't in use.
+    fn default() -> Self {
+        Self {
+            volume: Arc::new(AtomicI64::new(SOFT_VOLUME_RANGE.1)),
+            muted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Sound card hotplug notifications, through kernel and udev uevents.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::FromRawFd;
+
+/// Multicast group for uevents sent by the kernel.
+const GROUP_KERNEL: u32 = 1;
+
+/// Multicast group for uevents sent by udev, after it has set up device nodes.
+const GROUP_UDEV: u32 = 2;
+
+/// Maximum size of a uevent message.
+const MESSAGE_MAX: usize = 8192;
+
+/// Sound subsystem property, as it appears in uevent messages.
+const SOUND_SUBSYSTEM: &[u8] = b"SUBSYSTEM=sound";
+
+/// Listens for sound devices being added or removed.
+pub(crate) struct Monitor {
+    /// Non-blocking uevent netlink socket.
+    socket: File,
+}
+
+impl Monitor {
+    /// Start listening for sound device uevents.
+    pub(crate) fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Closes socket when dropped, also on errors below
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = GROUP_KERNEL | GROUP_UDEV;
+        let result = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { socket })
+    }
+
+    /// Whether any sound device uevent was received since the last call, doesn't block.
+    pub(crate) fn changed(&mut self) -> bool {
+        let mut buf = [0u8; MESSAGE_MAX];
+        let mut changed = false;
+        loop {
+            match self.socket.read(&mut buf) {
+                Ok(len) => changed |= is_sound_event(&buf[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    // Messages may be lost if the socket buffer overflowed, assume a change
+                    error!("Failed to read sound device uevent: {}", err);
+                    return true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Whether uevent message is for a sound device.
+///
+/// Kernel and udev messages both hold `KEY=value` properties separated by NUL bytes.
+fn is_sound_event(message: &[u8]) -> bool {
+    message
+        .split(|b| *b == 0)
+        .any(|field| field == SOUND_SUBSYSTEM)
+}
@@ -2,21 +2,85 @@
 const ALSA_CARD_PREFER: &str = "Intel";
 
 /// Select sound card to use.
-pub(crate) fn select_card() -> String {
-    format!(
-        "hw:{}",
-        alsa::card::Iter::new()
-            .filter_map(|c| c.ok())
-            .filter_map(|c| {
-                c.get_name()
-                    .ok()
-                    .map(|n| Some((c.get_index(), n)))
-                    .unwrap_or(None)
-            })
-            .filter(|(_, n)| n.contains(ALSA_CARD_PREFER))
-            .inspect(|(i, n)| info!("Selected sound card: {} (hw:{})", n, i))
-            .map(|(i, _)| i)
-            .next()
-            .unwrap_or(0)
-    )
+///
+/// See `pick_card` for the preference order. Returns `None` if there are no sound cards.
+pub(crate) fn select_card() -> Option<String> {
+    let cards: Vec<_> = alsa::card::Iter::new()
+        .filter_map(|c| c.ok())
+        .filter_map(|c| {
+            let name = c.get_name().ok()?;
+            let long_name = c.get_longname().unwrap_or_default();
+            Some((c.get_index(), name, long_name))
+        })
+        .collect();
+
+    pick_card(&cards).map(|i| format!("hw:{}", i))
+}
+
+/// Pick sound card from list of card index, name and long name.
+///
+/// Prefers USB sound cards such as DACs, the most recently added if there are multiple. Then
+/// `ALSA_CARD_PREFER`, then the first card. USB cards are plugged in later than onboard audio, so
+/// they're picked up when plugged in at runtime.
+fn pick_card(cards: &[(i32, String, String)]) -> Option<i32> {
+    cards
+        .iter()
+        .filter(|(_, _, l)| l.contains("USB"))
+        .max_by_key(|(i, _, _)| *i)
+        .or_else(|| cards.iter().find(|(_, n, _)| n.contains(ALSA_CARD_PREFER)))
+        .or_else(|| cards.first())
+        .map(|(i, _, _)| *i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(index: i32, name: &str, long_name: &str) -> (i32, String, String) {
+        (index, name.into(), long_name.into())
+    }
+
+    #[test]
+    fn no_cards() {
+        assert_eq!(pick_card(&[]), None);
+    }
+
+    #[test]
+    fn first_card() {
+        let cards = [
+            card(0, "bcm2835 Headphones", "bcm2835 Headphones"),
+            card(1, "vc4-hdmi", "vc4-hdmi"),
+        ];
+        assert_eq!(pick_card(&cards), Some(0));
+    }
+
+    #[test]
+    fn prefer_name() {
+        let cards = [
+            card(0, "HDMI", "HDA NVidia"),
+            card(1, "HDA Intel PCH", "HDA Intel PCH at 0xf7f10000 irq 32"),
+        ];
+        assert_eq!(pick_card(&cards), Some(1));
+    }
+
+    #[test]
+    fn prefer_usb() {
+        // USB DAC plugged in after onboard audio
+        let cards = [
+            card(0, "bcm2835 Headphones", "bcm2835 Headphones"),
+            card(1, "HDA Intel PCH", "HDA Intel PCH at 0xf7f10000 irq 32"),
+            card(2, "DAC", "Generic USB Audio at usb-1.3"),
+        ];
+        assert_eq!(pick_card(&cards), Some(2));
+    }
+
+    #[test]
+    fn prefer_newest_usb() {
+        let cards = [
+            card(0, "bcm2835 Headphones", "bcm2835 Headphones"),
+            card(1, "DAC", "Generic USB Audio at usb-1.3"),
+            card(2, "Headset", "Logitech USB Headset at usb-1.2"),
+        ];
+        assert_eq!(pick_card(&cards), Some(2));
+    }
 }
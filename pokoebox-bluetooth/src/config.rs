@@ -0,0 +1,144 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use bluez::interface::controller::{Controller, ControllerInfo};
+use serde::Deserialize;
+
+use crate::identity::Identity;
+use crate::util;
+
+/// Bluetooth manager configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Controller to use, the first one that can be powered is used if not set.
+    pub controller: Option<ControllerSelector>,
+
+    /// Name and class of device to present as, may be changed at runtime.
+    pub identity: Identity,
+
+    /// File to remember known devices in, devices are not remembered if not set.
+    pub store: Option<PathBuf>,
+
+    /// Reconnecting to recently connected devices.
+    pub reconnect: ReconnectConfig,
+
+    /// Policy for devices that connect.
+    pub policy: PolicyConfig,
+
+    /// Secure mode, pairing must be confirmed by the user.
+    pub secure: bool,
+
+    /// Seconds after which unanswered pairing requests are rejected in secure mode.
+    pub confirm_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            controller: None,
+            identity: Identity::default(),
+            store: None,
+            reconnect: ReconnectConfig::default(),
+            policy: PolicyConfig::default(),
+            secure: false,
+            confirm_timeout: 30,
+        }
+    }
+}
+
+/// Selects a bluetooth controller by index or address.
+///
+/// In config, this is either a number such as `0` for `hci0`, or an address string.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ControllerSelector {
+    /// Select by controller index.
+    Index(u16),
+
+    /// Select by controller address, such as `00:11:22:33:44:55`.
+    Address(String),
+}
+
+impl ControllerSelector {
+    /// Check whether this selects the given controller.
+    pub fn matches(&self, controller: Controller, info: &ControllerInfo) -> bool {
+        match self {
+            ControllerSelector::Index(index) => {
+                let controller: u16 = controller.into();
+                controller == *index
+            }
+            ControllerSelector::Address(address) => {
+                util::address_hex(info.address).eq_ignore_ascii_case(address)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ControllerSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControllerSelector::Index(index) => write!(f, "hci{}", index),
+            ControllerSelector::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+/// Configuration for reconnecting to recently connected devices.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    /// Whether to reconnect on startup and when bluetooth is turned on.
+    pub enabled: bool,
+
+    /// Number of most recently connected devices to try.
+    pub devices: usize,
+
+    /// Number of times to try all devices.
+    pub attempts: u32,
+
+    /// Seconds to wait after the first round of attempts, doubles after each round.
+    pub backoff: u64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            devices: 3,
+            attempts: 4,
+            backoff: 5,
+        }
+    }
+}
+
+/// Connection policy configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Maximum number of connected audio sources, unlimited if not set.
+    ///
+    /// Other devices, such as remotes and keyboards, don't count.
+    pub max_devices: Option<usize>,
+
+    /// What to do when a device connects while the maximum is reached.
+    pub takeover: Takeover,
+
+    /// Addresses of the only devices allowed to connect, any device if not set.
+    pub allow: Option<Vec<String>>,
+
+    /// Addresses of devices not allowed to connect.
+    pub deny: Vec<String>,
+}
+
+/// What to do when a device connects while the maximum number of devices is connected.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Takeover {
+    /// Disconnect the device that connected first.
+    #[default]
+    KickOldest,
+
+    /// Disconnect the new device.
+    RejectNew,
+}
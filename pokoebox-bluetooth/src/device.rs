@@ -3,6 +3,8 @@ use std::slice::Iter;
 use bluez::client::AddressType;
 use bluez::Address;
 
+use crate::eir::{DeviceId, EirData, Uuid};
+use crate::store::KnownDevice;
 use crate::util;
 
 /// Represents a bluetooth device.
@@ -12,6 +14,35 @@ pub struct Device {
     pub address_type: AddressType,
     pub name: Option<String>,
     pub connected: bool,
+
+    /// Custom name set by the user.
+    pub alias: Option<String>,
+
+    /// Whether the device is paired.
+    pub paired: bool,
+
+    /// Whether the device is trusted.
+    pub trusted: bool,
+
+    /// Whether the device is blocked.
+    pub blocked: bool,
+
+    /// Advertised service UUIDs.
+    pub services: Vec<Uuid>,
+
+    /// Class of device, 24 bits.
+    pub class: Option<u32>,
+
+    /// Transmit power level in dBm.
+    pub tx_power: Option<i8>,
+
+    /// Appearance, external look of LE devices.
+    pub appearance: Option<u16>,
+
+    pub device_id: Option<DeviceId>,
+
+    /// Manufacturer specific data, by company identifier.
+    pub manufacturer_data: Vec<(u16, Vec<u8>)>,
 }
 
 impl Device {
@@ -22,15 +53,126 @@ impl Device {
             address_type,
             name: None,
             connected: true,
+            alias: None,
+            paired: false,
+            trusted: false,
+            blocked: false,
+            services: Vec::new(),
+            class: None,
+            tx_power: None,
+            appearance: None,
+            device_id: None,
+            manufacturer_data: Vec::new(),
+        }
+    }
+
+    /// Update device with parsed EIR data, keeps known values missing in the data.
+    pub(crate) fn process_eir(&mut self, eir: &EirData) {
+        if eir.name.is_some() {
+            self.name = eir.name.clone();
+        }
+        for service in &eir.services {
+            if !self.services.contains(service) {
+                self.services.push(*service);
+            }
+        }
+        self.class = eir.class.or(self.class);
+        self.tx_power = eir.tx_power.or(self.tx_power);
+        self.appearance = eir.appearance.or(self.appearance);
+        self.device_id = eir.device_id.or(self.device_id);
+        if !eir.manufacturer_data.is_empty() {
+            self.manufacturer_data = eir.manufacturer_data.clone();
+        }
+    }
+
+    /// Get device kind, from its class of device or appearance.
+    pub fn kind(&self) -> DeviceKind {
+        self.class
+            .map(DeviceKind::from_class)
+            .filter(|kind| *kind != DeviceKind::Unknown)
+            .or_else(|| self.appearance.map(DeviceKind::from_appearance))
+            .unwrap_or(DeviceKind::Unknown)
+    }
+
+    /// Whether the device can stream audio to us over A2DP.
+    ///
+    /// `None` if unknown, because the device did not advertise its services.
+    pub fn supports_a2dp(&self) -> Option<bool> {
+        if self.services.is_empty() {
+            return None;
+        }
+        Some(
+            self.services.contains(&Uuid::AUDIO_SOURCE)
+                || self.services.contains(&Uuid::ADVANCED_AUDIO),
+        )
+    }
+
+    /// Whether the device may stream audio to us, counted by the connection policy.
+    ///
+    /// Uses advertised services if known, the class of device otherwise. LE-only devices can't
+    /// stream A2DP audio, devices we know nothing about are assumed to.
+    pub fn audio_source(&self) -> bool {
+        if let Some(a2dp) = self.supports_a2dp() {
+            return a2dp;
+        }
+        if self.address_type != AddressType::BREDR {
+            return false;
+        }
+        match self.class {
+            Some(class) => match (class >> 8) & 0x1F {
+                // Miscellaneous or uncategorized, can't tell
+                0x00 | 0x1F => true,
+                _ => DeviceKind::from_class(class) != DeviceKind::Unknown,
+            },
+            None => true,
         }
     }
 
+    /// Get name to show, the alias if set or the device name.
+    pub fn display_name(&self) -> Option<&str> {
+        self.alias.as_deref().or(self.name.as_deref())
+    }
+
     /// Get address as human readable hex string separated by `:`.
     pub fn address_string(&self) -> String {
         util::address_hex(self.address)
     }
 }
 
+/// Kind of bluetooth device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Phone,
+    Computer,
+
+    /// Headset, headphones, speaker or other audio device.
+    Headset,
+
+    Unknown,
+}
+
+impl DeviceKind {
+    /// Get kind from class of device major and minor class.
+    pub fn from_class(class: u32) -> Self {
+        match (class >> 8) & 0x1F {
+            0x01 => DeviceKind::Computer,
+            0x02 => DeviceKind::Phone,
+            0x04 => DeviceKind::Headset,
+            _ => DeviceKind::Unknown,
+        }
+    }
+
+    /// Get kind from LE appearance category.
+    pub fn from_appearance(appearance: u16) -> Self {
+        match appearance >> 6 {
+            0x001 => DeviceKind::Phone,
+            0x002 => DeviceKind::Computer,
+            0x021 | 0x025 => DeviceKind::Headset,
+            _ => DeviceKind::Unknown,
+        }
+    }
+}
+
 /// Represents a list of bluetooth devices.
 #[derive(Clone)]
 pub struct DeviceList {
@@ -67,6 +209,48 @@ impl DeviceList {
         }
     }
 
+    /// Process controller power off, marks all devices disconnected.
+    pub(crate) fn process_power_off(&mut self) {
+        for device in &mut self.devices {
+            device.connected = false;
+        }
+    }
+
+    /// Sync with known devices, adding disconnected known devices and dropping forgotten ones.
+    pub(crate) fn sync_known(&mut self, known: &[KnownDevice]) {
+        for known in known {
+            let device = match self.get_mut(known.address) {
+                Some(device) => device,
+                None => {
+                    let mut device = Device::from_address(known.address, known.address_type);
+                    device.connected = false;
+                    self.devices.push(device);
+                    self.devices.last_mut().unwrap()
+                }
+            };
+            if device.name.is_none() {
+                device.name = known.name.clone();
+            }
+            device.class = device.class.or(known.class);
+            device.alias = known.alias.clone();
+            device.paired = known.paired;
+            device.trusted = known.trusted;
+            device.blocked = known.blocked;
+        }
+
+        // Reset devices that are no longer known, drop them if not connected
+        self.devices
+            .retain(|d| d.connected || known.iter().any(|k| k.address == d.address));
+        for device in &mut self.devices {
+            if !known.iter().any(|k| k.address == device.address) {
+                device.alias = None;
+                device.paired = false;
+                device.trusted = false;
+                device.blocked = false;
+            }
+        }
+    }
+
     /// Get device by address if exists.
     pub fn get(&self, address: Address) -> Option<&Device> {
         self.devices.iter().find(|d| d.address == address)
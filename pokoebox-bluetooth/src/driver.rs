@@ -1,15 +1,25 @@
-use std::error::Error;
+use std::error;
+use std::fmt;
+use std::time::Duration;
 
-use bluez::client::{AddressType, BlueZClient, DiscoverableMode, IoCapability};
+use bluez::client::{AddDeviceAction, AddressType, BlueZClient, DiscoverableMode, IoCapability};
 use bluez::interface::controller::{Controller, ControllerInfo, ControllerSetting};
+use bluez::interface::CommandStatus;
 use bluez::result::Error as BlueZError;
 use bluez::Address;
 use futures::executor::block_on;
 
+use crate::mgmt;
+use crate::{Config, Identity};
+
 /// Drives a bluetooth controller for PokoeBox audio connectivity.
 ///
 /// On creation this selects a capable bluetooth controller, and prepares it for audio
-/// connectivity.
+/// connectivity, presenting it with the given identity. The controller is left powered off if
+/// `powered` is `false`.
+///
+/// There may be no controller at all, commands then fail with `Error::NoController` until one is
+/// attached with `attach`.
 // TODO: attempt to power off controller on drop?
 pub struct Driver<'a> {
     pub(crate) client: BlueZClient<'a>,
@@ -17,84 +27,363 @@ pub struct Driver<'a> {
 }
 
 impl<'a> Driver<'a> {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        config: &Config,
+        identity: &Identity,
+        powered: bool,
+    ) -> Result<Self, Box<dyn error::Error>> {
         // Build client, find controller, initialize
         let mut client = BlueZClient::new()?;
-        let controller = Self::select_controller(&mut client)?;
-        if let Some(controller) = controller {
-            Self::init_controller(&mut client, controller)?;
+        let controller = Self::select_controller(&mut client, config)?;
+        match controller {
+            Some(controller) => {
+                Self::init_controller(&mut client, controller, config, identity, powered)?
+            }
+            None => warn!("No bluetooth controller found, waiting for one to be added"),
         }
 
         Ok(Self { client, controller })
     }
 
-    fn select_controller(client: &mut BlueZClient) -> Result<Option<Controller>, Box<dyn Error>> {
+    fn select_controller(
+        client: &mut BlueZClient,
+        config: &Config,
+    ) -> Result<Option<Controller>, Box<dyn error::Error>> {
         let controllers = block_on(client.get_controller_list())?;
 
-        // Select first controller we can power
+        // Select first controller we can use
         Ok(controllers
             .into_iter()
-            .filter_map(|controller| {
-                let info = block_on(client.get_controller_info(controller)).ok()?;
+            .find(|controller| Self::usable(client, *controller, config)))
+    }
+
+    /// Check whether we can power the given controller, and it is selected in the config.
+    fn usable(client: &mut BlueZClient, controller: Controller, config: &Config) -> bool {
+        let info = match block_on(client.get_controller_info(controller)) {
+            Ok(info) => info,
+            Err(_) => return false,
+        };
+
+        info.supported_settings.contains(ControllerSetting::Powered)
+            && config
+                .controller
+                .as_ref()
+                .map(|selector| selector.matches(controller, &info))
+                .unwrap_or(true)
+    }
+
+    /// Get the controller being driven, if any.
+    pub fn controller(&self) -> Option<Controller> {
+        self.controller
+    }
+
+    /// Attach to a newly added controller, if we have none and it is usable.
+    ///
+    /// Returns whether the controller was attached.
+    pub fn attach(
+        &mut self,
+        controller: Controller,
+        config: &Config,
+        identity: &Identity,
+        powered: bool,
+    ) -> Result<bool, Box<dyn error::Error>> {
+        if self.controller.is_some() || !Self::usable(&mut self.client, controller, config) {
+            return Ok(false);
+        }
 
-                if info.supported_settings.contains(ControllerSetting::Powered) {
-                    Some(controller)
-                } else {
-                    None
-                }
-            })
-            .next())
+        Self::init_controller(&mut self.client, controller, config, identity, powered)?;
+        self.controller = Some(controller);
+        Ok(true)
+    }
+
+    /// Detach from the controller, because it was removed.
+    pub fn detach(&mut self) {
+        self.controller.take();
+    }
+
+    /// Get the controller being driven, or fail if there is none.
+    fn attached(&self) -> Result<Controller, Error> {
+        self.controller.ok_or(Error::NoController)
     }
 
     fn init_controller(
         client: &mut BlueZClient,
         controller: Controller,
-    ) -> Result<(), Box<dyn Error>> {
-        block_on(client.set_powered(controller, true))?;
-        block_on(client.set_local_name(controller, crate::BT_NAME, crate::BT_NAME_SHORT))?;
-        block_on(client.set_io_capability(controller, IoCapability::NoInputNoOutput))?;
+        config: &Config,
+        identity: &Identity,
+        powered: bool,
+    ) -> Result<(), Box<dyn error::Error>> {
+        block_on(client.set_powered(controller, powered))?;
+        Self::apply_identity(client, controller, identity)?;
+
+        // In secure mode, pairing must be confirmed by the user
+        block_on(client.set_io_capability(
+            controller,
+            if config.secure {
+                IoCapability::DisplayYesNo
+            } else {
+                IoCapability::NoInputNoOutput
+            },
+        ))?;
         block_on(client.set_connectable(controller, true))?;
 
         Ok(())
     }
 
+    /// Set controller name and class of device.
+    fn apply_identity(
+        client: &mut BlueZClient,
+        controller: Controller,
+        identity: &Identity,
+    ) -> Result<(), BlueZError> {
+        block_on(client.set_local_name(controller, &identity.name, identity.short_name()))?;
+        block_on(client.set_device_class(controller, identity.class.device_class()))?;
+        Ok(())
+    }
+
+    /// Change the identity the controller presents itself with, applied immediately.
+    pub fn set_identity(&mut self, identity: &Identity) -> Result<(), Error> {
+        let controller = self.attached()?;
+        Self::apply_identity(&mut self.client, controller, identity)?;
+        Ok(())
+    }
+
     /// Get bluetooth controller state.
-    pub fn get_state(
-        &mut self,
-    ) -> Result<(ControllerInfo, Vec<(Address, AddressType)>), BlueZError> {
-        let controller = self.controller.unwrap();
+    pub fn get_state(&mut self) -> Result<(ControllerInfo, Vec<(Address, AddressType)>), Error> {
+        let controller = self.attached()?;
         let info = block_on(self.client.get_controller_info(controller))?;
         let connections = block_on(self.client.get_connections(controller))?;
         Ok((info, connections))
     }
 
+    /// Power bluetooth controller on or off.
+    ///
+    /// Powering off drops all connections, and disables discoverability.
+    pub fn set_powered(&mut self, powered: bool) -> Result<(), Error> {
+        let controller = self.attached()?;
+        block_on(self.client.set_powered(controller, powered))?;
+        Ok(())
+    }
+
     /// Set discoverability of bluetooth controller.
     ///
     /// Discoverability is enabled for a limited time and is automatically disabled after a while,
     /// see `BT_DISCOVER_TIMEOUT`.
-    pub fn set_discoverable(&mut self, discoverable: bool) -> Result<(), BlueZError> {
-        let controller = self.controller.unwrap();
+    pub fn set_discoverable(&mut self, discoverable: bool) -> Result<(), Error> {
+        self.set_discoverable_timeout(if discoverable {
+            Some(Duration::from_secs(crate::BT_DISCOVER_TIMEOUT.into()))
+        } else {
+            None
+        })
+    }
+
+    /// Make bluetooth controller discoverable for given time, or disable it with `None`.
+    ///
+    /// The time is rounded to whole seconds, and limited to `u16::MAX` seconds.
+    pub fn set_discoverable_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        let controller = self.attached()?;
         block_on(self.client.set_connectable(controller, true))?;
         block_on(self.client.set_bondable(controller, true))?;
         block_on(self.client.set_discoverable(
             controller,
-            if discoverable {
+            if timeout.is_some() {
                 DiscoverableMode::General
             } else {
                 DiscoverableMode::None
             },
-            if discoverable {
-                Some(crate::BT_DISCOVER_TIMEOUT)
-            } else {
-                None
-            },
-        ))
-        .map(|_| ())
+            // A timeout of 0 would keep the controller discoverable forever
+            timeout.map(|timeout| timeout.as_secs().max(1).min(u16::MAX.into()) as u16),
+        ))?;
+        Ok(())
+    }
+
+    /// Accept or reject pairing with device, answering a user confirmation request.
+    pub fn confirm_pairing(
+        &mut self,
+        address: Address,
+        address_type: AddressType,
+        accept: bool,
+    ) -> Result<(), Error> {
+        let controller = self.attached()?;
+        block_on(
+            self.client
+                .user_confirmation_reply(controller, address, address_type, accept),
+        )?;
+        Ok(())
     }
 }
 
-#[derive(Clone, Eq, PartialEq)]
-pub(crate) enum DriverCmd {
+/// Device management operations on a bluetooth controller.
+///
+/// Implemented by `Driver`, allows managing devices against a fake controller in tests.
+pub trait DeviceControl {
+    /// Trust device, allowing it to connect even if the controller is not connectable.
+    fn trust_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error>;
+
+    /// Untrust device, removing it from the list of devices allowed to connect.
+    fn untrust_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error>;
+
+    /// Remove pairing with device, disconnecting it, and untrust it.
+    fn forget_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error>;
+
+    /// Disconnect connected device.
+    fn disconnect_device(
+        &mut self,
+        address: Address,
+        address_type: AddressType,
+    ) -> Result<(), Error>;
+
+    /// Block device, rejecting any connection from it.
+    fn block_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error>;
+
+    /// Unblock device, allowing it to connect again.
+    fn unblock_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error>;
+}
+
+impl DeviceControl for Driver<'_> {
+    fn trust_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error> {
+        let controller = self.attached()?;
+        block_on(self.client.add_device(
+            controller,
+            address,
+            address_type,
+            AddDeviceAction::AllowConnect,
+        ))?;
+        Ok(())
+    }
+
+    fn untrust_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error> {
+        let controller = self.attached()?;
+        mgmt::remove_device(controller.into(), address, address_type)?;
+        Ok(())
+    }
+
+    fn forget_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error> {
+        let controller = self.attached()?;
+
+        // Succeed if the device wasn't trusted or paired
+        match mgmt::remove_device(controller.into(), address, address_type) {
+            Ok(()) | Err(mgmt::Error::Status(mgmt::STATUS_INVALID_PARAMS)) => {}
+            Err(err) => return Err(err.into()),
+        }
+        match block_on(
+            self.client
+                .unpair_device(controller, address, address_type, true),
+        ) {
+            Ok(_)
+            | Err(BlueZError::CommandError {
+                status: CommandStatus::NotPaired,
+                ..
+            }) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn disconnect_device(
+        &mut self,
+        address: Address,
+        address_type: AddressType,
+    ) -> Result<(), Error> {
+        let controller = self.attached()?;
+        block_on(self.client.disconnect(controller, address, address_type))?;
+        Ok(())
+    }
+
+    fn block_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error> {
+        let controller = self.attached()?;
+        block_on(self.client.block_device(controller, address, address_type))?;
+        Ok(())
+    }
+
+    fn unblock_device(&mut self, address: Address, address_type: AddressType) -> Result<(), Error> {
+        let controller = self.attached()?;
+        block_on(
+            self.client
+                .unblock_device(controller, address, address_type),
+        )?;
+        Ok(())
+    }
+}
+
+/// Bluetooth driver error.
+#[derive(Debug)]
+pub enum Error {
+    /// There is no bluetooth controller to drive.
+    NoController,
+
+    /// BlueZ error.
+    BlueZ(BlueZError),
+
+    /// Management command not supported by BlueZ failed.
+    Mgmt(mgmt::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoController => write!(f, "no bluetooth controller available"),
+            Error::BlueZ(err) => write!(f, "{}", err),
+            Error::Mgmt(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<BlueZError> for Error {
+    fn from(err: BlueZError) -> Self {
+        Error::BlueZ(err)
+    }
+}
+
+impl From<mgmt::Error> for Error {
+    fn from(err: mgmt::Error) -> Self {
+        Error::Mgmt(err)
+    }
+}
+
+/// Bluetooth driver command.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DriverCmd {
+    /// Set discoverability of the controller.
     Discoverable(bool),
+
+    /// Extend the time the controller is discoverable by the given duration.
+    ExtendDiscoverable(Duration),
+
+    /// Emit events describing the current state.
     EmitState,
+
+    /// Power the controller on or off, remembered across restarts.
+    Power(bool),
+
+    /// Trust device, allowing it to connect.
+    Trust(Address),
+
+    /// Untrust device.
+    Untrust(Address),
+
+    /// Remove pairing with device, and forget it.
+    Forget(Address),
+
+    /// Emit list of known devices.
+    ListKnown,
+
+    /// Disconnect device.
+    Disconnect(Address),
+
+    /// Block device, rejecting connections from it.
+    Block(Address),
+
+    /// Unblock device.
+    Unblock(Address),
+
+    /// Accept or reject pairing request of device, see `Event::PairingConfirm`.
+    ConfirmPairing(Address, bool),
+
+    /// Set custom device name, or reset it with `None`.
+    SetAlias(Address, Option<String>),
+
+    /// Change controller name and class of device, remembered across restarts.
+    SetIdentity(Identity),
 }
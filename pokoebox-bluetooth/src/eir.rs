@@ -1,7 +1,13 @@
 //! Based on: https://android.googlesource.com/platform/external/bluetooth/bluez/+/master/src/eir.c
 
+use std::convert::TryInto;
+use std::fmt;
+
 const HCI_MAX_EIR_LENGTH: usize = 240;
 
+/// Bluetooth base UUID, 16 and 32 bit UUIDs are offsets into it.
+const BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5F9B_34FB;
+
 #[repr(u8)]
 #[allow(unused)]
 enum EirByte {
@@ -15,120 +21,316 @@ enum EirByte {
     NameShort = 0x08,
     NameComplete = 0x09,
     TxPower = 0x0A,
+    ClassOfDevice = 0x0D,
     DeviceId = 0x10,
+    Appearance = 0x19,
+    ManufacturerData = 0xFF,
+}
+
+/// Service UUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    /// A2DP audio source, a device that can stream audio to us.
+    pub const AUDIO_SOURCE: Uuid = Uuid::from_u16(0x110A);
+
+    /// A2DP audio sink.
+    pub const AUDIO_SINK: Uuid = Uuid::from_u16(0x110B);
+
+    /// A2DP advanced audio distribution profile.
+    pub const ADVANCED_AUDIO: Uuid = Uuid::from_u16(0x110D);
+
+    /// UUID from 16 bit short form.
+    pub const fn from_u16(uuid: u16) -> Self {
+        Self::from_u32(uuid as u32)
+    }
+
+    /// UUID from 32 bit short form.
+    pub const fn from_u32(uuid: u32) -> Self {
+        Uuid(BASE_UUID | (uuid as u128) << 96)
+    }
+
+    /// Get 16 bit short form, if this is a 16 bit UUID.
+    pub fn as_u16(self) -> Option<u16> {
+        let short = self.0 >> 96;
+        if self.0 & ((1 << 96) - 1) == BASE_UUID && short <= 0xFFFF {
+            Some(short as u16)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v = self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            v >> 96,
+            (v >> 80) & 0xFFFF,
+            (v >> 64) & 0xFFFF,
+            (v >> 48) & 0xFFFF,
+            v & 0xFFFF_FFFF_FFFF,
+        )
+    }
+}
+
+/// Device ID record, identifies vendor and product.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceId {
+    /// Vendor ID source, 1 for Bluetooth SIG, 2 for USB Implementer's Forum.
+    pub source: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
 }
 
 /// EIR data object.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EirData {
-    // GSList *services;
     pub flags: u8,
     pub name: Option<String>,
     pub name_complete: bool,
+
+    /// Advertised service UUIDs.
+    pub services: Vec<Uuid>,
+
+    /// Class of device, 24 bits.
+    pub class: Option<u32>,
+
+    /// Transmit power level in dBm.
+    pub tx_power: Option<i8>,
+
+    /// Appearance, external look of LE devices.
+    pub appearance: Option<u16>,
+
+    pub device_id: Option<DeviceId>,
+
+    /// Manufacturer specific data, by company identifier.
+    pub manufacturer_data: Vec<(u16, Vec<u8>)>,
 }
 
 /// Parse given EIR data.
+///
+/// Also parses LE advertising data, which uses the same format.
 pub fn parse(mut eir_data: &[u8]) -> Option<EirData> {
-    // No EIR data
-    if eir_data.is_empty() {
+    // No EIR data, or invalid length
+    if eir_data.is_empty() || eir_data.len() > HCI_MAX_EIR_LENGTH {
         return None;
     }
 
-    let mut flags = 1u8;
-    let mut name = None;
-    let mut name_complete = false;
-
-    let mut len = 0usize;
-    let mut _uuid16_count: usize = 0;
-    let mut _uuid32_count: usize = 0;
-    let mut _uuid128_count: usize = 0;
-    // let *uuid16 = NULL;
-    // let *uuid32 = NULL;
-    // let *uuid128 = NULL;
-    // uuid_t service;
-    // char *uuid_str;
-    // unsigned int i;
+    let mut data = EirData {
+        flags: 1,
+        name: None,
+        name_complete: false,
+        services: Vec::new(),
+        class: None,
+        tx_power: None,
+        appearance: None,
+        device_id: None,
+        manufacturer_data: Vec::new(),
+    };
 
-    while !eir_data.is_empty() && len < HCI_MAX_EIR_LENGTH - 1 {
+    while eir_data.len() >= 2 {
         let field_len = eir_data[0] as usize;
 
-        // Check for EIR end
-        if field_len == 0 {
+        // Check for EIR end, stop at truncated field
+        if field_len == 0 || field_len >= eir_data.len() {
             break;
         }
+        let value = &eir_data[2..=field_len];
 
         match eir_data[1] {
             x if x == EirByte::Uuid16Some as u8 || x == EirByte::Uuid16All as u8 => {
-                _uuid16_count = field_len as usize / 2;
-                // uuid16 = &eir_data[2];
+                data.services.extend(
+                    value
+                        .chunks_exact(2)
+                        .map(|uuid| Uuid::from_u16(u16::from_le_bytes([uuid[0], uuid[1]]))),
+                );
             }
             x if x == EirByte::Uuid32Some as u8 || x == EirByte::Uuid32All as u8 => {
-                _uuid32_count = field_len as usize / 4;
-                // uuid32 = &eir_data[2];
+                data.services.extend(
+                    value
+                        .chunks_exact(4)
+                        .map(|uuid| Uuid::from_u32(u32::from_le_bytes(uuid.try_into().unwrap()))),
+                );
             }
             x if x == EirByte::Uuid128Some as u8 || x == EirByte::Uuid128All as u8 => {
-                _uuid128_count = field_len as usize / 16;
-                // uuid128 = &eir_data[2];
+                data.services.extend(
+                    value
+                        .chunks_exact(16)
+                        .map(|uuid| Uuid(u128::from_le_bytes(uuid.try_into().unwrap()))),
+                );
             }
-            x if x == EirByte::Flags as u8 => {
-                flags = eir_data[2];
+            x if x == EirByte::Flags as u8 && !value.is_empty() => {
+                data.flags = value[0];
             }
             x if x == EirByte::NameShort as u8 || x == EirByte::NameComplete as u8 => {
-                name = String::from_utf8(eir_data[2..=field_len].to_vec()).ok();
-                name_complete = x == EirByte::NameComplete as u8;
+                data.name = String::from_utf8(value.to_vec()).ok();
+                data.name_complete = x == EirByte::NameComplete as u8;
+            }
+            x if x == EirByte::TxPower as u8 && !value.is_empty() => {
+                data.tx_power = Some(value[0] as i8);
+            }
+            x if x == EirByte::ClassOfDevice as u8 && value.len() >= 3 => {
+                data.class = Some(u32::from_le_bytes([value[0], value[1], value[2], 0]));
+            }
+            x if x == EirByte::DeviceId as u8 && value.len() >= 8 => {
+                let word = |i: usize| u16::from_le_bytes([value[i], value[i + 1]]);
+                data.device_id = Some(DeviceId {
+                    source: word(0),
+                    vendor: word(2),
+                    product: word(4),
+                    version: word(6),
+                });
+            }
+            x if x == EirByte::Appearance as u8 && value.len() >= 2 => {
+                data.appearance = Some(u16::from_le_bytes([value[0], value[1]]));
+            }
+            x if x == EirByte::ManufacturerData as u8 && value.len() >= 2 => {
+                data.manufacturer_data.push((
+                    u16::from_le_bytes([value[0], value[1]]),
+                    value[2..].to_vec(),
+                ));
             }
             _ => {}
         }
 
-        len += field_len as usize + 1;
-        eir_data = &eir_data[field_len as usize + 1..];
+        eir_data = &eir_data[field_len + 1..];
     }
 
-    // Stop if invalid length
-    if len > HCI_MAX_EIR_LENGTH {
-        return None;
+    data.services.dedup();
+
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_or_oversized() {
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&[0; HCI_MAX_EIR_LENGTH + 1]), None);
+    }
+
+    #[test]
+    fn truncated_field() {
+        // Name field claims 9 bytes but only 4 follow, flags before it are kept
+        let data = parse(&[0x02, 0x01, 0x06, 0x09, 0x09, b'a', b'b', b'c']).unwrap();
+        assert_eq!(data.flags, 0x06);
+        assert_eq!(data.name, None);
+    }
+
+    #[test]
+    fn field_without_value() {
+        // Fields with length 1 only have a type, they must not be indexed
+        let data = parse(&[0x01, 0x01, 0x01, 0x0A, 0x01, 0x0D, 0x01, 0x19]).unwrap();
+        assert_eq!(data.flags, 1);
+        assert_eq!(data.tx_power, None);
+        assert_eq!(data.class, None);
+        assert_eq!(data.appearance, None);
+    }
+
+    #[test]
+    fn name() {
+        let data = parse(&[0x04, 0x08, b'P', b'o', b'k', 0x00]).unwrap();
+        assert_eq!(data.name.as_deref(), Some("Pok"));
+        assert!(!data.name_complete);
+
+        let data = parse(&[0x03, 0x09, b'P', b'o']).unwrap();
+        assert_eq!(data.name.as_deref(), Some("Po"));
+        assert!(data.name_complete);
+    }
+
+    #[test]
+    fn uuid16_list() {
+        let data = parse(&[0x05, 0x03, 0x0A, 0x11, 0x0B, 0x11]).unwrap();
+        assert_eq!(data.services, vec![Uuid::AUDIO_SOURCE, Uuid::AUDIO_SINK]);
+    }
+
+    #[test]
+    fn uuid32_list() {
+        let data = parse(&[0x05, 0x05, 0x0D, 0x11, 0x00, 0x00]).unwrap();
+        assert_eq!(data.services, vec![Uuid::ADVANCED_AUDIO]);
+
+        let data = parse(&[0x05, 0x04, 0x78, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(data.services, vec![Uuid::from_u32(0x1234_5678)]);
     }
 
-    // Return data
-    Some(EirData {
-        flags,
-        name,
-        name_complete,
-    })
-
-    // // Skip if no UUIDs were parsed
-    // let total = uuid16_count + _uuid32_count + _uuid128_count;
-    // if total == 0 {
-    //     return 0;
-    // }
-
-    // /* Generate uuids in SDP format (EIR data is Little Endian) */
-    // service.type = SDP_UUID16;
-    // for (i = 0; i < uuid16_count; i++) {
-    //         uint16_t val16 = uuid16[1];
-    //         val16 = (val16 << 8) + uuid16[0];
-    //         service.value.uuid16 = val16;
-    //         uuid_str = bt_uuid2string(&service);
-    //         eir->services = g_slist_append(eir->services, uuid_str);
-    //         uuid16 += 2;
-    // }
-    // service.type = SDP_UUID32;
-    // for (i = uuid16_count; i < uuid32_count + uuid16_count; i++) {
-    //         uint32_t val32 = uuid32[3];
-    //         int k;
-    //         for (k = 2; k >= 0; k--)
-    //                 val32 = (val32 << 8) + uuid32[k];
-    //         service.value.uuid32 = val32;
-    //         uuid_str = bt_uuid2string(&service);
-    //         eir->services = g_slist_append(eir->services, uuid_str);
-    //         uuid32 += 4;
-    // }
-    // service.type = SDP_UUID128;
-    // for (i = uuid32_count + uuid16_count; i < total; i++) {
-    //         int k;
-    //         for (k = 0; k < 16; k++)
-    //                 service.value.uuid128.data[k] = uuid128[16 - k - 1];
-    //         uuid_str = bt_uuid2string(&service);
-    //         eir->services = g_slist_append(eir->services, uuid_str);
-    //         uuid128 += 16;
-    // }
+    #[test]
+    fn uuid128_list() {
+        let uuid: u128 = 0x0123_4567_89AB_CDEF_0123_4567_89AB_CDEF;
+        let mut raw = vec![0x11, 0x07];
+        raw.extend_from_slice(&uuid.to_le_bytes());
+        let data = parse(&raw).unwrap();
+        assert_eq!(data.services, vec![Uuid(uuid)]);
+    }
+
+    #[test]
+    fn uuid_list_partial_entry() {
+        // Trailing byte that doesn't form a full UUID is ignored
+        let data = parse(&[0x04, 0x03, 0x0A, 0x11, 0x0B]).unwrap();
+        assert_eq!(data.services, vec![Uuid::AUDIO_SOURCE]);
+    }
+
+    #[test]
+    fn class_of_device() {
+        // Audio service, audio/video major class, loudspeaker minor class
+        let data = parse(&[0x04, 0x0D, 0x14, 0x04, 0x20]).unwrap();
+        assert_eq!(data.class, Some(0x20_0414));
+
+        let data = parse(&[0x03, 0x0D, 0x14, 0x04]).unwrap();
+        assert_eq!(data.class, None);
+    }
+
+    #[test]
+    fn device_id() {
+        let data = parse(&[0x09, 0x10, 0x01, 0x00, 0x4C, 0x00, 0x34, 0x12, 0x02, 0x01]).unwrap();
+        assert_eq!(
+            data.device_id,
+            Some(DeviceId {
+                source: 1,
+                vendor: 0x004C,
+                product: 0x1234,
+                version: 0x0102,
+            })
+        );
+    }
+
+    #[test]
+    fn manufacturer_data() {
+        let data = parse(&[0x05, 0xFF, 0x4C, 0x00, 0xAA, 0xBB, 0x03, 0xFF, 0x06, 0x00]).unwrap();
+        assert_eq!(
+            data.manufacturer_data,
+            vec![(0x004C, vec![0xAA, 0xBB]), (0x0006, vec![])]
+        );
+    }
+
+    #[test]
+    fn tx_power_and_appearance() {
+        let data = parse(&[0x02, 0x0A, 0xF6, 0x03, 0x19, 0x41, 0x08]).unwrap();
+        assert_eq!(data.tx_power, Some(-10));
+        assert_eq!(data.appearance, Some(0x0841));
+    }
+
+    #[test]
+    fn uuid_as_u16() {
+        assert_eq!(Uuid::AUDIO_SOURCE.as_u16(), Some(0x110A));
+        assert_eq!(Uuid::from_u32(0x1234_5678).as_u16(), None);
+        assert_eq!(
+            Uuid(0x0123_4567_89AB_CDEF_0123_4567_89AB_CDEF).as_u16(),
+            None
+        );
+    }
+
+    #[test]
+    fn uuid_display() {
+        assert_eq!(
+            Uuid::AUDIO_SINK.to_string(),
+            "0000110b-0000-1000-8000-00805f9b34fb"
+        );
+    }
 }
@@ -0,0 +1,137 @@
+//! How the controller presents itself to other devices.
+
+use std::fmt;
+
+use bluez::interface::class::{AudioVideoDeviceClass, DeviceClass};
+use serde::{Deserialize, Serialize};
+
+/// Maximum length of the controller name in bytes.
+pub const NAME_MAX: usize = 248;
+
+/// Maximum length of the controller short name in bytes.
+pub const SHORT_NAME_MAX: usize = 10;
+
+/// Controller identity, its name and class of device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Identity {
+    /// Publicly visible name.
+    pub name: String,
+
+    /// Short name, with at most `SHORT_NAME_MAX` bytes.
+    ///
+    /// The name is used if not set and short enough.
+    #[serde(default)]
+    pub short_name: Option<String>,
+
+    /// Class of device to present as.
+    pub class: SpeakerClass,
+}
+
+impl Identity {
+    /// Check whether names fit within their limits.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::EmptyName);
+        }
+        if self.name.len() > NAME_MAX {
+            return Err(Error::NameTooLong(self.name.clone()));
+        }
+        match &self.short_name {
+            Some(name) if name.len() > SHORT_NAME_MAX => Err(Error::ShortNameTooLong(name.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get short name to present, the name itself if not set and short enough.
+    pub fn short_name(&self) -> Option<&str> {
+        match &self.short_name {
+            Some(name) => Some(name),
+            None if self.name.len() <= SHORT_NAME_MAX => Some(&self.name),
+            None => None,
+        }
+    }
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Self {
+            name: crate::BT_NAME.into(),
+            short_name: crate::BT_NAME_SHORT.map(Into::into),
+            class: SpeakerClass::default(),
+        }
+    }
+}
+
+/// Audio class of device to present as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeakerClass {
+    #[default]
+    Loudspeaker,
+    Portable,
+    #[serde(rename = "hifi")]
+    HiFi,
+    Headphones,
+}
+
+impl SpeakerClass {
+    /// All classes, in order to list them in.
+    pub const ALL: [SpeakerClass; 4] = [
+        SpeakerClass::Loudspeaker,
+        SpeakerClass::Portable,
+        SpeakerClass::HiFi,
+        SpeakerClass::Headphones,
+    ];
+
+    /// Human readable name.
+    pub fn name(self) -> &'static str {
+        match self {
+            SpeakerClass::Loudspeaker => "Loudspeaker",
+            SpeakerClass::Portable => "Portable audio",
+            SpeakerClass::HiFi => "HiFi audio",
+            SpeakerClass::Headphones => "Headphones",
+        }
+    }
+
+    /// Get BlueZ device class.
+    pub(crate) fn device_class(self) -> DeviceClass {
+        DeviceClass::AudioVideo(match self {
+            SpeakerClass::Loudspeaker => AudioVideoDeviceClass::Loudspeaker,
+            SpeakerClass::Portable => AudioVideoDeviceClass::Portable,
+            SpeakerClass::HiFi => AudioVideoDeviceClass::HiFi,
+            SpeakerClass::Headphones => AudioVideoDeviceClass::Headphones,
+        })
+    }
+}
+
+/// Invalid identity error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Name is empty.
+    EmptyName,
+
+    /// Name is longer than `NAME_MAX` bytes.
+    NameTooLong(String),
+
+    /// Short name is longer than `SHORT_NAME_MAX` bytes.
+    ShortNameTooLong(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EmptyName => write!(f, "bluetooth name must not be empty"),
+            Error::NameTooLong(name) => write!(
+                f,
+                "bluetooth name '{}' is too long, at most {} bytes allowed",
+                name, NAME_MAX
+            ),
+            Error::ShortNameTooLong(name) => write!(
+                f,
+                "bluetooth short name '{}' is too long, at most {} bytes allowed",
+                name, SHORT_NAME_MAX
+            ),
+        }
+    }
+}
@@ -1,17 +1,34 @@
 #[macro_use]
 extern crate log;
 
+pub mod config;
 pub mod device;
 pub mod driver;
 pub mod eir;
+pub mod identity;
 pub mod manager;
+mod mgmt;
+pub mod policy;
+mod reconnect;
+pub mod store;
 pub mod util;
 
-/// Publicly visible bluetooth controller name.
+// Re-export
+pub use bluez::Address;
+pub use config::Config;
+pub use identity::Identity;
+
+/// Publicly visible bluetooth controller name, default if not configured.
 pub const BT_NAME: &str = "PokoeBox";
 
 /// Publicly visible bluetooth controller name, short variant, with at most 10 bytes.
 pub const BT_NAME_SHORT: Option<&str> = Some(BT_NAME);
 
+// The short name must fit, see `identity::SHORT_NAME_MAX`
+const _: () = assert!(match BT_NAME_SHORT {
+    Some(name) => name.len() <= identity::SHORT_NAME_MAX,
+    None => true,
+});
+
 /// Bluetooth discovery mode timeout in seconds.
 pub(crate) const BT_DISCOVER_TIMEOUT: u16 = 60;
@@ -1,19 +1,26 @@
 use std::error::Error;
 use std::sync::mpsc::{self, Receiver};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
+use bluez::client::AddressType;
 use bluez::interface::controller::ControllerSetting;
 use bluez::interface::event::Event as BlueZEvent;
 use bluez::Address;
 use futures::executor::block_on;
-use pokoebox_common::pipe::Pipe;
+use pokoebox_common::pipe::{Error as PipeError, Pipe};
 use tokio_executor::park::ParkThread;
 use tokio_timer::timer::Timer;
 
-use super::device::DeviceList;
-use super::driver::{Driver, DriverCmd};
+use super::device::{Device, DeviceList};
+use super::driver::{DeviceControl, Driver, DriverCmd, Error as DriverError};
+use super::store::{DeviceStore, KnownDevice};
+use crate::config::ReconnectConfig;
+use crate::eir;
+use crate::policy::{self, Decision, Reason};
+use crate::reconnect::Reconnect;
 use crate::util;
+use crate::{Config, Identity};
 
 /// Bluetooth manager.
 ///
@@ -24,8 +31,8 @@ pub struct Manager {
 }
 
 impl Manager {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let (pipe_event, pipe_cmd) = Self::spawn_worker();
+    pub fn new(config: Config) -> Result<Self, Box<dyn Error>> {
+        let (pipe_event, pipe_cmd) = Self::spawn_worker(config);
         let manager = Self {
             events: pipe_event,
             cmds: pipe_cmd,
@@ -40,7 +47,7 @@ impl Manager {
     }
 
     /// Spawn single worker thread with bluetooth controller
-    fn spawn_worker() -> (Pipe<Event>, Pipe<DriverCmd>) {
+    fn spawn_worker(config: Config) -> (Pipe<Event>, Pipe<DriverCmd>) {
         // Channel for bluetooth events, driver commands
         let event_pipe = Pipe::default();
         let cmd_pipe = Pipe::default();
@@ -49,7 +56,7 @@ impl Manager {
         let closure_cmd_pipe = cmd_pipe.clone();
         thread::spawn(move || {
             // TODO: propagate error
-            Self::process_loop(closure_event_pipe, closure_cmd_pipe)
+            Self::process_loop(config, closure_event_pipe, closure_cmd_pipe)
                 .expect("Bluetooth controller error");
         });
 
@@ -57,18 +64,22 @@ impl Manager {
     }
 
     fn process_loop(
+        config: Config,
         pipe_event: Pipe<Event>,
         pipe_cmd: Pipe<DriverCmd>,
     ) -> Result<(), Box<dyn Error>> {
-        // Set up bluetooth controller
-        let mut driver = Driver::new()?;
-        let mut devices = DeviceList::default();
-
-        // TODO: stop if no controller is found
-        // // We must have a controller selected
-        // if data.controller.is_none() {
-        //     return Ok(());
-        // }
+        // Set up bluetooth controller, keep it off if bluetooth was turned off
+        let store = DeviceStore::load(config.store.clone());
+        let mut state = State {
+            driver: Driver::new(&config, &identity(&config, &store), store.powered())?,
+            devices: DeviceList::default(),
+            store,
+            reconnect: None,
+            pairing: Vec::new(),
+            discoverable_until: None,
+            discoverable_remaining: None,
+        };
+        state.devices.sync_known(state.store.devices());
 
         // Create timer thread, get handle
         let (_worker_guard, worker_token): (_, Receiver<()>) = mpsc::channel();
@@ -95,21 +106,34 @@ impl Manager {
         // Allocate command listener
         let cmd_rx = pipe_cmd.listen();
 
+        // Prepare controller if we have one, we may get one later
+        if state.driver.controller().is_some() {
+            setup_controller(&config, &pipe_event, &mut state);
+        }
+
         loop {
             // Process bluetooth events with timeout
             // TODO: increase timeout if no events/commands for a while
-            let response = block_on(timer.timeout(driver.client.process(), Duration::from_secs(1)));
+            let response =
+                block_on(timer.timeout(state.driver.client.process(), Duration::from_secs(1)));
             if let Ok(response) = response {
                 // TODO: propagate error
                 process_bluetooth_event(
                     response.expect("failed to process bluetooth"),
-                    &mut devices,
+                    &config,
                     &pipe_event,
+                    &mut state,
                 );
             }
 
             // Process commands
-            process_commands(&cmd_rx, &pipe_event, &mut driver, &mut devices);
+            process_commands(&config, &cmd_rx, &pipe_event, &mut state);
+
+            // Reject pairing requests that weren't answered in time
+            expire_pairing(&pipe_event, &mut state);
+
+            // Emit remaining discoverable time
+            tick_discoverable(&pipe_event, &mut state);
 
             // TODO: break if bluetooth manager was dropped
         }
@@ -135,6 +159,13 @@ impl Manager {
             .map(|_| ())
             .map_err(|_| ())
     }
+
+    /// Send command to the bluetooth driver.
+    ///
+    /// Results are emitted as events, failures as `Event::CmdFailed`.
+    pub fn send_cmd(&self, cmd: DriverCmd) -> Result<(), PipeError> {
+        self.cmds.send(cmd).map(|_| ())
+    }
 }
 
 /// Bluetooth driver event.
@@ -147,23 +178,107 @@ pub enum Event {
     DeviceDisconnected(Address, DeviceList),
     Discoverable(bool),
     Power(bool),
+
+    /// Known devices, that paired, were trusted or connected before.
+    Known(Vec<KnownDevice>),
+
+    /// Device was trusted or untrusted.
+    Trusted(Address, bool),
+
+    /// Device pairing was removed, and it was forgotten.
+    Forgotten(Address),
+
+    /// Whether there is a bluetooth controller, it may be added or removed at any time.
+    Controller(bool),
+
+    /// Name and class of device the controller presents itself with.
+    Identity(Identity),
+
+    /// Driver command failed, with error description.
+    CmdFailed(DriverCmd, String),
+
+    /// Time the controller remains discoverable, emitted every second while discoverable.
+    DiscoverableRemaining(Duration),
+
+    /// Trying to reconnect to recently connected device, with attempt number starting at 1.
+    Reconnecting(Address, u32),
+
+    /// Stopped reconnecting, with the connected device or `None` if none connected.
+    ReconnectDone(Option<Address>),
+
+    /// Device was disconnected by the connection policy.
+    PolicyDisconnected(Address, Reason),
+
+    /// Device wants to pair in secure mode, the user must confirm with `DriverCmd::ConfirmPairing`.
+    ///
+    /// Has the device name if known, and the passkey to compare with the one shown on the device
+    /// if any.
+    PairingConfirm(Address, Option<String>, Option<u32>),
+
+    /// Pairing confirmation was answered or timed out, with whether pairing was accepted.
+    PairingDone(Address, bool),
+}
+
+/// Bluetooth worker state.
+struct State<'a> {
+    driver: Driver<'a>,
+    devices: DeviceList,
+    store: DeviceStore,
+
+    /// Running attempt to reconnect to recent devices.
+    reconnect: Option<Reconnect>,
+
+    /// Pairing requests waiting for confirmation by the user.
+    pairing: Vec<PendingPairing>,
+
+    /// When the controller stops being discoverable, if discoverable.
+    discoverable_until: Option<Instant>,
+
+    /// Remaining discoverable seconds last emitted.
+    discoverable_remaining: Option<u64>,
+}
+
+/// Pairing request waiting for confirmation by the user.
+struct PendingPairing {
+    address: Address,
+    address_type: AddressType,
+
+    /// When to reject the request if not answered.
+    deadline: Instant,
 }
 
 #[inline]
 fn process_bluetooth_event(
     response: bluez::interface::response::Response,
-    devices: &mut DeviceList,
+    config: &Config,
     pipe_event: &Pipe<Event>,
+    state: &mut State,
 ) {
+    if !process_controller_event(&response, config, pipe_event, state) {
+        return;
+    }
+
+    let State {
+        driver,
+        devices,
+        store,
+        reconnect,
+        pairing,
+        discoverable_until,
+        ..
+    } = state;
+
     // Parse bluetooth events, send over channel
     let mut events = vec![];
     match &response.event {
         BlueZEvent::NewSettings { settings, .. } => {
             // TODO: only invoke if this specific setting changed
+            let discoverable = settings.contains(ControllerSetting::Discoverable);
+            if !discoverable {
+                *discoverable_until = None;
+            }
             events.push(Event::Power(settings.contains(ControllerSetting::Powered)));
-            events.push(Event::Discoverable(
-                settings.contains(ControllerSetting::Discoverable),
-            ));
+            events.push(Event::Discoverable(discoverable));
         }
         BlueZEvent::DeviceConnected {
             address,
@@ -171,22 +286,160 @@ fn process_bluetooth_event(
             eir_data,
             ..
         } => {
-            // Add bluetooth device as trusted
-            util::trust_device(*address);
+            let eir = eir::parse(eir_data);
+            let name = eir.as_ref().and_then(|eir| eir.name.clone());
+            let class = eir.as_ref().and_then(|eir| eir.class);
+
+            // Apply connection policy, with what we know about the device
+            let mut device = devices
+                .get(*address)
+                .cloned()
+                .unwrap_or_else(|| Device::from_address(*address, *address_type));
+            if let Some(eir) = &eir {
+                device.process_eir(eir);
+            }
+            device.class = device
+                .class
+                .or_else(|| store.get(*address).and_then(|d| d.class));
+            match policy::decide(&config.policy, &device, devices, store) {
+                Decision::Accept => {}
+                Decision::Reject(reason) => {
+                    info!(
+                        "Rejecting bluetooth device by connection policy ({:?}): {}",
+                        reason,
+                        util::address_hex(*address)
+                    );
+                    if let Err(err) = driver.disconnect_device(*address, *address_type) {
+                        error!("Failed to disconnect rejected bluetooth device: {}", err);
+                    }
+                    events.push(Event::PolicyDisconnected(*address, reason));
+                    for event in events {
+                        let _ = pipe_event.send(event);
+                    }
+                    return;
+                }
+                Decision::Replace(replaced) => {
+                    for old in replaced {
+                        info!(
+                            "Disconnecting bluetooth device replaced by new device: {}",
+                            util::address_hex(old)
+                        );
+                        let old_type = self::address_type(devices, store, old);
+                        match driver.disconnect_device(old, old_type) {
+                            Ok(()) => {
+                                devices.process_device_disconnected(old);
+                                events.push(Event::PolicyDisconnected(old, Reason::Replaced));
+                                events.push(Event::DeviceDisconnected(old, devices.clone()));
+                            }
+                            Err(err) => {
+                                error!("Failed to disconnect replaced bluetooth device: {}", err)
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Stop reconnecting, a device connected
+            if let Some(reconnect) = reconnect.take() {
+                reconnect.finish(pipe_event, Some(*address));
+            }
+
+            // Remember device, add as trusted
+            store.update(*address, *address_type, |device| {
+                device.last_connected = Some(SystemTime::now());
+                if name.is_some() {
+                    device.name = name.clone();
+                }
+                device.class = class.or(device.class);
+            });
+            if !store.get(*address).map(|d| d.trusted).unwrap_or(false) {
+                events.push(trust(driver, store, *address, true));
+            }
 
             // Update device list
-            devices.process_device_connected(
-                *address,
-                *address_type,
-                util::parse_device_name(eir_data),
-            );
+            devices.process_device_connected(*address, *address_type, name);
+            if let (Some(device), Some(eir)) = (devices.get_mut(*address), &eir) {
+                device.process_eir(eir);
+            }
+            devices.sync_known(store.devices());
 
             events.push(Event::DeviceConnected(*address, devices.clone()));
+            events.push(Event::Known(store.devices().to_vec()));
+        }
+        BlueZEvent::NewLinkKey {
+            address,
+            address_type,
+            ..
+        } => {
+            store.update(*address, *address_type, |device| device.paired = true);
+            devices.sync_known(store.devices());
+            events.push(Event::Known(store.devices().to_vec()));
+            events.push(Event::Devices(devices.clone()));
+        }
+        BlueZEvent::DeviceUnpaired {
+            address,
+            address_type,
+        } => {
+            store.update(*address, *address_type, |device| device.paired = false);
+            devices.sync_known(store.devices());
+            events.push(Event::Known(store.devices().to_vec()));
+            events.push(Event::Devices(devices.clone()));
+        }
+        BlueZEvent::UserConfirmationRequest {
+            address,
+            address_type,
+            confirm_hint,
+            value,
+        } => {
+            if !config.secure || !policy::allowed(&config.policy, *address) {
+                // Not in secure mode, or never allowed to connect, answer right away
+                let accept = !config.secure;
+                if let Err(err) = driver.confirm_pairing(*address, *address_type, accept) {
+                    error!("Failed to answer bluetooth pairing request: {}", err);
+                }
+            } else {
+                info!(
+                    "Bluetooth device wants to pair, asking for confirmation: {}",
+                    util::address_hex(*address)
+                );
+                pairing.retain(|p| p.address != *address);
+                pairing.push(PendingPairing {
+                    address: *address,
+                    address_type: *address_type,
+                    deadline: Instant::now() + Duration::from_secs(config.confirm_timeout),
+                });
+                let name = devices
+                    .get(*address)
+                    .and_then(|d| d.name.clone())
+                    .or_else(|| store.get(*address).and_then(|d| d.name.clone()));
+                let passkey = if *confirm_hint { None } else { Some(*value) };
+                events.push(Event::PairingConfirm(*address, name, passkey));
+            }
+        }
+        BlueZEvent::DeviceBlocked {
+            address,
+            address_type,
+        }
+        | BlueZEvent::DeviceUnblocked {
+            address,
+            address_type,
+        } => {
+            let blocked = matches!(response.event, BlueZEvent::DeviceBlocked { .. });
+            store.update(*address, *address_type, |device| device.blocked = blocked);
+            devices.sync_known(store.devices());
+            events.push(Event::Known(store.devices().to_vec()));
+            events.push(Event::Devices(devices.clone()));
         }
         BlueZEvent::DeviceDisconnected { address, .. } => {
             // Update device list
             devices.process_device_disconnected(*address);
 
+            // Drop pending pairing request of device
+            if pairing.iter().any(|p| p.address == *address) {
+                pairing.retain(|p| p.address != *address);
+                events.push(Event::PairingDone(*address, false));
+            }
+
             events.push(Event::DeviceDisconnected(*address, devices.clone()));
         }
         _ => {}
@@ -198,38 +451,692 @@ fn process_bluetooth_event(
 
 #[inline]
 fn process_commands(
+    config: &Config,
     cmd_rx: &Receiver<DriverCmd>,
     pipe_event: &Pipe<Event>,
-    driver: &mut Driver,
-    devices: &mut DeviceList,
+    state: &mut State,
 ) {
+    let State {
+        driver,
+        devices,
+        store,
+        reconnect,
+        pairing,
+        discoverable_until,
+        ..
+    } = state;
+
     while let Ok(cmd) = cmd_rx.try_recv() {
         match cmd {
             DriverCmd::Discoverable(discoverable) => {
-                driver
-                    .set_discoverable(discoverable)
-                    .expect("failed to make bluetooth device discoverable");
-                let _ = pipe_event.send(Event::Discoverable(discoverable));
-            }
-            DriverCmd::EmitState => {
-                // Get state, update device list
-                let (info, connections) = driver
-                    .get_state()
-                    .expect("failed to make bluetooth device discoverable");
-                for (address, address_type) in connections {
-                    devices.process_device_connected(address, address_type, None);
-                }
-
-                // Emit events
-                let _ = pipe_event.send(Event::Power(
-                    info.current_settings.contains(ControllerSetting::Powered),
-                ));
-                let _ = pipe_event.send(Event::Discoverable(
-                    info.current_settings
-                        .contains(ControllerSetting::Discoverable),
-                ));
-                let _ = pipe_event.send(Event::Devices(devices.clone()));
+                if discoverable && !store.powered() {
+                    let _ =
+                        pipe_event.send(Event::CmdFailed(cmd, "bluetooth is turned off".into()));
+                    let _ = pipe_event.send(Event::Discoverable(false));
+                    continue;
+                }
+                match driver.set_discoverable(discoverable) {
+                    Ok(()) => {
+                        *discoverable_until = if discoverable {
+                            let timeout = u64::from(crate::BT_DISCOVER_TIMEOUT);
+                            Some(Instant::now() + Duration::from_secs(timeout))
+                        } else {
+                            None
+                        };
+                        let _ = pipe_event.send(Event::Discoverable(discoverable));
+                    }
+                    Err(err) => {
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err.to_string()));
+                        let _ = pipe_event.send(Event::Discoverable(false));
+                    }
+                }
+            }
+            DriverCmd::ExtendDiscoverable(extend) => {
+                let until = match discoverable_until {
+                    Some(until) => *until,
+                    None => {
+                        let err = "bluetooth is not discoverable".into();
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err));
+                        continue;
+                    }
+                };
+                let now = Instant::now();
+                let timeout = until.saturating_duration_since(now) + extend;
+                match driver.set_discoverable_timeout(Some(timeout)) {
+                    Ok(()) => *discoverable_until = Some(now + timeout),
+                    Err(err) => {
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err.to_string()));
+                    }
+                }
+            }
+            DriverCmd::Power(power) => match driver.set_powered(power) {
+                Ok(()) => {
+                    info!("Turned bluetooth {}", if power { "on" } else { "off" });
+                    store.set_powered(power);
+                    let _ = pipe_event.send(Event::Power(power));
+                    if power {
+                        start_reconnect(config, store, pipe_event, reconnect);
+                    } else {
+                        if let Some(reconnect) = reconnect.take() {
+                            reconnect.finish(pipe_event, None);
+                        }
+                        *discoverable_until = None;
+                        devices.process_power_off();
+                        let _ = pipe_event.send(Event::Discoverable(false));
+                        let _ = pipe_event.send(Event::Devices(devices.clone()));
+                    }
+                }
+                Err(err) => {
+                    let _ = pipe_event.send(Event::CmdFailed(cmd, err.to_string()));
+                }
+            },
+            DriverCmd::EmitState => emit_state(
+                config,
+                pipe_event,
+                driver,
+                devices,
+                store,
+                discoverable_until,
+            ),
+            DriverCmd::Trust(address) => {
+                let _ = pipe_event.send(trust(driver, store, address, true));
+                emit_known(pipe_event, devices, store);
+            }
+            DriverCmd::Untrust(address) => {
+                let _ = pipe_event.send(trust(driver, store, address, false));
+                emit_known(pipe_event, devices, store);
+            }
+            DriverCmd::Forget(address) => {
+                let _ = pipe_event.send(forget(driver, devices, store, address));
+                emit_known(pipe_event, devices, store);
+            }
+            DriverCmd::ListKnown => {
+                let _ = pipe_event.send(Event::Known(store.devices().to_vec()));
+            }
+            DriverCmd::Disconnect(address) => {
+                let address_type = address_type(devices, store, address);
+                match driver.disconnect_device(address, address_type) {
+                    Ok(()) => {
+                        devices.process_device_disconnected(address);
+                        let _ =
+                            pipe_event.send(Event::DeviceDisconnected(address, devices.clone()));
+                    }
+                    Err(err) => {
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err.to_string()));
+                    }
+                }
+            }
+            DriverCmd::Block(address) | DriverCmd::Unblock(address) => {
+                let block = cmd == DriverCmd::Block(address);
+                let address_type = address_type(devices, store, address);
+                let result = if block {
+                    driver.block_device(address, address_type)
+                } else {
+                    driver.unblock_device(address, address_type)
+                };
+                match result {
+                    Ok(()) => {
+                        info!(
+                            "{} bluetooth device: {}",
+                            if block { "Blocked" } else { "Unblocked" },
+                            util::address_hex(address)
+                        );
+                        store.update(address, address_type, |device| device.blocked = block);
+
+                        // Blocking does not drop an existing connection
+                        if block && devices.get(address).map(|d| d.connected).unwrap_or(false) {
+                            match driver.disconnect_device(address, address_type) {
+                                Ok(()) => devices.process_device_disconnected(address),
+                                Err(err) => warn!(
+                                    "Failed to disconnect blocked bluetooth device {}: {}",
+                                    util::address_hex(address),
+                                    err
+                                ),
+                            }
+                        }
+                        emit_known(pipe_event, devices, store);
+                    }
+                    Err(err) => {
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err.to_string()));
+                    }
+                }
+            }
+            DriverCmd::ConfirmPairing(address, accept) => {
+                let request = match pairing.iter().position(|p| p.address == address) {
+                    Some(index) => pairing.remove(index),
+                    None => {
+                        let err = "no pairing request for device".into();
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err));
+                        continue;
+                    }
+                };
+                match driver.confirm_pairing(address, request.address_type, accept) {
+                    Ok(()) => {
+                        let _ = pipe_event.send(Event::PairingDone(address, accept));
+                    }
+                    Err(err) => {
+                        let _ = pipe_event.send(Event::PairingDone(address, false));
+                        let _ = pipe_event.send(Event::CmdFailed(cmd, err.to_string()));
+                    }
+                }
+            }
+            DriverCmd::SetAlias(address, alias) => {
+                let address_type = address_type(devices, store, address);
+                let alias = alias
+                    .map(|alias| alias.trim().to_owned())
+                    .filter(|alias| !alias.is_empty());
+                store.update(address, address_type, |device| device.alias = alias);
+                emit_known(pipe_event, devices, store);
+            }
+            DriverCmd::SetIdentity(ref identity) => {
+                if let Err(err) = identity.validate() {
+                    let _ = pipe_event.send(Event::CmdFailed(cmd.clone(), err.to_string()));
+                    continue;
+                }
+
+                // Without controller, remember to apply once we have one
+                match driver.set_identity(identity) {
+                    Ok(()) | Err(DriverError::NoController) => {
+                        store.set_identity(identity.clone());
+                        let _ = pipe_event.send(Event::Identity(identity.clone()));
+                    }
+                    Err(err) => {
+                        let _ = pipe_event.send(Event::CmdFailed(cmd.clone(), err.to_string()));
+                    }
+                }
             }
         }
     }
 }
+
+/// Reject pairing requests past their deadline.
+fn expire_pairing(pipe_event: &Pipe<Event>, state: &mut State) {
+    let now = Instant::now();
+    let (expired, pending) = state.pairing.drain(..).partition(|p| p.deadline <= now);
+    state.pairing = pending;
+
+    for request in expired {
+        info!(
+            "Bluetooth pairing request timed out: {}",
+            util::address_hex(request.address)
+        );
+        if let Err(err) = state
+            .driver
+            .confirm_pairing(request.address, request.address_type, false)
+        {
+            error!("Failed to reject bluetooth pairing request: {}", err);
+        }
+        let _ = pipe_event.send(Event::PairingDone(request.address, false));
+    }
+}
+
+/// Poll controller state, and emit events for it.
+fn emit_state(
+    config: &Config,
+    pipe_event: &Pipe<Event>,
+    driver: &mut Driver,
+    devices: &mut DeviceList,
+    store: &DeviceStore,
+    discoverable_until: &mut Option<Instant>,
+) {
+    // Get state, update device list
+    let (powered, discoverable) = match driver.get_state() {
+        Ok((info, connections)) => {
+            for (address, address_type) in connections {
+                devices.process_device_connected(address, address_type, None);
+            }
+            (
+                info.current_settings.contains(ControllerSetting::Powered),
+                info.current_settings
+                    .contains(ControllerSetting::Discoverable),
+            )
+        }
+        Err(err) => {
+            if driver.controller().is_some() {
+                error!("Failed to get bluetooth controller state: {}", err);
+            }
+            (false, false)
+        }
+    };
+    devices.sync_known(store.devices());
+    if !discoverable {
+        *discoverable_until = None;
+    }
+
+    // Emit events
+    let _ = pipe_event.send(Event::Controller(driver.controller().is_some()));
+    let _ = pipe_event.send(Event::Power(powered));
+    let _ = pipe_event.send(Event::Discoverable(discoverable));
+    let _ = pipe_event.send(Event::Devices(devices.clone()));
+    let _ = pipe_event.send(Event::Identity(identity(config, store)));
+}
+
+/// Prepare the controller once it is attached.
+///
+/// Allows trusted devices to connect again, keeps blocked devices out, and starts reconnecting to
+/// recently connected devices.
+fn setup_controller(config: &Config, pipe_event: &Pipe<Event>, state: &mut State) {
+    let State {
+        driver,
+        store,
+        reconnect,
+        ..
+    } = state;
+
+    for device in store.devices().iter().filter(|d| d.trusted) {
+        if let Err(err) = driver.trust_device(device.address, device.address_type) {
+            error!(
+                "Failed to trust known bluetooth device {}: {}",
+                device.address_string(),
+                err
+            );
+        }
+    }
+    for device in store.devices().iter().filter(|d| d.blocked) {
+        if let Err(err) = driver.block_device(device.address, device.address_type) {
+            error!(
+                "Failed to block known bluetooth device {}: {}",
+                device.address_string(),
+                err
+            );
+        }
+    }
+
+    if store.powered() {
+        start_reconnect(config, store, pipe_event, reconnect);
+    }
+}
+
+/// Process controller added or removed events.
+///
+/// Returns `false` if the event is about another controller than the one we drive, and must be
+/// ignored.
+fn process_controller_event(
+    response: &bluez::interface::response::Response,
+    config: &Config,
+    pipe_event: &Pipe<Event>,
+    state: &mut State,
+) -> bool {
+    let attached = state.driver.controller();
+    match response.event {
+        BlueZEvent::IndexAdded if attached.is_none() => {
+            match state.driver.attach(
+                response.controller,
+                config,
+                &identity(config, &state.store),
+                state.store.powered(),
+            ) {
+                Ok(true) => {
+                    info!("Bluetooth controller {} added", response.controller);
+                    setup_controller(config, pipe_event, state);
+                    let State {
+                        driver,
+                        devices,
+                        store,
+                        discoverable_until,
+                        ..
+                    } = state;
+                    emit_state(
+                        config,
+                        pipe_event,
+                        driver,
+                        devices,
+                        store,
+                        discoverable_until,
+                    );
+                }
+                Ok(false) => {}
+                Err(err) => error!(
+                    "Failed to set up bluetooth controller {}: {}",
+                    response.controller, err
+                ),
+            }
+            false
+        }
+        BlueZEvent::IndexRemoved if attached == Some(response.controller) => {
+            warn!("Bluetooth controller {} removed", response.controller);
+            state.driver.detach();
+            if let Some(reconnect) = state.reconnect.take() {
+                reconnect.finish(pipe_event, None);
+            }
+            for request in state.pairing.drain(..) {
+                let _ = pipe_event.send(Event::PairingDone(request.address, false));
+            }
+            state.discoverable_until = None;
+            state.devices.process_power_off();
+
+            let _ = pipe_event.send(Event::Controller(false));
+            let _ = pipe_event.send(Event::Power(false));
+            let _ = pipe_event.send(Event::Discoverable(false));
+            let _ = pipe_event.send(Event::Devices(state.devices.clone()));
+            false
+        }
+        _ => attached == Some(response.controller),
+    }
+}
+
+/// Get identity to present the controller with.
+///
+/// Uses the identity changed at runtime, or the configured one. Falls back to the default if the
+/// configured identity is invalid.
+fn identity(config: &Config, store: &DeviceStore) -> Identity {
+    if let Some(identity) = store.identity() {
+        return identity.clone();
+    }
+    match config.identity.validate() {
+        Ok(()) => config.identity.clone(),
+        Err(err) => {
+            error!(
+                "Invalid bluetooth identity in config, using default: {}",
+                err
+            );
+            Identity::default()
+        }
+    }
+}
+
+/// Emit remaining discoverable time if it changed by a second.
+fn tick_discoverable(pipe_event: &Pipe<Event>, state: &mut State) {
+    let remaining = state.discoverable_until.map(|until| {
+        let remaining = until.saturating_duration_since(Instant::now());
+        remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+    });
+    if remaining == state.discoverable_remaining {
+        return;
+    }
+
+    state.discoverable_remaining = remaining;
+    if let Some(remaining) = remaining {
+        let _ = pipe_event.send(Event::DiscoverableRemaining(Duration::from_secs(remaining)));
+    }
+}
+
+/// Start reconnecting to recently connected devices if enabled, replaces running attempt.
+fn start_reconnect(
+    config: &Config,
+    store: &DeviceStore,
+    pipe_event: &Pipe<Event>,
+    reconnect: &mut Option<Reconnect>,
+) {
+    let ReconnectConfig {
+        enabled, devices, ..
+    } = config.reconnect;
+    let recent: Vec<Address> = store
+        .recent(devices)
+        .into_iter()
+        .map(|d| d.address)
+        .filter(|address| policy::allowed(&config.policy, *address))
+        .collect();
+    if !enabled || recent.is_empty() {
+        return;
+    }
+
+    if let Some(reconnect) = reconnect.take() {
+        reconnect.finish(pipe_event, None);
+    }
+    *reconnect = Some(Reconnect::start(
+        &config.reconnect,
+        recent,
+        pipe_event.clone(),
+    ));
+}
+
+/// Sync device list with known devices, and emit both.
+fn emit_known(pipe_event: &Pipe<Event>, devices: &mut DeviceList, store: &DeviceStore) {
+    devices.sync_known(store.devices());
+    let _ = pipe_event.send(Event::Known(store.devices().to_vec()));
+    let _ = pipe_event.send(Event::Devices(devices.clone()));
+}
+
+/// Trust or untrust device, returns event describing the result.
+fn trust(
+    control: &mut impl DeviceControl,
+    store: &mut DeviceStore,
+    address: Address,
+    trust: bool,
+) -> Event {
+    let address_type = store
+        .get(address)
+        .map(|d| d.address_type)
+        .unwrap_or(AddressType::BREDR);
+    let result = if trust {
+        control.trust_device(address, address_type)
+    } else {
+        control.untrust_device(address, address_type)
+    };
+
+    match result {
+        Ok(()) => {
+            info!(
+                "{} bluetooth device: {}",
+                if trust { "Trusted" } else { "Untrusted" },
+                util::address_hex(address)
+            );
+            store.update(address, address_type, |device| device.trusted = trust);
+            Event::Trusted(address, trust)
+        }
+        Err(err) => {
+            let cmd = if trust {
+                DriverCmd::Trust(address)
+            } else {
+                DriverCmd::Untrust(address)
+            };
+            Event::CmdFailed(cmd, err.to_string())
+        }
+    }
+}
+
+/// Remove pairing with device and forget it, returns event describing the result.
+fn forget(
+    control: &mut impl DeviceControl,
+    devices: &DeviceList,
+    store: &mut DeviceStore,
+    address: Address,
+) -> Event {
+    let address_type = address_type(devices, store, address);
+    match control.forget_device(address, address_type) {
+        Ok(()) => {
+            info!("Forgot bluetooth device: {}", util::address_hex(address));
+            store.remove(address);
+            Event::Forgotten(address)
+        }
+        Err(err) => Event::CmdFailed(DriverCmd::Forget(address), err.to_string()),
+    }
+}
+
+/// Get address type of device, assumes BR/EDR if unknown.
+fn address_type(devices: &DeviceList, store: &DeviceStore, address: Address) -> AddressType {
+    store
+        .get(address)
+        .map(|d| d.address_type)
+        .or_else(|| devices.get(address).map(|d| d.address_type))
+        .unwrap_or(AddressType::BREDR)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [1, 2, 3, 4, 5, 6];
+
+    /// Fake controller, tracks trusted and paired devices.
+    #[derive(Default)]
+    struct FakeControl {
+        trusted: Vec<Address>,
+        paired: Vec<Address>,
+
+        /// Fail all commands.
+        fail: bool,
+    }
+
+    impl FakeControl {
+        fn result(&self) -> Result<(), DriverError> {
+            if self.fail {
+                Err(DriverError::NoController)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl DeviceControl for FakeControl {
+        fn trust_device(&mut self, address: Address, _: AddressType) -> Result<(), DriverError> {
+            self.result()?;
+            self.trusted.push(address);
+            Ok(())
+        }
+
+        fn untrust_device(&mut self, address: Address, _: AddressType) -> Result<(), DriverError> {
+            self.result()?;
+            self.trusted.retain(|a| *a != address);
+            Ok(())
+        }
+
+        fn forget_device(&mut self, address: Address, _: AddressType) -> Result<(), DriverError> {
+            self.result()?;
+            self.trusted.retain(|a| *a != address);
+            self.paired.retain(|a| *a != address);
+            Ok(())
+        }
+
+        fn disconnect_device(&mut self, _: Address, _: AddressType) -> Result<(), DriverError> {
+            self.result()
+        }
+
+        fn block_device(&mut self, _: Address, _: AddressType) -> Result<(), DriverError> {
+            self.result()
+        }
+
+        fn unblock_device(&mut self, _: Address, _: AddressType) -> Result<(), DriverError> {
+            self.result()
+        }
+    }
+
+    /// Store file unique to the test, removed when dropped.
+    struct TempStore(PathBuf);
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "pokoebox-bluetooth-{}-{}.toml",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+
+        fn load(&self) -> DeviceStore {
+            DeviceStore::load(Some(self.0.clone()))
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn address() -> Address {
+        Address::from(ADDRESS)
+    }
+
+    #[test]
+    fn trust_persists() {
+        let file = TempStore::new("trust");
+        let mut control = FakeControl::default();
+        let mut store = file.load();
+
+        let event = trust(&mut control, &mut store, address(), true);
+        assert!(matches!(event, Event::Trusted(a, true) if a == address()));
+        assert!(control.trusted.contains(&address()));
+        assert!(file.load().get(address()).unwrap().trusted);
+    }
+
+    #[test]
+    fn untrust_persists() {
+        let file = TempStore::new("untrust");
+        let mut control = FakeControl::default();
+        let mut store = file.load();
+        trust(&mut control, &mut store, address(), true);
+
+        let event = trust(&mut control, &mut store, address(), false);
+        assert!(matches!(event, Event::Trusted(a, false) if a == address()));
+        assert!(!control.trusted.contains(&address()));
+        assert!(!file.load().get(address()).unwrap().trusted);
+    }
+
+    #[test]
+    fn trust_failure_keeps_store() {
+        let file = TempStore::new("trust-failure");
+        let mut control = FakeControl::default();
+        let mut store = file.load();
+        trust(&mut control, &mut store, address(), true);
+
+        control.fail = true;
+        let event = trust(&mut control, &mut store, address(), false);
+        assert!(matches!(event, Event::CmdFailed(DriverCmd::Untrust(a), _) if a == address()));
+        assert!(file.load().get(address()).unwrap().trusted);
+    }
+
+    #[test]
+    fn forget_removes_device() {
+        let file = TempStore::new("forget");
+        let mut control = FakeControl::default();
+        let mut store = file.load();
+        store.update(address(), AddressType::BREDR, |device| device.paired = true);
+        control.paired.push(address());
+        trust(&mut control, &mut store, address(), true);
+
+        let event = forget(&mut control, &DeviceList::default(), &mut store, address());
+        assert!(matches!(event, Event::Forgotten(a) if a == address()));
+        assert!(!control.trusted.contains(&address()));
+        assert!(!control.paired.contains(&address()));
+        assert!(store.get(address()).is_none());
+        assert!(file.load().get(address()).is_none());
+    }
+
+    #[test]
+    fn forget_failure_keeps_device() {
+        let file = TempStore::new("forget-failure");
+        let mut control = FakeControl::default();
+        let mut store = file.load();
+        trust(&mut control, &mut store, address(), true);
+
+        control.fail = true;
+        let event = forget(&mut control, &DeviceList::default(), &mut store, address());
+        assert!(matches!(event, Event::CmdFailed(DriverCmd::Forget(a), _) if a == address()));
+        assert!(file.load().get(address()).is_some());
+    }
+
+    #[test]
+    fn store_persists_known_devices() {
+        let file = TempStore::new("persist");
+        let mut store = file.load();
+        store.set_powered(false);
+        store.update(address(), AddressType::LEPublic, |device| {
+            device.name = Some("Phone".into());
+            device.alias = Some("My phone".into());
+            device.paired = true;
+            device.blocked = true;
+            device.class = Some(0x5A_020C);
+            device.last_connected = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        });
+
+        let store = file.load();
+        assert!(!store.powered());
+        let device = store.get(address()).unwrap();
+        assert_eq!(device.address_type, AddressType::LEPublic);
+        assert_eq!(device.name.as_deref(), Some("Phone"));
+        assert_eq!(device.alias.as_deref(), Some("My phone"));
+        assert!(device.paired && device.blocked && !device.trusted);
+        assert_eq!(device.class, Some(0x5A_020C));
+        assert_eq!(
+            device.last_connected,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Management API commands that `bluez` 0.1.2 sends incorrectly.
+//!
+//! Each command opens its own management socket, and blocks until the kernel replies.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::FromRawFd;
+use std::time::Duration;
+
+use bluez::client::AddressType;
+use bluez::Address;
+
+/// Remove Device command opcode.
+const OP_REMOVE_DEVICE: u16 = 0x0034;
+
+/// Command Complete event code.
+const EV_CMD_COMPLETE: u16 = 0x0001;
+
+/// Command Status event code.
+const EV_CMD_STATUS: u16 = 0x0002;
+
+/// Invalid Parameters status, returned when removing a device that wasn't added.
+pub(crate) const STATUS_INVALID_PARAMS: u8 = 0x0D;
+
+const BTPROTO_HCI: libc::c_int = 1;
+const HCI_DEV_NONE: u16 = 0xFFFF;
+const HCI_CHANNEL_CONTROL: u16 = 3;
+
+/// Time to wait for the kernel to reply to a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[repr(C)]
+struct SockAddrHci {
+    hci_family: libc::sa_family_t,
+    hci_dev: u16,
+    hci_channel: u16,
+}
+
+/// Remove device from the list of devices allowed to connect, see `Driver::trust_device`.
+pub(crate) fn remove_device(
+    controller: u16,
+    address: Address,
+    address_type: AddressType,
+) -> Result<(), Error> {
+    let address: [u8; 6] = address.into();
+    let mut params = address.to_vec();
+    params.push(address_type as u8);
+    command(controller, OP_REMOVE_DEVICE, &params)
+}
+
+/// Send command to controller, and wait for it to complete.
+fn command(controller: u16, opcode: u16, params: &[u8]) -> Result<(), Error> {
+    let mut socket = open().map_err(Error::Io)?;
+
+    let mut packet = Vec::with_capacity(6 + params.len());
+    packet.extend_from_slice(&opcode.to_le_bytes());
+    packet.extend_from_slice(&controller.to_le_bytes());
+    packet.extend_from_slice(&(params.len() as u16).to_le_bytes());
+    packet.extend_from_slice(params);
+    socket.write_all(&packet).map_err(Error::Io)?;
+
+    // Skip events until we get the reply for our command
+    let mut buf = [0u8; 1024];
+    loop {
+        let len = match socket.read(&mut buf) {
+            Ok(len) => len,
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Err(Error::TimedOut)
+            }
+            Err(err) => return Err(Error::Io(err)),
+        };
+        if let Some(status) = reply_status(&buf[..len], controller, opcode) {
+            return match status {
+                0 => Ok(()),
+                status => Err(Error::Status(status)),
+            };
+        }
+    }
+}
+
+/// Get status from command reply packet, `None` if the packet isn't a reply to the command.
+fn reply_status(packet: &[u8], controller: u16, opcode: u16) -> Option<u8> {
+    if packet.len() < 9 {
+        return None;
+    }
+    let word = |i: usize| u16::from_le_bytes([packet[i], packet[i + 1]]);
+    let event = word(0);
+    if (event != EV_CMD_COMPLETE && event != EV_CMD_STATUS)
+        || word(2) != controller
+        || word(6) != opcode
+    {
+        return None;
+    }
+    Some(packet[8])
+}
+
+/// Open management socket, with a read timeout.
+fn open() -> io::Result<File> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_BLUETOOTH,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            BTPROTO_HCI,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Closes socket when dropped, also on errors below
+    let socket = unsafe { File::from_raw_fd(fd) };
+
+    let addr = SockAddrHci {
+        hci_family: libc::AF_BLUETOOTH as libc::sa_family_t,
+        hci_dev: HCI_DEV_NONE,
+        hci_channel: HCI_CHANNEL_CONTROL,
+    };
+    let result = unsafe {
+        libc::bind(
+            fd,
+            &addr as *const SockAddrHci as *const libc::sockaddr,
+            mem::size_of::<SockAddrHci>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let timeout = libc::timeval {
+        tv_sec: REPLY_TIMEOUT.as_secs() as libc::time_t,
+        tv_usec: 0,
+    };
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &timeout as *const libc::timeval as *const libc::c_void,
+            mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(socket)
+}
+
+/// Management command error.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to communicate over management socket.
+    Io(io::Error),
+
+    /// Kernel didn't reply in time.
+    TimedOut,
+
+    /// Command failed with management status code.
+    Status(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "management socket error: {}", err),
+            Error::TimedOut => write!(f, "management command timed out"),
+            Error::Status(status) => {
+                write!(f, "management command failed with status 0x{:02X}", status)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_for_command() {
+        // Command complete for remove device on hci0, invalid parameters
+        let packet = [0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x34, 0x00, 0x0D];
+        assert_eq!(
+            reply_status(&packet, 0, OP_REMOVE_DEVICE),
+            Some(STATUS_INVALID_PARAMS)
+        );
+    }
+
+    #[test]
+    fn skips_other_packets() {
+        // Other controller, other opcode, other event, truncated
+        let packet = [0x01, 0x00, 0x01, 0x00, 0x03, 0x00, 0x34, 0x00, 0x00];
+        assert_eq!(reply_status(&packet, 0, OP_REMOVE_DEVICE), None);
+        let packet = [0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x33, 0x00, 0x00];
+        assert_eq!(reply_status(&packet, 0, OP_REMOVE_DEVICE), None);
+        let packet = [0x1B, 0x00, 0x00, 0x00, 0x03, 0x00, 0x34, 0x00, 0x00];
+        assert_eq!(reply_status(&packet, 0, OP_REMOVE_DEVICE), None);
+        assert_eq!(reply_status(&[0x01, 0x00], 0, OP_REMOVE_DEVICE), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Connection policy, decides which devices may stay connected.
+
+use bluez::Address;
+
+use crate::config::{PolicyConfig, Takeover};
+use crate::device::{Device, DeviceList};
+use crate::store::DeviceStore;
+use crate::util;
+
+/// Reason a device was disconnected by the connection policy.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Reason {
+    /// Device is not on the allow list, or is on the deny list.
+    Denied,
+
+    /// Maximum number of devices is connected.
+    Full,
+
+    /// Replaced by a device that connected later.
+    Replaced,
+}
+
+/// Policy decision for a newly connected device.
+pub(crate) enum Decision {
+    /// Keep device connected.
+    Accept,
+
+    /// Disconnect the new device.
+    Reject(Reason),
+
+    /// Keep device connected, disconnect the given devices to make room.
+    Replace(Vec<Address>),
+}
+
+/// Whether a device is allowed to connect at all by the allow and deny lists.
+pub(crate) fn allowed(config: &PolicyConfig, address: Address) -> bool {
+    let listed = |list: &[String]| {
+        list.iter()
+            .filter_map(|entry| util::parse_address(entry))
+            .any(|entry| entry == address)
+    };
+
+    if listed(&config.deny) {
+        return false;
+    }
+    config
+        .allow
+        .as_ref()
+        .map(|allow| listed(allow))
+        .unwrap_or(true)
+}
+
+/// Decide what to do with a newly connected device.
+///
+/// Only audio sources count towards the maximum number of devices, other devices such as remotes
+/// and keyboards are always accepted if allowed. `devices` must not yet include the new device as
+/// connected.
+pub(crate) fn decide(
+    config: &PolicyConfig,
+    device: &Device,
+    devices: &DeviceList,
+    store: &DeviceStore,
+) -> Decision {
+    if !allowed(config, device.address) {
+        return Decision::Reject(Reason::Denied);
+    }
+
+    let max = match config.max_devices {
+        Some(max) if device.audio_source() => max,
+        _ => return Decision::Accept,
+    };
+    let mut connected: Vec<Address> = devices
+        .iter()
+        .filter(|d| d.connected && d.address != device.address && d.audio_source())
+        .map(|d| d.address)
+        .collect();
+    if connected.len() < max {
+        return Decision::Accept;
+    }
+
+    match config.takeover {
+        Takeover::RejectNew => Decision::Reject(Reason::Full),
+        Takeover::KickOldest if max == 0 => Decision::Reject(Reason::Full),
+        Takeover::KickOldest => {
+            // Disconnect devices that connected first, until there is room
+            connected.sort_by_key(|address| store.get(*address).and_then(|d| d.last_connected));
+            connected.truncate(connected.len() + 1 - max);
+            Decision::Replace(connected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bluez::client::AddressType;
+
+    use super::*;
+    use crate::eir::Uuid;
+
+    const PHONE: [u8; 6] = [1, 0, 0, 0, 0, 0];
+    const KEYBOARD: [u8; 6] = [2, 0, 0, 0, 0, 0];
+    const NEW_PHONE: [u8; 6] = [3, 0, 0, 0, 0, 0];
+
+    /// Policy allowing a single audio source, kicking the oldest.
+    fn config() -> PolicyConfig {
+        PolicyConfig {
+            max_devices: Some(1),
+            ..PolicyConfig::default()
+        }
+    }
+
+    fn phone(address: [u8; 6]) -> Device {
+        let mut device = Device::from_address(Address::from(address), AddressType::BREDR);
+        device.services = vec![Uuid::AUDIO_SOURCE];
+        device
+    }
+
+    fn keyboard() -> Device {
+        let mut device = Device::from_address(Address::from(KEYBOARD), AddressType::BREDR);
+        // Peripheral major class, keyboard
+        device.class = Some(0x00_0540);
+        device
+    }
+
+    fn connected(list: &[Device]) -> DeviceList {
+        let mut devices = DeviceList::default();
+        for device in list {
+            devices.process_device_connected(device.address, device.address_type, None);
+            *devices.get_mut(device.address).unwrap() = device.clone();
+        }
+        devices
+    }
+
+    #[test]
+    fn kicks_audio_source_not_keyboard() {
+        let devices = connected(&[keyboard(), phone(PHONE)]);
+        let store = DeviceStore::load(None);
+        match decide(&config(), &phone(NEW_PHONE), &devices, &store) {
+            Decision::Replace(replaced) => assert_eq!(replaced, vec![Address::from(PHONE)]),
+            _ => panic!("expected old phone to be replaced"),
+        }
+    }
+
+    #[test]
+    fn keyboard_does_not_fill() {
+        let devices = connected(&[keyboard()]);
+        let store = DeviceStore::load(None);
+        assert!(matches!(
+            decide(&config(), &phone(PHONE), &devices, &store),
+            Decision::Accept
+        ));
+    }
+
+    #[test]
+    fn accepts_non_audio_when_full() {
+        let devices = connected(&[phone(PHONE)]);
+        let store = DeviceStore::load(None);
+        let remote = Device::from_address(Address::from(KEYBOARD), AddressType::LERandom);
+        assert!(matches!(
+            decide(&config(), &remote, &devices, &store),
+            Decision::Accept
+        ));
+    }
+
+    #[test]
+    fn rejects_new_when_full() {
+        let devices = connected(&[phone(PHONE)]);
+        let store = DeviceStore::load(None);
+        let config = PolicyConfig {
+            takeover: Takeover::RejectNew,
+            ..config()
+        };
+        assert!(matches!(
+            decide(&config, &phone(NEW_PHONE), &devices, &store),
+            Decision::Reject(Reason::Full)
+        ));
+    }
+
+    #[test]
+    fn denied() {
+        let config = PolicyConfig {
+            deny: vec![util::address_hex(Address::from(PHONE))],
+            ..config()
+        };
+        let store = DeviceStore::load(None);
+        assert!(matches!(
+            decide(&config, &phone(PHONE), &DeviceList::default(), &store),
+            Decision::Reject(Reason::Denied)
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+//! Reconnecting to recently connected devices.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use bluez::Address;
+use pokoebox_common::pipe::Pipe;
+
+use crate::config::ReconnectConfig;
+use crate::manager::Event;
+use crate::util;
+
+/// Handle to a running reconnect attempt, stops it when dropped.
+pub(crate) struct Reconnect {
+    stop: Sender<()>,
+
+    /// Whether `Event::ReconnectDone` was emitted.
+    done: Arc<AtomicBool>,
+}
+
+impl Reconnect {
+    /// Start reconnecting to given devices in order in a background thread.
+    ///
+    /// Each round tries all devices once, and stops when one connects. Waits between rounds
+    /// with exponential backoff. Progress is reported as `Event::Reconnecting` and
+    /// `Event::ReconnectDone`.
+    pub fn start(config: &ReconnectConfig, devices: Vec<Address>, pipe_event: Pipe<Event>) -> Self {
+        let (stop, stop_rx) = mpsc::channel();
+        let (attempts, mut backoff) = (config.attempts, Duration::from_secs(config.backoff));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let closure_done = done.clone();
+        thread::spawn(move || {
+            for attempt in 1..=attempts {
+                for address in &devices {
+                    if stop_rx.try_recv() != Err(mpsc::TryRecvError::Empty) {
+                        return;
+                    }
+
+                    let _ = pipe_event.send(Event::Reconnecting(*address, attempt));
+                    match util::connect_device(*address) {
+                        Ok(()) => {
+                            info!(
+                                "Reconnected bluetooth device: {}",
+                                util::address_hex(*address)
+                            );
+                            emit_done(&closure_done, &pipe_event, Some(*address));
+                            return;
+                        }
+                        Err(err) => debug!(
+                            "Failed to reconnect bluetooth device {}: {}",
+                            util::address_hex(*address),
+                            err
+                        ),
+                    }
+                }
+
+                // Wait before next round, stop early if requested
+                if attempt < attempts {
+                    match stop_rx.recv_timeout(backoff) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => return,
+                    }
+                    backoff *= 2;
+                }
+            }
+
+            info!("Failed to reconnect any recent bluetooth device");
+            emit_done(&closure_done, &pipe_event, None);
+        });
+
+        Self { stop, done }
+    }
+
+    /// Stop reconnecting, because given device connected or `None` if cancelled.
+    pub fn finish(self, pipe_event: &Pipe<Event>, address: Option<Address>) {
+        emit_done(&self.done, pipe_event, address);
+    }
+}
+
+impl Drop for Reconnect {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+    }
+}
+
+/// Emit `Event::ReconnectDone`, if not emitted before.
+fn emit_done(done: &AtomicBool, pipe_event: &Pipe<Event>, address: Option<Address>) {
+    if !done.swap(true, Ordering::SeqCst) {
+        let _ = pipe_event.send(Event::ReconnectDone(address));
+    }
+}
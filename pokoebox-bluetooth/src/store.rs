@@ -0,0 +1,289 @@
+//! Persistent store of known bluetooth devices and controller state.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bluez::client::AddressType;
+use bluez::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::identity::Identity;
+use crate::util;
+
+/// A known bluetooth device, that paired, was trusted or connected before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownDevice {
+    pub address: Address,
+    pub address_type: AddressType,
+    pub name: Option<String>,
+
+    /// Custom name set by the user.
+    pub alias: Option<String>,
+
+    /// Whether the device is paired, has a link key.
+    pub paired: bool,
+
+    /// Whether the device is trusted, allowed to connect.
+    pub trusted: bool,
+
+    /// Whether the device is blocked, rejected when connecting.
+    pub blocked: bool,
+
+    /// Class of device, 24 bits.
+    pub class: Option<u32>,
+
+    /// When the device last connected.
+    pub last_connected: Option<SystemTime>,
+}
+
+impl KnownDevice {
+    fn new(address: Address, address_type: AddressType) -> Self {
+        Self {
+            address,
+            address_type,
+            name: None,
+            alias: None,
+            paired: false,
+            trusted: false,
+            blocked: false,
+            class: None,
+            last_connected: None,
+        }
+    }
+
+    /// Get address as human readable hex string separated by `:`.
+    pub fn address_string(&self) -> String {
+        util::address_hex(self.address)
+    }
+}
+
+/// Store of known devices and controller state, saved to file on each change.
+pub(crate) struct DeviceStore {
+    /// File to save to, not saved if `None`.
+    path: Option<PathBuf>,
+    devices: Vec<KnownDevice>,
+
+    /// Whether the controller should be powered, `false` if bluetooth was turned off.
+    powered: bool,
+
+    /// Identity changed at runtime, overrides the configured identity.
+    identity: Option<Identity>,
+}
+
+impl DeviceStore {
+    /// Load store from given file, empty if it doesn't exist.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let stored = match &path {
+            Some(path) if path.is_file() => match fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| toml::from_str::<Stored>(&data).map_err(|err| err.to_string()))
+            {
+                Ok(stored) => stored,
+                Err(err) => {
+                    error!("Failed to load known bluetooth devices: {}", err);
+                    Stored::default()
+                }
+            },
+            _ => Stored::default(),
+        };
+
+        Self {
+            path,
+            devices: stored
+                .devices
+                .into_iter()
+                .filter_map(StoredDevice::into_device)
+                .collect(),
+            powered: stored.powered,
+            identity: stored.identity,
+        }
+    }
+
+    /// Save store to file.
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let stored = Stored {
+            powered: self.powered,
+            devices: self.devices.iter().map(StoredDevice::from_device).collect(),
+            identity: self.identity.clone(),
+        };
+        let result = toml::to_string(&stored)
+            .map_err(|err| err.to_string())
+            .and_then(|data| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+                }
+                fs::write(path, data).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            error!("Failed to save known bluetooth devices: {}", err);
+        }
+    }
+
+    /// Whether the controller should be powered.
+    pub fn powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Set whether the controller should be powered, and save.
+    pub fn set_powered(&mut self, powered: bool) {
+        self.powered = powered;
+        self.save();
+    }
+
+    /// Identity changed at runtime, if any.
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /// Set identity changed at runtime, and save.
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = Some(identity);
+        self.save();
+    }
+
+    /// Get all known devices.
+    pub fn devices(&self) -> &[KnownDevice] {
+        &self.devices
+    }
+
+    /// Get up to `count` most recently connected devices, most recent first.
+    ///
+    /// Blocked devices are skipped.
+    pub fn recent(&self, count: usize) -> Vec<&KnownDevice> {
+        let mut devices: Vec<&KnownDevice> = self
+            .devices
+            .iter()
+            .filter(|d| d.last_connected.is_some() && !d.blocked)
+            .collect();
+        devices.sort_by_key(|d| Reverse(d.last_connected));
+        devices.truncate(count);
+        devices
+    }
+
+    /// Get known device by address.
+    pub fn get(&self, address: Address) -> Option<&KnownDevice> {
+        self.devices.iter().find(|d| d.address == address)
+    }
+
+    /// Update known device, added if unknown, and save.
+    pub fn update<F>(&mut self, address: Address, address_type: AddressType, f: F)
+    where
+        F: FnOnce(&mut KnownDevice),
+    {
+        let index = match self.devices.iter().position(|d| d.address == address) {
+            Some(index) => index,
+            None => {
+                self.devices.push(KnownDevice::new(address, address_type));
+                self.devices.len() - 1
+            }
+        };
+        let device = &mut self.devices[index];
+        device.address_type = address_type;
+        f(device);
+        self.save();
+    }
+
+    /// Remove known device, and save.
+    pub fn remove(&mut self, address: Address) {
+        self.devices.retain(|d| d.address != address);
+        self.save();
+    }
+}
+
+/// Store file contents.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Stored {
+    powered: bool,
+    devices: Vec<StoredDevice>,
+
+    /// Must be last, tables can't be followed by plain values.
+    identity: Option<Identity>,
+}
+
+impl Default for Stored {
+    fn default() -> Self {
+        Self {
+            powered: true,
+            devices: Vec::new(),
+            identity: None,
+        }
+    }
+}
+
+/// Known device as stored in file.
+#[derive(Serialize, Deserialize)]
+struct StoredDevice {
+    address: String,
+    address_type: u8,
+    name: Option<String>,
+    alias: Option<String>,
+    #[serde(default)]
+    paired: bool,
+    #[serde(default)]
+    trusted: bool,
+    #[serde(default)]
+    blocked: bool,
+    class: Option<u32>,
+
+    /// Last connection time in seconds since the Unix epoch.
+    last_connected: Option<u64>,
+}
+
+impl StoredDevice {
+    fn from_device(device: &KnownDevice) -> Self {
+        Self {
+            address: device.address_string(),
+            address_type: device.address_type as u8,
+            name: device.name.clone(),
+            alias: device.alias.clone(),
+            paired: device.paired,
+            trusted: device.trusted,
+            blocked: device.blocked,
+            class: device.class,
+            last_connected: device
+                .last_connected
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs()),
+        }
+    }
+
+    fn into_device(self) -> Option<KnownDevice> {
+        let address = match util::parse_address(&self.address) {
+            Some(address) => address,
+            None => {
+                warn!(
+                    "Skipping known bluetooth device with invalid address: {}",
+                    self.address
+                );
+                return None;
+            }
+        };
+        let address_type = match self.address_type {
+            1 => AddressType::LEPublic,
+            2 => AddressType::LERandom,
+            _ => AddressType::BREDR,
+        };
+
+        Some(KnownDevice {
+            address,
+            address_type,
+            name: self.name,
+            alias: self.alias,
+            paired: self.paired,
+            trusted: self.trusted,
+            blocked: self.blocked,
+            class: self.class,
+            last_connected: self
+                .last_connected
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        })
+    }
+}
@@ -1,44 +1,53 @@
-use std::process::Command;
-use std::thread;
+use std::collections::HashMap;
 use std::time::Duration;
 
 use bluez::Address;
+use dbus::arg::{RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::ObjectManager;
+use dbus::blocking::Connection;
 
 use crate::eir;
 
-/// Mark a bluetooth device as trusted through the `bluetoothctl` command-line.
+/// Timeout for querying BlueZ over D-Bus.
+const DBUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timeout for connecting to a device over D-Bus, connecting may take a while.
+const DBUS_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Managed D-Bus objects, by path and interface.
+type ManagedObjects =
+    HashMap<dbus::Path<'static>, HashMap<String, HashMap<String, Variant<Box<dyn RefArg>>>>>;
+
+/// Connect to a bluetooth device through the BlueZ D-Bus API.
 ///
-/// TODO: find way to do this through bluez API.
-pub fn trust_device(address: Address) {
-    // Get hex address for device
-    let address_hex = address_hex(address);
-
-    info!("Trusting bluetooth device: {}", address_hex);
-
-    // Delay trusting for a second
-    thread::spawn(move || {
-        thread::sleep(Duration::from_secs(1));
-
-        // Add bluetooth device as trusted
-        match Command::new("bluetoothctl")
-            .arg("trust")
-            .arg(&address_hex)
-            .output()
-        {
-            Ok(output) if !output.status.success() => {
-                error!(
-                    "Failed to add bluetooth device as trusted, command had non-zero exit code ({}):\nstdout: {}\nstderr: {}",
-                    output.status.code().unwrap_or(-1),
-                    String::from_utf8(output.stdout).unwrap_or_else(|_| "?".into()),
-                    String::from_utf8(output.stderr).unwrap_or_else(|_| "?".into()),
-                );
-            }
-            Err(err) => {
-                error!("Failed to add bluetooth device as trusted: {:?}", err);
-            }
-            _ => {}
-        }
-    });
+/// This connects audio profiles as well, which the management API can't do. Blocks until the
+/// connection succeeds or fails.
+pub fn connect_device(address: Address) -> Result<(), String> {
+    let connection = Connection::new_system().map_err(|err| err.to_string())?;
+
+    // Find device object by address, on any adapter
+    let objects: ManagedObjects = connection
+        .with_proxy("org.bluez", "/", DBUS_TIMEOUT)
+        .get_managed_objects()
+        .map_err(|err| err.to_string())?;
+    let address = address_hex(address);
+    let path = objects
+        .into_iter()
+        .find(|(_, interfaces)| {
+            interfaces
+                .get("org.bluez.Device1")
+                .and_then(|props| props.get("Address"))
+                .and_then(|value| value.0.as_str())
+                .map(|value| value.eq_ignore_ascii_case(&address))
+                .unwrap_or(false)
+        })
+        .map(|(path, _)| path)
+        .ok_or_else(|| format!("device {} unknown to BlueZ", address))?;
+
+    connection
+        .with_proxy("org.bluez", path, DBUS_CONNECT_TIMEOUT)
+        .method_call("org.bluez.Device1", "Connect", ())
+        .map_err(|err| err.to_string())
 }
 
 /// Convert BlueZ address into hexadecimal representation with `:` separator.
@@ -52,6 +61,24 @@ pub fn address_hex(address: Address) -> String {
         .join(":")
 }
 
+/// Parse BlueZ address from hexadecimal representation with `:` separator.
+pub fn parse_address(address: &str) -> Option<Address> {
+    let parts: Vec<u8> = address
+        .split(':')
+        .map(|part| u8::from_str_radix(part, 16).ok())
+        .collect::<Option<_>>()?;
+    if parts.len() != 6 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 6];
+    bytes
+        .iter_mut()
+        .zip(parts.iter().rev())
+        .for_each(|(byte, part)| *byte = *part);
+    Some(Address::from(bytes))
+}
+
 /// Parse device name from EIR data.
 pub fn parse_device_name(eir_data: &[u8]) -> Option<String> {
     let name = eir::parse(eir_data).and_then(|data| data.name);
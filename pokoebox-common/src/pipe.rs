@@ -38,6 +38,15 @@ where
         Ok(receivers)
     }
 
+    /// Send item through pipe, drop it if there is no receiver.
+    ///
+    /// For frequent updates that are meaningless once outdated, so they aren't queued.
+    ///
+    /// Returns number of receivers this was passed onto.
+    pub fn send_or_drop(&self, item: T) -> Result<usize, Error> {
+        Ok(self.send_channels(item.clone())? + self.send_callbacks(item)?)
+    }
+
     fn send_channels(&self, item: T) -> Result<usize, Error> {
         let mut receivers = self
             .inner
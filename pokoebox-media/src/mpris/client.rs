@@ -56,6 +56,9 @@ struct InnerClient {
 
     /// Last time the MPRIS player list was refreshed.
     last_refresh: Instant,
+
+    /// Player volumes before scaling them with `Cmd::SetVolumeFactor`.
+    base_volumes: HashMap<PlayerHandle, f64>,
 }
 
 impl InnerClient {
@@ -68,6 +71,7 @@ impl InnerClient {
             mpris_players: HashMap::new(),
             players: HashMap::new(),
             last_refresh: Instant::now(),
+            base_volumes: HashMap::new(),
         }
     }
 
@@ -174,6 +178,7 @@ impl InnerClient {
 
                     self.mpris_players.remove(&handle);
                     self.players.remove(&handle);
+                    self.base_volumes.remove(&handle);
                 }
 
                 // Emit last list of players
@@ -222,6 +227,41 @@ impl InnerClient {
                     }
                 }
             }
+            Cmd::SetVolumeFactor(factor) => self.set_volume_factor(factor),
+        }
+    }
+
+    /// Scale volume of all players relative to their base volume.
+    ///
+    /// The base volume is remembered when first scaling a player, and is restored and forgotten
+    /// when the factor is `1.0` or more.
+    fn set_volume_factor(&mut self, factor: f64) {
+        let restore = factor >= 1.0;
+        for (handle, player) in self.mpris_players.iter() {
+            let base = match self.base_volumes.get(handle) {
+                Some(base) => *base,
+                None if restore => continue,
+                None => match player.player.get_volume() {
+                    Ok(volume) => *self.base_volumes.entry(handle.clone()).or_insert(volume),
+                    Err(err) => {
+                        error!("Failed to get MPRIS player volume: {:?}", err);
+                        continue;
+                    }
+                },
+            };
+
+            let volume = if restore {
+                base
+            } else {
+                base * factor.max(0.0)
+            };
+            if let Err(err) = player.player.set_volume(volume) {
+                error!("Failed to set MPRIS player volume: {:?}", err);
+            }
+        }
+
+        if restore {
+            self.base_volumes.clear();
         }
     }
 
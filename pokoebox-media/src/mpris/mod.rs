@@ -50,4 +50,9 @@ pub enum Cmd {
     /// Previous on current player.
     // TODO: link operation to specific player
     Previous,
+
+    /// Scale volume of all players relative to their volume before scaling, `1.0` restores it.
+    ///
+    /// Used to temporarily lower music, such as while a sound effect plays.
+    SetVolumeFactor(f64),
 }
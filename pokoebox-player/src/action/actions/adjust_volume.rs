@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use pokoebox_audio::volume::Cmd as VolumeCmd;
+use pokoebox_audio::volume::{Cmd as VolumeCmd, Role};
 
 use crate::action::prelude::*;
 use crate::app::Core;
@@ -13,20 +13,23 @@ pub const ACTION_NAME: &str = "Adjust volume";
 /// Defualt step size.
 const STEP_SIZE: i64 = 3;
 
-/// Go to page action.
-pub struct AdjustVolume(i64);
+/// Adjust volume action, for the control assigned to a role.
+pub struct AdjustVolume {
+    role: Role,
+    amount: i64,
+}
 
 impl AdjustVolume {
-    pub fn new(amount: i64) -> Self {
-        Self(amount)
+    pub fn new(role: Role, amount: i64) -> Self {
+        Self { role, amount }
     }
 
     pub fn up() -> Self {
-        Self::new(STEP_SIZE)
+        Self::new(Role::Master, STEP_SIZE)
     }
 
     pub fn down() -> Self {
-        Self::new(-STEP_SIZE)
+        Self::new(Role::Master, -STEP_SIZE)
     }
 }
 
@@ -36,12 +39,16 @@ impl Action for AdjustVolume {
     }
 
     fn invoke(&self, core: Arc<Core>) -> Result<bool> {
-        // Get master control
-        let control = core.volume.get_master_control().0.clone();
+        // Get control for role
+        let control = core
+            .volume
+            .get_role_control(self.role)
+            .ok_or_else(|| Error::new(format!("No mixer control for {} role", self.role)))?
+            .0;
 
         // Adjust volume, report errors
         core.volume
-            .send_cmd(VolumeCmd::AdjustVolume(control, self.0))
+            .send_cmd(VolumeCmd::AdjustVolume(control, self.amount))
             .map(|_| true)
             .map_err(|err| Error::new(format!("Failed to adjust volume: {:?}", err,)))
     }
@@ -1,8 +1,10 @@
 pub mod adjust_volume;
 pub mod goto_page;
 pub mod nop;
+pub mod shutdown;
 
 // Re-export actions
 pub use adjust_volume::AdjustVolume;
 pub use goto_page::GotoPageAction;
 pub use nop::NopAction;
+pub use shutdown::ShutdownAction;
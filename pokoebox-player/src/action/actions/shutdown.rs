@@ -0,0 +1,25 @@
+use std::sync::Arc;
+use std::thread;
+
+use crate::action::prelude::*;
+use crate::app::Core;
+use crate::result::Result;
+use crate::sequence;
+
+/// Name of this action.
+pub const ACTION_NAME: &str = "Shutdown";
+
+/// Shutdown action, powers down audio output and exits the application.
+pub struct ShutdownAction;
+
+impl Action for ShutdownAction {
+    fn name(&self) -> &'static str {
+        ACTION_NAME
+    }
+
+    fn invoke(&self, core: Arc<Core>) -> Result<bool> {
+        // Shutdown sequence blocks, don't run it on the caller thread
+        thread::spawn(move || sequence::exit(&core));
+        Ok(true)
+    }
+}
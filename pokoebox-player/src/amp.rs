@@ -0,0 +1,222 @@
+//! Amplifier power control, sequenced with a mixer mute to prevent pops.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use pokoebox_audio::volume::{ControlHandle, Curve, Manager as VolumeManager, Role};
+#[cfg(feature = "rpi")]
+use pokoebox_rpi::output::{Interface as OutputInterface, Output, OutputConfig};
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::result::Result;
+
+/// Time to wait after muting before switching the amplifier off.
+pub(crate) const MUTE_DELAY: Duration = Duration::from_millis(50);
+
+/// Time for the amplifier to settle after switching it on, before unmuting.
+const SETTLE_DELAY: Duration = Duration::from_millis(250);
+
+/// Time to ramp master volume down or up, if the master control can't be muted.
+const MUTE_RAMP_DURATION: Duration = Duration::from_millis(50);
+
+/// Amplifier configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AmpConfig {
+    /// GPIO pin enabling the amplifier, amplifier is not controlled if not set.
+    pub pin: Option<u8>,
+
+    /// Whether the enable pin is active low.
+    pub active_low: bool,
+}
+
+/// Amplifier power control.
+pub struct Amp {
+    /// Output interface with amplifier enable output, if configured.
+    #[cfg(feature = "rpi")]
+    outputs: Option<OutputInterface>,
+
+    /// Whether the amplifier is powered.
+    powered: Mutex<bool>,
+
+    /// Master control and volume to restore, if muted by ramping its volume down.
+    ramped: Mutex<Option<(ControlHandle, i64)>>,
+}
+
+impl Amp {
+    /// Construct amplifier control, the amplifier starts powered off.
+    ///
+    /// Use the startup sequence to power it on without pops.
+    pub fn new(config: &AmpConfig) -> Result<Self> {
+        #[cfg(feature = "rpi")]
+        let outputs = match config.pin {
+            Some(pin) => {
+                let outputs = OutputInterface::new().map_err(|err| {
+                    Error::new(format!("Failed to initialize output interface: {:?}", err))
+                })?;
+                outputs
+                    .setup_output(
+                        Output::Amp,
+                        OutputConfig::Gpio {
+                            pin,
+                            active_low: config.active_low,
+                        },
+                        false,
+                    )
+                    .map_err(|err| {
+                        Error::new(format!("Failed to set up amplifier output: {:?}", err))
+                    })?;
+                Some(outputs)
+            }
+            None => None,
+        };
+        #[cfg(not(feature = "rpi"))]
+        {
+            if config.pin.is_some() {
+                return Err(Error::new(
+                    "Amplifier pin configured, but compiled without Raspberry Pi support",
+                ));
+            }
+        }
+
+        Ok(Self {
+            #[cfg(feature = "rpi")]
+            outputs,
+            powered: Mutex::new(false),
+            ramped: Mutex::new(None),
+        })
+    }
+
+    /// Whether the amplifier is powered.
+    pub fn is_powered(&self) -> bool {
+        *self.powered.lock().expect("failed to lock amplifier state")
+    }
+
+    /// Power the amplifier on or off.
+    ///
+    /// The master control is muted while switching to prevent pops, and is unmuted after powering
+    /// on. This blocks for a short while.
+    pub fn set_powered(&self, volume: &VolumeManager, power: bool) {
+        let mut powered = self.powered.lock().expect("failed to lock amplifier state");
+        if *powered == power {
+            return;
+        }
+
+        if power {
+            self.switch(&mut powered, true);
+            self.set_master_mute(volume, false);
+        } else {
+            self.set_master_mute(volume, true);
+            thread::sleep(MUTE_DELAY);
+            self.switch(&mut powered, false);
+        }
+    }
+
+    /// Switch the amplifier on or off, without touching the mixer.
+    ///
+    /// The caller must mute output first to prevent pops. Blocks until the amplifier has settled
+    /// when switching on.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut powered = self.powered.lock().expect("failed to lock amplifier state");
+        if *powered != enabled {
+            self.switch(&mut powered, enabled);
+        }
+    }
+
+    /// Switch amplifier enable output, wait for it to settle when switching on.
+    fn switch(&self, powered: &mut bool, enabled: bool) {
+        info!("Powering amplifier {}", if enabled { "on" } else { "off" });
+        self.output_set(enabled);
+        if enabled {
+            thread::sleep(SETTLE_DELAY);
+        }
+        *powered = enabled;
+    }
+
+    /// Mute or unmute the master control, this is blocking.
+    ///
+    /// If the master control has no mute switch, its volume is ramped down to its minimum instead,
+    /// and ramped back up to restore it when unmuting.
+    pub(crate) fn set_master_mute(&self, volume: &VolumeManager, mute: bool) {
+        let (control, props) = match volume.get_role_control(Role::Master) {
+            Some(control) => control,
+            None => {
+                warn!("No master control to mute amplifier with");
+                return;
+            }
+        };
+
+        let mut ramped = self.ramped.lock().expect("failed to lock amplifier state");
+        match volume.set_mute_blocking(&control, mute) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => {
+                error!("Failed to set master mute: {:?}", err);
+                return;
+            }
+        }
+
+        // No mute switch, ramp volume instead
+        if mute {
+            let min = match props.playback {
+                Some(playback) => playback.range.0,
+                None => {
+                    warn!("Master control can't be muted, and has no volume to ramp down");
+                    return;
+                }
+            };
+            let current = match volume.query_volume(&control) {
+                Ok(Some(current)) => current,
+                Ok(None) => {
+                    warn!("Mixer didn't report master volume in time");
+                    return;
+                }
+                Err(err) => {
+                    error!("Failed to query master volume: {:?}", err);
+                    return;
+                }
+            };
+            debug!("Master control can't be muted, ramping volume down");
+            if ramped.is_none() {
+                *ramped = Some((control.clone(), current));
+            }
+            ramp_volume(volume, &control, min);
+        } else if let Some((control, restore)) = ramped.take() {
+            debug!("Master control can't be unmuted, ramping volume up");
+            ramp_volume(volume, &control, restore);
+        }
+    }
+
+    /// Don't restore master volume when unmuting, if muted by ramping its volume down.
+    ///
+    /// Use this when restoring the volume some other way, such as by fading it in.
+    pub(crate) fn forget_master_volume(&self) {
+        self.ramped
+            .lock()
+            .expect("failed to lock amplifier state")
+            .take();
+    }
+
+    /// Set amplifier enable output.
+    fn output_set(&self, _active: bool) {
+        #[cfg(feature = "rpi")]
+        {
+            if let Some(outputs) = &self.outputs {
+                if let Err(err) = outputs.output_set(Output::Amp, _active) {
+                    error!("Failed to set amplifier enable output: {:?}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Ramp volume of control to target, blocking until done.
+fn ramp_volume(volume: &VolumeManager, control: &ControlHandle, target: i64) {
+    if let Err(err) =
+        volume.fade_volume_blocking(control, target, MUTE_RAMP_DURATION, Curve::Linear)
+    {
+        error!("Failed to ramp master volume: {:?}", err);
+    }
+}
@@ -1,7 +1,12 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use glib::clone;
-use pokoebox_audio::volume::Manager as VolumeManager;
+use pokoebox_audio::{
+    dsp::Dsp,
+    meter::Manager as MeterManager,
+    volume::{Event as VolumeEvent, Manager as VolumeManager, Role},
+};
 #[cfg(feature = "bluetooth")]
 use pokoebox_bluetooth::manager::Manager as BluetoothManager;
 use pokoebox_common::pipe::Pipe;
@@ -18,14 +23,37 @@ use crate::action::{
     actions::{AdjustVolume, GotoPageAction},
     ActionRuntime,
 };
-use crate::message::Message;
+use crate::amp::Amp;
+use crate::config::Config;
+use crate::duck;
+use crate::error::Error;
+use crate::message::{Confirm, Message};
 use crate::pages::PageType;
 use crate::result::Result;
+use crate::sampler::Sampler;
+use crate::sequence;
 use crate::soundeffecter::SoundEffecter;
+use crate::standby::Standby;
 use crate::ui::gtk::Ui;
 
 use super::pages::PageController;
 
+/// GPIO pins of the action buttons, in order.
+#[cfg(feature = "rpi")]
+const ACTION_BUTTON_PINS: [u8; crate::sampler::ACTION_BUTTONS] = [17, 27, 5, 6];
+
+/// LEDs to show output level on, with the level in dB to light them at.
+#[cfg(feature = "rpi")]
+const VU_LED_THRESHOLDS: [(Led, f32); 3] = [
+    (Led::Action1, -36.0),
+    (Led::Action2, -18.0),
+    (Led::Action3, -6.0),
+];
+
+/// Seconds of discoverability left at which bluetooth LEDs start blinking.
+#[cfg(all(feature = "rpi", feature = "bluetooth"))]
+const DISCOVERABLE_BLINK_SECS: u64 = 10;
+
 pub struct App {
     ui: Ui,
     pub core: Arc<Core>,
@@ -33,11 +61,22 @@ pub struct App {
 
 impl App {
     pub fn new() -> Result<Self> {
-        // Init app core
-        let core = Arc::new(Core::new()?);
+        // Load config, init app core
+        let core = Arc::new(Core::new(Config::load())?);
 
         #[cfg(feature = "rpi")]
         Core::setup_buttons(core.clone()).expect("Failed to set-up app buttons");
+        Standby::setup(core.clone());
+        Core::setup_dsp(core.clone());
+        #[cfg(feature = "bluetooth")]
+        Core::setup_pairing(core.clone());
+        duck::setup(core.clone());
+
+        // Power down output on termination, power up output in background
+        if let Err(err) = sequence::handle_signals(core.clone()) {
+            error!("Failed to set up signal handler: {:?}", err);
+        }
+        thread::spawn(clone!(@strong core => move || sequence::startup(&core)));
 
         let app = Self {
             ui: Ui::new(core.clone())?,
@@ -56,9 +95,15 @@ impl App {
 }
 
 pub struct Core {
+    /// Application configuration.
+    pub config: Config,
+
     /// Messages pipe.
     pub messages: Pipe<Message>,
 
+    /// Question waiting for an answer, may be answered with a hardware button.
+    confirm: Mutex<Option<Confirm>>,
+
     /// Action manager
     pub actions: ActionRuntime,
 
@@ -68,6 +113,12 @@ pub struct Core {
     /// Volume manager.
     pub volume: VolumeManager,
 
+    /// Output level meter.
+    pub meter: MeterManager,
+
+    /// DSP for audio rendered by PokoeBox.
+    pub dsp: Dsp,
+
     /// MPRIS manager.
     pub mpris: MprisManager,
 
@@ -90,25 +141,52 @@ pub struct Core {
     /// Sound effecter.
     pub effecter: SoundEffecter,
 
+    /// Amplifier power control.
+    pub amp: Amp,
+
+    /// Auto-standby controller.
+    pub standby: Standby,
+
+    /// Sampler looping recorded soundboard patterns.
+    pub sampler: Sampler,
+
     pub pages: PageController,
 }
 
 impl Core {
     /// Construct a new core.
-    pub fn new() -> Result<Self> {
+    pub fn new(config: Config) -> Result<Self> {
         // Construct RPi base to share resources
         #[cfg(feature = "rpi")]
         let mut rpi = Rpi::default();
 
+        let volume = VolumeManager::new(&config.volume)
+            .map_err(|err| Error::new(format!("Failed to initialize volume manager: {}", err)))?;
+        let soft_volume = volume.mixer.soft_volume.clone();
+        let meter = MeterManager::new(&config.meter);
+        let tap = meter.tap();
+        let dsp = Dsp::new(&config.dsp);
+        let amp = Amp::new(&config.amp)?;
+        let standby = Standby::new(config.standby.clone());
+        let max_voices = config.soundboard.max_voices;
+        let sampler = Sampler::new(config.sampler.clone());
+        #[cfg(feature = "bluetooth")]
+        let bluetooth_config = config.bluetooth_config();
+
         Ok(Self {
+            config,
             messages: Pipe::default(),
+            confirm: Mutex::new(None),
             actions: ActionRuntime::default(),
             player: Player::default(),
-            volume: VolumeManager::new(),
+            volume,
+            meter,
+            dsp: dsp.clone(),
             mpris: MprisManager::new(),
             // TODO: propagate error
             #[cfg(feature = "bluetooth")]
-            bluetooth: BluetoothManager::new().expect("failed to initialize bluetooth manager"),
+            bluetooth: BluetoothManager::new(bluetooth_config)
+                .expect("failed to initialize bluetooth manager"),
             // TODO: propagate error
             #[cfg(feature = "rpi")]
             leds: LedInterface::new(&mut rpi).expect("failed to initialize LED interface"),
@@ -119,7 +197,11 @@ impl Core {
             #[cfg(feature = "rpi")]
             power: PowerInterface::new(&mut rpi).expect("failed to initialize power interface"),
             // TODO: propagate error
-            effecter: SoundEffecter::new().expect("failed to initialize sound effecter"),
+            effecter: SoundEffecter::new(soft_volume, dsp, tap, max_voices)
+                .expect("failed to initialize sound effecter"),
+            amp,
+            standby,
+            sampler,
             pages: PageController::new(),
         })
     }
@@ -128,8 +210,11 @@ impl Core {
     fn setup_buttons(core: Arc<Core>) -> std::result::Result<(), pokoebox_rpi::button::Error> {
         // Set up buttons
         core.buttons.setup_button(
-            ButtonConfig::Push(17),
+            ButtonConfig::Push(ACTION_BUTTON_PINS[0]),
             clone!(@weak core => move |_| {
+                if core.sampler.trigger(&core, 0) {
+                    return;
+                }
                 if let Err(err) = core
                     .mpris
                     .send_cmd(pokoebox_media::mpris::Cmd::PlayPause)
@@ -143,8 +228,11 @@ impl Core {
         )?;
 
         core.buttons.setup_button(
-            ButtonConfig::Push(27),
+            ButtonConfig::Push(ACTION_BUTTON_PINS[1]),
             clone!(@weak core => move |_| {
+                if core.sampler.trigger(&core, 1) {
+                    return;
+                }
                 if let Err(err) = core
                     .mpris
                     .send_cmd(pokoebox_media::mpris::Cmd::Next)
@@ -155,8 +243,11 @@ impl Core {
         )?;
 
         core.buttons.setup_button(
-            ButtonConfig::Push(5),
+            ButtonConfig::Push(ACTION_BUTTON_PINS[2]),
             clone!(@weak core => move |_| {
+                if core.sampler.trigger(&core, 2) {
+                    return;
+                }
                 core.actions.invoke(
                     GotoPageAction::new(PageType::Launchpad),
                     core.clone(),
@@ -164,21 +255,29 @@ impl Core {
             }),
         )?;
 
+        core.buttons.setup_button(
+            ButtonConfig::Push(ACTION_BUTTON_PINS[3]),
+            clone!(@weak core => move |_| {
+                if core.sampler.trigger(&core, 3) {
+                    return;
+                }
+                #[cfg(feature = "bluetooth")]
+                core.actions.invoke(
+                    GotoPageAction::new(PageType::Bluetooth),
+                    core.clone(),
+                );
+            }),
+        )?;
+
         #[cfg(feature = "bluetooth")]
         {
-            core.buttons.setup_button(
-                ButtonConfig::Push(6),
-                clone!(@weak core => move |_| {
-                    core.actions.invoke(
-                        GotoPageAction::new(PageType::Bluetooth),
-                        core.clone(),
-                    );
-                }),
-            )?;
-
             core.buttons.setup_button(
                 ButtonConfig::Push(13),
                 clone!(@weak core => move |_| {
+                    // Accept pending question, such as a pairing request
+                    if core.answer_confirm(true) {
+                        return;
+                    }
                     if let Err(err) = core.bluetooth.set_discoverable(true) {
                         error!("Failed to send bluetooth discover command: {:?}", err);
                     }
@@ -204,13 +303,23 @@ impl Core {
             core.bluetooth
                 .events
                 .register_callback(clone!(@weak core => move |event| {
-                    if let pokoebox_bluetooth::manager::Event::Discoverable(status) = event {
-                        if let Err(err) = core.leds.led_set(Led::Action4, status) {
-                            error!("Failed to set bluetooth status LED: {:?}", err);
-                        }
-                        if let Err(err) = core.leds.led_set(Led::PowerButton, status) {
-                            error!("Failed to set bluetooth status LED: {:?}", err);
+                    use pokoebox_bluetooth::manager::Event;
+
+                    // Light LEDs while discoverable, blink when almost done, turn off with bluetooth
+                    let status = match event {
+                        Event::Discoverable(status) => status,
+                        Event::DiscoverableRemaining(remaining) => {
+                            let secs = remaining.as_secs();
+                            secs > DISCOVERABLE_BLINK_SECS || secs % 2 == 0
                         }
+                        Event::Power(false) => false,
+                        _ => return,
+                    };
+                    if let Err(err) = core.leds.led_set(Led::Action4, status) {
+                        error!("Failed to set bluetooth status LED: {:?}", err);
+                    }
+                    if let Err(err) = core.leds.led_set(Led::PowerButton, status) {
+                        error!("Failed to set bluetooth status LED: {:?}", err);
                     }
                 }));
         }
@@ -229,6 +338,27 @@ impl Core {
             }
         }));
 
+        // Show output level as bar on action LEDs
+        if core.config.leds.vu_meter {
+            let mut lit = [false; VU_LED_THRESHOLDS.len()];
+            core.meter
+                .events
+                .register_callback(clone!(@weak core => move |event| {
+                    let pokoebox_audio::meter::Event::Levels(levels) = event;
+                    let db = pokoebox_audio::meter::Levels::to_db(levels.max_peak());
+                    for (i, (led, threshold)) in VU_LED_THRESHOLDS.iter().enumerate() {
+                        let on = db >= *threshold;
+                        if on == lit[i] {
+                            continue;
+                        }
+                        lit[i] = on;
+                        if let Err(err) = core.leds.led_set(*led, on) {
+                            error!("Failed to set level meter LED: {:?}", err);
+                        }
+                    }
+                }));
+        }
+
         // Add new source on new MPRIS player
         core.mpris
             .events()
@@ -252,8 +382,117 @@ impl Core {
         Ok(())
     }
 
+    /// Ask user to confirm bluetooth pairing requests in secure mode.
+    ///
+    /// The question may be answered on screen, or accepted with the bluetooth button.
+    #[cfg(feature = "bluetooth")]
+    fn setup_pairing(core: Arc<Core>) {
+        use pokoebox_bluetooth::{driver::DriverCmd, manager::Event, util};
+
+        // Pairing request being asked
+        let mut pending: Option<(pokoebox_bluetooth::Address, Confirm)> = None;
+        core.bluetooth
+            .events
+            .register_callback(clone!(@weak core => move |event| {
+                match event {
+                    Event::PairingConfirm(address, name, passkey) => {
+                        let device = name.unwrap_or_else(|| util::address_hex(address));
+                        let text = match passkey {
+                            Some(passkey) => format!(
+                                "Pair with {}?\n\nMake sure it shows passkey {:06}.",
+                                device, passkey
+                            ),
+                            None => format!("Pair with {}?", device),
+                        };
+                        let closure_core = Arc::downgrade(&core);
+                        let confirm = Confirm::new(text, move |accept| {
+                            let core = match closure_core.upgrade() {
+                                Some(core) => core,
+                                None => return,
+                            };
+                            let cmd = DriverCmd::ConfirmPairing(address, accept);
+                            if let Err(err) = core.bluetooth.send_cmd(cmd) {
+                                error!("Failed to send bluetooth pairing answer: {:?}", err);
+                            }
+                        });
+                        core.confirm(confirm.clone());
+                        pending = Some((address, confirm));
+                    }
+                    Event::PairingDone(address, _) => {
+                        if pending.as_ref().map(|(a, _)| *a == address).unwrap_or(false) {
+                            if let Some((_, confirm)) = pending.take() {
+                                confirm.dismiss();
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }));
+    }
+
+    /// Follow master volume for DSP loudness compensation.
+    fn setup_dsp(core: Arc<Core>) {
+        if let Some((_, props)) = core.volume.get_role_control(Role::Master) {
+            if let Some(volume) = props.playback {
+                core.dsp
+                    .set_volume_level(volume_level(volume.init_value, volume.range));
+            }
+        }
+
+        core.volume
+            .mixer
+            .events
+            .register_callback(clone!(@weak core => move |event| {
+                if let VolumeEvent::Volume(control, volume) = event {
+                    match core.volume.get_role_control(Role::Master) {
+                        Some((master, props)) if master == control => {
+                            if let Some(props) = props.playback {
+                                core.dsp.set_volume_level(volume_level(volume, props.range));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }));
+    }
+
     /// Show a message to the user.
     pub fn show_message(&self, msg: Message) {
         self.messages.send(msg).expect("Failed to send message");
     }
+
+    /// Ask the user a question, dismisses the question pending before.
+    pub fn confirm(&self, confirm: Confirm) {
+        let previous = self
+            .confirm
+            .lock()
+            .expect("failed to lock pending question")
+            .replace(confirm.clone());
+        if let Some(previous) = previous {
+            previous.dismiss();
+        }
+        self.show_message(Message::Confirm(confirm));
+    }
+
+    /// Answer the pending question.
+    ///
+    /// Returns `false` if there is no question waiting for an answer.
+    pub fn answer_confirm(&self, accept: bool) -> bool {
+        let confirm = self
+            .confirm
+            .lock()
+            .expect("failed to lock pending question")
+            .take();
+        confirm
+            .map(|confirm| confirm.answer(accept))
+            .unwrap_or(false)
+    }
+}
+
+/// Get volume level in `[0, 1]` for volume in given range.
+fn volume_level(volume: i64, (min, max): (i64, i64)) -> f32 {
+    if max <= min {
+        return 1.0;
+    }
+    (volume - min) as f32 / (max - min) as f32
 }
@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::amp::AmpConfig;
+use crate::duck::DuckConfig;
+use crate::sampler::SamplerConfig;
+use crate::sounds::SoundboardConfig;
+use crate::standby::StandbyConfig;
+
+/// Application directory name in user config directory.
+pub(crate) const CONFIG_DIR: &str = "pokoebox";
+
+/// Config file name.
+const CONFIG_FILE: &str = "config.toml";
+
+/// File name to remember known bluetooth devices in.
+#[cfg(feature = "bluetooth")]
+const BLUETOOTH_STORE_FILE: &str = "bluetooth.toml";
+
+/// Application configuration.
+///
+/// Loaded from `config.toml` in the user config directory, all fields are optional.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Volume configuration.
+    pub volume: pokoebox_audio::volume::Config,
+
+    /// Output level meter configuration.
+    pub meter: pokoebox_audio::meter::Config,
+
+    /// DSP configuration for audio rendered by PokoeBox.
+    pub dsp: pokoebox_audio::dsp::Config,
+
+    /// Ducking configuration, lowers music while sound effects play.
+    pub duck: DuckConfig,
+
+    /// Front LED configuration.
+    pub leds: LedConfig,
+
+    /// Amplifier configuration.
+    pub amp: AmpConfig,
+
+    /// Auto-standby configuration.
+    pub standby: StandbyConfig,
+
+    /// Soundboard configuration.
+    pub soundboard: SoundboardConfig,
+
+    /// Sampler configuration.
+    pub sampler: SamplerConfig,
+
+    /// Bluetooth configuration.
+    #[cfg(feature = "bluetooth")]
+    pub bluetooth: pokoebox_bluetooth::Config,
+}
+
+/// Front LED configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LedConfig {
+    /// Show output level as a bar on the action LEDs.
+    pub vu_meter: bool,
+}
+
+impl Config {
+    /// Load configuration from user config file.
+    ///
+    /// Falls back to defaults if the file doesn't exist or is invalid.
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) if path.is_file() => path,
+            _ => {
+                info!("No config file found, using defaults");
+                return Self::default();
+            }
+        };
+
+        info!("Loading config from: {}", path.display());
+        match fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| toml::from_str(&data).map_err(|err| err.to_string()))
+        {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Failed to load config, using defaults: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Get path of user config file.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    /// Get bluetooth configuration, known devices are stored in the user config directory by
+    /// default.
+    #[cfg(feature = "bluetooth")]
+    pub fn bluetooth_config(&self) -> pokoebox_bluetooth::Config {
+        let mut config = self.bluetooth.clone();
+        if config.store.is_none() {
+            config.store =
+                dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(BLUETOOTH_STORE_FILE));
+        }
+        config
+    }
+}
@@ -0,0 +1,290 @@
+//! Ducking, lowers music while sound effects play.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pokoebox_audio::volume::{Cmd as VolumeCmd, ControlHandle, Curve, Role};
+use pokoebox_media::mpris::Cmd as MprisCmd;
+use serde::Deserialize;
+
+use crate::app::Core;
+use crate::soundeffecter::Event as EffecterEvent;
+
+/// Interval between volume steps while fading MPRIS players.
+const STEP_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Ducking configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DuckConfig {
+    /// Whether to lower music while sound effects play.
+    pub enabled: bool,
+
+    /// What to lower.
+    pub target: DuckTarget,
+
+    /// Volume while ducked, as fraction of the normal volume.
+    pub level: f64,
+
+    /// Time to fade music down in milliseconds.
+    pub attack: u64,
+
+    /// Time to fade music back up in milliseconds.
+    pub release: u64,
+}
+
+impl Default for DuckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target: DuckTarget::default(),
+            level: 0.3,
+            attack: 150,
+            release: 600,
+        }
+    }
+}
+
+/// What to lower while ducking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuckTarget {
+    /// Volume of MPRIS players.
+    #[default]
+    Mpris,
+
+    /// Master mixer volume, this lowers sound effects as well unless they use another output.
+    Master,
+}
+
+/// Start ducking music while sound effects play, if enabled.
+pub fn setup(core: Arc<Core>) {
+    let config = core.config.duck.clone();
+    if !config.enabled {
+        return;
+    }
+
+    // Track number of playing effects
+    let (tx, rx) = mpsc::channel();
+    core.effecter.events.register_callback(move |event| {
+        let playing = matches!(event, EffecterEvent::Play(_));
+        if let Err(err) = tx.send(playing) {
+            error!("Failed to send sound effect state to ducker: {:?}", err);
+        }
+    });
+
+    let core = Arc::downgrade(&core);
+    thread::spawn(move || {
+        let mut ducker = Ducker::new(config);
+        loop {
+            let timeout = if ducker.is_fading() {
+                STEP_INTERVAL
+            } else {
+                Duration::from_secs(60)
+            };
+            match rx.recv_timeout(timeout) {
+                Ok(true) => ducker.active += 1,
+                Ok(false) => ducker.active = ducker.active.saturating_sub(1),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            match core.upgrade() {
+                Some(core) => ducker.update(&core),
+                None => break,
+            }
+        }
+    });
+}
+
+/// Ducking state.
+struct Ducker {
+    config: DuckConfig,
+
+    /// Number of effects currently playing.
+    active: usize,
+
+    /// Current MPRIS volume factor.
+    factor: f64,
+
+    /// Time of last MPRIS volume step.
+    last_step: Instant,
+
+    /// Ducked master control, if ducked.
+    master: Option<MasterDuck>,
+}
+
+/// Ducked master control state.
+struct MasterDuck {
+    control: ControlHandle,
+
+    /// Volume range of the control.
+    range: (i64, i64),
+
+    /// Volume before ducking.
+    volume: i64,
+
+    /// Ducked volume faded to.
+    ducked: i64,
+
+    /// Time ducking started.
+    since: Instant,
+}
+
+impl Ducker {
+    fn new(config: DuckConfig) -> Self {
+        Self {
+            config,
+            active: 0,
+            factor: 1.0,
+            last_step: Instant::now(),
+            master: None,
+        }
+    }
+
+    /// Whether MPRIS volume is still fading to its target.
+    fn is_fading(&self) -> bool {
+        self.config.target == DuckTarget::Mpris && self.factor != self.target_factor()
+    }
+
+    fn target_factor(&self) -> f64 {
+        if self.active > 0 {
+            self.config.level.clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+
+    /// Move volume towards target for current number of playing effects.
+    fn update(&mut self, core: &Core) {
+        match self.config.target {
+            DuckTarget::Mpris => self.step_mpris(core),
+            DuckTarget::Master => self.update_master(core),
+        }
+    }
+
+    /// Step MPRIS volume factor towards target.
+    fn step_mpris(&mut self, core: &Core) {
+        let elapsed = self.last_step.elapsed();
+        self.last_step = Instant::now();
+
+        let target = self.target_factor();
+        if self.factor == target {
+            return;
+        }
+
+        // Fade over attack or release time, over the full ducking range
+        let (time, direction) = if target < self.factor {
+            (self.config.attack, -1.0)
+        } else {
+            (self.config.release, 1.0)
+        };
+        let range = 1.0 - self.config.level.clamp(0.0, 1.0);
+        let step = match time {
+            0 => range,
+            time => range * elapsed.min(STEP_INTERVAL).as_millis() as f64 / time as f64,
+        };
+        self.factor += direction * step.max(f64::EPSILON);
+        self.factor = if direction < 0.0 {
+            self.factor.max(target)
+        } else {
+            self.factor.min(target)
+        };
+
+        if let Err(err) = core.mpris.send_cmd(MprisCmd::SetVolumeFactor(self.factor)) {
+            error!("Failed to set MPRIS volume for ducking: {:?}", err);
+        }
+    }
+
+    /// Fade master volume down or back up.
+    fn update_master(&mut self, core: &Core) {
+        let duck = self.active > 0;
+        if duck == self.master.is_some() {
+            return;
+        }
+
+        if duck {
+            let (control, props) = match core.volume.get_role_control(Role::Master) {
+                Some(master) => master,
+                None => return,
+            };
+            let range = match props.playback {
+                Some(playback) => playback.range,
+                None => return,
+            };
+            let volume = match self.query_master(core, &control) {
+                Some(volume) => volume,
+                None => return,
+            };
+            let ducked =
+                range.0 + ((volume - range.0) as f64 * self.config.level.clamp(0.0, 1.0)) as i64;
+            self.fade_master(core, &control, ducked, self.config.attack);
+            self.master = Some(MasterDuck {
+                control,
+                range,
+                volume,
+                ducked,
+                since: Instant::now(),
+            });
+        } else if let Some(duck) = self.master.take() {
+            let volume = self.restore_volume(core, &duck);
+            self.fade_master(core, &duck.control, volume, self.config.release);
+        }
+    }
+
+    /// Volume to restore master control to after ducking.
+    ///
+    /// If the user changed the volume while ducked, the current volume is scaled back up instead
+    /// of restoring the volume from before ducking.
+    fn restore_volume(&self, core: &Core, duck: &MasterDuck) -> i64 {
+        // Still fading down, the volume wasn't changed by the user
+        if duck.since.elapsed() < Duration::from_millis(self.config.attack) {
+            return duck.volume;
+        }
+
+        let current = match self.query_master(core, &duck.control) {
+            Some(current) => current,
+            None => return duck.volume,
+        };
+        // Allow for rounding by the mixer
+        if (current - duck.ducked).abs() <= 1 {
+            return duck.volume;
+        }
+
+        let (min, max) = duck.range;
+        let level = self.config.level.clamp(0.0, 1.0);
+        if level <= 0.0 {
+            return current;
+        }
+        (min + ((current - min) as f64 / level).round() as i64).clamp(min, max)
+    }
+
+    /// Query current master volume.
+    fn query_master(&self, core: &Core, control: &ControlHandle) -> Option<i64> {
+        match core.volume.query_volume(control) {
+            Ok(Some(volume)) => Some(volume),
+            Ok(None) => {
+                warn!("Mixer didn't report master volume for ducking in time");
+                None
+            }
+            Err(err) => {
+                error!("Failed to query master volume for ducking: {:?}", err);
+                None
+            }
+        }
+    }
+
+    fn fade_master(&self, core: &Core, control: &ControlHandle, target: i64, time: u64) {
+        let cmd = VolumeCmd::FadeVolume(
+            control.clone(),
+            target,
+            Duration::from_millis(time),
+            Curve::SCurve,
+        );
+        if let Err(err) = core.volume.send_cmd(cmd) {
+            error!("Failed to fade master volume for ducking: {:?}", err);
+        }
+    }
+}
@@ -4,13 +4,21 @@ extern crate glib;
 extern crate log;
 
 pub mod action;
+pub mod amp;
 pub mod app;
+pub mod config;
+pub mod duck;
 pub mod error;
 pub mod message;
 pub mod pages;
 pub mod perif;
+pub mod pipeline;
 pub mod result;
+pub mod sampler;
+pub mod sequence;
 pub mod soundeffecter;
+pub mod sounds;
+pub mod standby;
 pub mod ui;
 pub mod volume;
 
@@ -1,6 +1,71 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 /// Global messages.
 #[derive(Debug, Clone)]
 pub enum Message {
     /// An error message.
     Error(String),
+
+    /// A question for the user to accept or reject.
+    Confirm(Confirm),
+}
+
+/// A question for the user to accept or reject.
+///
+/// Answered once, by the user through the dialog or a hardware button. The question may be
+/// dismissed without answer, for example if it timed out.
+#[derive(Clone)]
+pub struct Confirm {
+    /// Question to show.
+    pub text: String,
+
+    /// Called with the answer.
+    callback: Arc<dyn Fn(bool) + Send + Sync>,
+
+    /// Whether answered or dismissed.
+    done: Arc<AtomicBool>,
+}
+
+impl Confirm {
+    /// Construct question, `callback` is called with the answer.
+    pub fn new<F>(text: String, callback: F) -> Self
+    where
+        F: Fn(bool) + Send + Sync + 'static,
+    {
+        Self {
+            text,
+            callback: Arc::new(callback),
+            done: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Answer question, returns `false` if already answered or dismissed.
+    pub fn answer(&self, accept: bool) -> bool {
+        if self.done.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        (self.callback)(accept);
+        true
+    }
+
+    /// Dismiss question without answering.
+    pub fn dismiss(&self) {
+        self.done.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether answered or dismissed.
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for Confirm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Confirm")
+            .field("text", &self.text)
+            .field("done", &self.is_done())
+            .finish()
+    }
 }
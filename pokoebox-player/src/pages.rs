@@ -9,8 +9,10 @@ pub enum PageType {
     #[cfg(feature = "bluetooth")]
     Bluetooth,
     Clock,
+    Equalizer,
     Player,
     Power,
+    Settings,
     Soundboard,
     Test,
     Volume,
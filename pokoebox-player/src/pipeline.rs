@@ -0,0 +1,462 @@
+//! Playback pipeline for audio rendered by PokoeBox itself.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use pokoebox_audio::dsp::{Chain, Dsp};
+use pokoebox_audio::meter::{Accumulator, Tap};
+use pokoebox_audio::volume::SoftVolume;
+use rodio::{Sample, Source};
+
+/// Number of frames to accumulate before flushing to the meter tap.
+const METER_FLUSH_FRAMES: usize = 512;
+
+/// Time to fade out a stopped source in, prevents clicks.
+const STOP_FADE: Duration = Duration::from_millis(10);
+
+/// Source applying software volume to each sample.
+pub struct SoftVolumeSource<I> {
+    input: I,
+    volume: SoftVolume,
+}
+
+impl<I> SoftVolumeSource<I> {
+    /// Wrap the given source.
+    pub fn new(input: I, volume: SoftVolume) -> Self {
+        Self { input, volume }
+    }
+}
+
+impl<I> Iterator for SoftVolumeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        self.input.next().map(|sample| sample * self.volume.gain())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for SoftVolumeSource<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Source feeding each sample into a level meter tap, passing it on unchanged.
+pub struct MeterSource<I> {
+    input: I,
+    tap: Tap,
+    accumulator: Accumulator,
+
+    /// Channel of the next sample.
+    channel: usize,
+
+    /// Frames accumulated since last flush.
+    frames: usize,
+}
+
+impl<I> MeterSource<I> {
+    /// Wrap the given source.
+    pub fn new(input: I, tap: Tap) -> Self {
+        Self {
+            input,
+            tap,
+            accumulator: Accumulator::default(),
+            channel: 0,
+            frames: 0,
+        }
+    }
+}
+
+impl<I> Iterator for MeterSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = match self.input.next() {
+            Some(sample) => sample,
+            None => {
+                // Flush remaining samples at end of source
+                self.tap.push(&mut self.accumulator);
+                return None;
+            }
+        };
+
+        self.accumulator.add(self.channel, sample);
+        self.channel += 1;
+        if self.channel >= self.input.channels().max(1) as usize {
+            self.channel = 0;
+            self.frames += 1;
+            if self.frames >= METER_FLUSH_FRAMES {
+                self.frames = 0;
+                self.tap.push(&mut self.accumulator);
+            }
+        }
+
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for MeterSource<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Source applying the DSP chain, such as equalizer and loudness compensation.
+pub struct DspSource<I> {
+    input: I,
+    chain: Chain,
+
+    /// Channel of the next sample.
+    channel: usize,
+}
+
+impl<I> DspSource<I>
+where
+    I: Source<Item = f32>,
+{
+    /// Wrap the given source.
+    pub fn new(input: I, dsp: Dsp) -> Self {
+        let chain = Chain::new(dsp, input.channels(), input.sample_rate());
+        Self {
+            input,
+            chain,
+            channel: 0,
+        }
+    }
+}
+
+impl<I> Iterator for DspSource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        // Format may change between frames
+        if self.channel == 0 {
+            self.chain
+                .set_format(self.input.channels(), self.input.sample_rate());
+        }
+
+        let sample = self.chain.process(self.channel, self.input.next()?);
+        self.channel += 1;
+        if self.channel >= self.input.channels().max(1) as usize {
+            self.channel = 0;
+        }
+
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for DspSource<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Source invoking a callback once when it ends or is dropped, passing samples on unchanged.
+pub struct NotifySource<I> {
+    input: I,
+    on_end: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<I> NotifySource<I> {
+    /// Wrap the given source.
+    pub fn new<F>(input: I, on_end: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        Self {
+            input,
+            on_end: Some(Box::new(on_end)),
+        }
+    }
+
+    fn notify(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end();
+        }
+    }
+}
+
+impl<I> Drop for NotifySource<I> {
+    fn drop(&mut self) {
+        self.notify();
+    }
+}
+
+impl<I> Iterator for NotifySource<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next();
+        if sample.is_none() {
+            self.notify();
+        }
+        sample
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for NotifySource<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Shared playback control for a `ControlSource`.
+#[derive(Debug)]
+pub struct Control {
+    stopped: AtomicBool,
+    looping: AtomicBool,
+
+    /// Volume factor, as `f32` bits.
+    volume: AtomicU32,
+}
+
+impl Control {
+    /// Construct control with given volume factor.
+    pub fn new(volume: f32, looping: bool) -> Self {
+        Self {
+            stopped: AtomicBool::new(false),
+            looping: AtomicBool::new(looping),
+            volume: AtomicU32::new(volume.max(0.0).to_bits()),
+        }
+    }
+
+    /// Stop playback, fades out quickly.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether playback is stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Set whether to restart the source when it ends.
+    pub fn set_looping(&self, looping: bool) {
+        self.looping.store(looping, Ordering::Relaxed);
+    }
+
+    /// Whether the source restarts when it ends.
+    pub fn is_looping(&self) -> bool {
+        self.looping.load(Ordering::Relaxed)
+    }
+
+    /// Set volume factor.
+    pub fn set_volume(&self, volume: f32) {
+        self.volume
+            .store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get volume factor.
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+}
+
+/// Source that can be stopped, looped and changed in volume while playing.
+pub struct ControlSource<I> {
+    /// Copy of the input at its start, to restart from when looping.
+    start: I,
+    input: I,
+    control: Arc<Control>,
+
+    /// Current fade out factor, and step for each sample.
+    fade: (f32, f32),
+}
+
+impl<I> ControlSource<I>
+where
+    I: Source + Clone,
+    I::Item: Sample,
+{
+    /// Wrap the given source.
+    pub fn new(input: I, control: Arc<Control>) -> Self {
+        let samples =
+            input.sample_rate() as f32 * input.channels() as f32 * STOP_FADE.as_secs_f32();
+        Self {
+            start: input.clone(),
+            input,
+            control,
+            fade: (1.0, 1.0 / samples.max(1.0)),
+        }
+    }
+}
+
+impl<I> Iterator for ControlSource<I>
+where
+    I: Source + Clone,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        if self.control.is_stopped() {
+            self.fade.0 -= self.fade.1;
+            if self.fade.0 <= 0.0 {
+                return None;
+            }
+        }
+
+        let sample = match self.input.next() {
+            Some(sample) => sample,
+            None if self.control.is_looping() => {
+                self.input = self.start.clone();
+                self.input.next()?
+            }
+            None => return None,
+        };
+        Some(sample.amplify(self.control.volume() * self.fade.0))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.control.is_looping() {
+            (self.input.size_hint().0, None)
+        } else {
+            self.input.size_hint()
+        }
+    }
+}
+
+impl<I> Source for ControlSource<I>
+where
+    I: Source + Clone,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        if self.control.is_looping() {
+            None
+        } else {
+            self.input.total_duration()
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Sampler, records soundboard pad taps into patterns and loops them.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pokoebox_common::pipe::Pipe;
+use serde::{Deserialize, Serialize};
+
+use crate::app::Core;
+use crate::config::CONFIG_DIR;
+use crate::soundeffecter::Sound;
+
+/// Patterns file name in the application config directory.
+const PATTERNS_FILE: &str = "patterns.toml";
+
+/// Number of beats in a bar, recordings are rounded to whole bars.
+const BEATS_PER_BAR: u32 = 4;
+
+/// Number of hardware action buttons patterns can be bound to.
+pub const ACTION_BUTTONS: usize = 4;
+
+/// Sampler configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SamplerConfig {
+    /// Default tempo to quantize recordings to, in beats per minute.
+    pub bpm: f64,
+
+    /// Quantization steps per beat, 4 quantizes to 16th notes.
+    pub steps_per_beat: u32,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            bpm: 120.0,
+            steps_per_beat: 4,
+        }
+    }
+}
+
+/// A recorded pattern of pad taps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Pattern {
+    /// Tempo in beats per minute.
+    pub bpm: f64,
+
+    /// Pattern length in beats.
+    pub beats: u32,
+
+    /// Pad taps, ordered by position.
+    pub steps: Vec<Step>,
+}
+
+impl Pattern {
+    /// Build pattern from pad taps, quantized to given tempo.
+    ///
+    /// The first tap is at beat 0. The length is rounded up to whole bars to fit the last tap,
+    /// and extended if `end` is further away, rounded to whole bars. Taps snapped to the end of
+    /// the pattern wrap around to the start. Returns `None` if there are no taps.
+    fn quantize(
+        taps: &[(Instant, Sound, f32)],
+        end: Instant,
+        bpm: f64,
+        steps_per_beat: u32,
+    ) -> Option<Self> {
+        let first = taps.first()?.0;
+        let beat = 60.0 / bpm;
+        let steps_per_beat = steps_per_beat.max(1);
+        let steps_per_bar = (BEATS_PER_BAR * steps_per_beat) as u64;
+
+        // Snap taps to grid
+        let snapped: Vec<_> = taps
+            .iter()
+            .map(|(time, sound, gain)| {
+                let position = time.saturating_duration_since(first).as_secs_f64() / beat;
+                let index = (position * steps_per_beat as f64).round() as u64;
+                (index, sound, *gain)
+            })
+            .collect();
+
+        // Fit the last tap, extend to end
+        let last = snapped
+            .iter()
+            .map(|(index, _, _)| *index)
+            .max()
+            .unwrap_or(0);
+        let tap_bars = last.div_ceil(steps_per_bar);
+        let end_bars =
+            (end.saturating_duration_since(first).as_secs_f64() / beat / BEATS_PER_BAR as f64)
+                .round() as u64;
+        let bars = tap_bars.max(end_bars).max(1);
+        let beats = bars as u32 * BEATS_PER_BAR;
+        let total_steps = bars * steps_per_bar;
+
+        // Wrap taps at the end to the start, drop repeated taps of the same sound on the same step
+        let mut seen = HashSet::new();
+        let mut steps: Vec<(u64, Step)> = snapped
+            .into_iter()
+            .map(|(index, sound, gain)| {
+                let index = index % total_steps;
+                (
+                    index,
+                    Step {
+                        beat: index as f64 / steps_per_beat as f64,
+                        gain,
+                        sound: sound.clone(),
+                    },
+                )
+            })
+            .filter(|(index, step)| seen.insert((*index, step.sound.clone())))
+            .collect();
+        steps.sort_by_key(|(index, _)| *index);
+        let steps = steps.into_iter().map(|(_, step)| step).collect();
+
+        Some(Self { bpm, beats, steps })
+    }
+
+    /// Duration of a single beat.
+    fn beat_duration(&self) -> Duration {
+        Duration::from_secs_f64(60.0 / self.bpm.max(1.0))
+    }
+}
+
+/// A pad tap in a pattern.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    /// Position in beats from the start of the pattern.
+    pub beat: f64,
+
+    /// Volume factor.
+    pub gain: f32,
+
+    /// Sound to play.
+    pub sound: Sound,
+}
+
+/// Sampler events.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Recording started or stopped.
+    Recording(bool),
+
+    /// Pattern with given name started looping, or looping stopped.
+    Looping(Option<String>),
+
+    /// List of patterns or button bindings changed.
+    Patterns,
+}
+
+/// Saved patterns and button bindings.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Store {
+    /// Pattern names bound to action buttons.
+    bindings: Bindings,
+
+    /// Patterns by name.
+    patterns: BTreeMap<String, Pattern>,
+}
+
+impl Store {
+    /// Load from patterns file, empty if there is none.
+    fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) if path.is_file() => path,
+            _ => return Self::default(),
+        };
+
+        match fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| toml::from_str(&data).map_err(|err| err.to_string()))
+        {
+            Ok(store) => store,
+            Err(err) => {
+                error!("Failed to load sampler patterns: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save to patterns file.
+    fn save(&self) {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result = toml::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|data| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+                }
+                fs::write(&path, data).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            error!("Failed to save sampler patterns: {}", err);
+        }
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(PATTERNS_FILE))
+    }
+}
+
+/// Pattern names bound to action buttons.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Bindings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action2: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action3: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action4: Option<String>,
+}
+
+impl Bindings {
+    /// Get binding for action button by index.
+    fn get_mut(&mut self, button: usize) -> Option<&mut Option<String>> {
+        match button {
+            0 => Some(&mut self.action1),
+            1 => Some(&mut self.action2),
+            2 => Some(&mut self.action3),
+            3 => Some(&mut self.action4),
+            _ => None,
+        }
+    }
+}
+
+/// Sampler state.
+#[derive(Default)]
+struct State {
+    /// Pad taps while recording.
+    recording: Option<Vec<(Instant, Sound, f32)>>,
+
+    /// Saved patterns and bindings.
+    store: Store,
+
+    /// Name of looping pattern, and channel to stop it.
+    looping: Option<(String, Sender<()>)>,
+}
+
+/// Sampler, records soundboard pad taps into patterns and loops them over the music.
+pub struct Sampler {
+    /// Sampler events.
+    pub events: Pipe<Event>,
+
+    config: SamplerConfig,
+    state: Mutex<State>,
+}
+
+impl Sampler {
+    /// Construct sampler, loads saved patterns.
+    pub fn new(config: SamplerConfig) -> Self {
+        Self {
+            events: Pipe::default(),
+            config,
+            state: Mutex::new(State {
+                store: Store::load(),
+                ..State::default()
+            }),
+        }
+    }
+
+    /// Default tempo to quantize recordings to.
+    pub fn default_bpm(&self) -> f64 {
+        self.config.bpm
+    }
+
+    /// Start recording pad taps, discards any running recording.
+    pub fn start_recording(&self) {
+        self.state().recording = Some(Vec::new());
+        self.emit(Event::Recording(true));
+    }
+
+    /// Whether pad taps are being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.state().recording.is_some()
+    }
+
+    /// Record a pad tap, if recording.
+    pub fn record(&self, sound: &Sound, gain: f32) {
+        if let Some(taps) = &mut self.state().recording {
+            taps.push((Instant::now(), sound.clone(), gain));
+        }
+    }
+
+    /// Stop recording, saving taps as pattern quantized to given tempo.
+    ///
+    /// Returns the name of the new pattern, or `None` if nothing was recorded.
+    pub fn stop_recording(&self, bpm: f64) -> Option<String> {
+        let taps = self.state().recording.take();
+        self.emit(Event::Recording(false));
+
+        let pattern = Pattern::quantize(
+            &taps?,
+            Instant::now(),
+            bpm.max(1.0),
+            self.config.steps_per_beat,
+        )?;
+
+        // Save under first free name
+        let name = {
+            let mut state = self.state();
+            let name = (1..)
+                .map(|i| format!("Pattern {}", i))
+                .find(|name| !state.store.patterns.contains_key(name))
+                .unwrap();
+            state.store.patterns.insert(name.clone(), pattern);
+            state.store.save();
+            name
+        };
+        self.emit(Event::Patterns);
+        Some(name)
+    }
+
+    /// Get names of saved patterns.
+    pub fn patterns(&self) -> Vec<String> {
+        self.state().store.patterns.keys().cloned().collect()
+    }
+
+    /// Delete saved pattern, stops it if looping and removes its bindings.
+    pub fn delete(&self, name: &str) {
+        if self.looping().as_deref() == Some(name) {
+            self.stop();
+        }
+
+        {
+            let mut state = self.state();
+            state.store.patterns.remove(name);
+            for button in 0..ACTION_BUTTONS {
+                let binding = state.store.bindings.get_mut(button).unwrap();
+                if binding.as_deref() == Some(name) {
+                    *binding = None;
+                }
+            }
+            state.store.save();
+        }
+        self.emit(Event::Patterns);
+    }
+
+    /// Get pattern bound to action button by index.
+    pub fn binding(&self, button: usize) -> Option<String> {
+        self.state()
+            .store
+            .bindings
+            .get_mut(button)
+            .and_then(|binding| binding.clone())
+    }
+
+    /// Bind pattern to action button by index, or unbind with `None`.
+    pub fn bind(&self, button: usize, name: Option<String>) {
+        {
+            let mut state = self.state();
+            match state.store.bindings.get_mut(button) {
+                Some(binding) => *binding = name,
+                None => return,
+            }
+            state.store.save();
+        }
+        self.emit(Event::Patterns);
+    }
+
+    /// Get name of looping pattern.
+    pub fn looping(&self) -> Option<String> {
+        self.state().looping.as_ref().map(|(name, _)| name.clone())
+    }
+
+    /// Start looping pattern with given name, replaces looping pattern.
+    pub fn play(&self, core: &Arc<Core>, name: &str) {
+        let pattern = match self.state().store.patterns.get(name) {
+            Some(pattern) if !pattern.steps.is_empty() => pattern.clone(),
+            _ => {
+                warn!("Sampler pattern '{}' not found or empty", name);
+                return;
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let previous = self.state().looping.replace((name.into(), tx));
+        if let Some((_, stop)) = previous {
+            let _ = stop.send(());
+        }
+        self.emit(Event::Looping(Some(name.into())));
+
+        let core = Arc::downgrade(core);
+        thread::spawn(move || {
+            // Decode sounds up front, keeps disk and decoder latency out of the timing
+            if let Some(core) = core.upgrade() {
+                let sounds: HashSet<&Sound> =
+                    pattern.steps.iter().map(|step| &step.sound).collect();
+                for sound in sounds {
+                    if let Err(err) = core.effecter.preload(sound) {
+                        error!("Failed to load sampler sound: {:?}", err);
+                    }
+                }
+            }
+
+            let beat = pattern.beat_duration();
+            let length = beat * pattern.beats;
+            let mut cycle = Instant::now();
+            loop {
+                for step in &pattern.steps {
+                    let at = cycle + beat.mul_f64(step.beat);
+                    match rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => return,
+                    }
+                    let core = match core.upgrade() {
+                        Some(core) => core,
+                        None => return,
+                    };
+                    if let Err(err) = core.effecter.play_with(&step.sound, step.gain, false) {
+                        error!("Failed to play sampler sound: {:?}", err);
+                    }
+                }
+                cycle += length;
+            }
+        });
+    }
+
+    /// Stop looping pattern.
+    pub fn stop(&self) {
+        let looping = self.state().looping.take();
+        if let Some((_, stop)) = looping {
+            let _ = stop.send(());
+            self.emit(Event::Looping(None));
+        }
+    }
+
+    /// Start or stop looping pattern with given name.
+    pub fn toggle(&self, core: &Arc<Core>, name: &str) {
+        if self.looping().as_deref() == Some(name) {
+            self.stop();
+        } else {
+            self.play(core, name);
+        }
+    }
+
+    /// Toggle pattern bound to action button by index.
+    ///
+    /// Returns `false` if no pattern is bound, the button should do its default action then.
+    pub fn trigger(&self, core: &Arc<Core>, button: usize) -> bool {
+        match self.binding(button) {
+            Some(name) => {
+                self.toggle(core, &name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<State> {
+        self.state.lock().expect("failed to lock sampler state")
+    }
+
+    fn emit(&self, event: Event) {
+        if let Err(err) = self.events.send_or_drop(event) {
+            error!("Failed to send sampler event: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::soundeffecter::Builtin;
+
+    const KICK: Sound = Sound::Builtin(Builtin::Kick);
+    const GUITAR: Sound = Sound::Builtin(Builtin::Guitar);
+
+    /// Build taps at given beats at 120 BPM, with the end at given beat.
+    fn quantize(taps: &[(f64, Sound)], end: f64) -> Pattern {
+        let start = Instant::now();
+        let at = |beat: f64| start + Duration::from_secs_f64(beat * 0.5);
+        let taps: Vec<_> = taps
+            .iter()
+            .map(|(beat, sound)| (at(*beat), sound.clone(), 1.0))
+            .collect();
+        Pattern::quantize(&taps, at(end), 120.0, 4).unwrap()
+    }
+
+    fn beats(pattern: &Pattern) -> Vec<(f64, Sound)> {
+        pattern
+            .steps
+            .iter()
+            .map(|step| (step.beat, step.sound.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn no_taps() {
+        assert!(Pattern::quantize(&[], Instant::now(), 120.0, 4).is_none());
+    }
+
+    #[test]
+    fn snap() {
+        let pattern = quantize(&[(0.0, KICK), (1.1, KICK), (2.4, GUITAR), (2.9, KICK)], 4.0);
+        assert_eq!(
+            beats(&pattern),
+            vec![(0.0, KICK), (1.0, KICK), (2.5, GUITAR), (3.0, KICK)]
+        );
+    }
+
+    #[test]
+    fn wrap_at_end() {
+        // Tap snapped to the exact end of the bar lands on the first step
+        let pattern = quantize(&[(0.0, KICK), (2.0, GUITAR), (3.95, GUITAR)], 3.95);
+        assert_eq!(pattern.beats, 4);
+        assert_eq!(
+            beats(&pattern),
+            vec![(0.0, KICK), (0.0, GUITAR), (2.0, GUITAR)]
+        );
+    }
+
+    #[test]
+    fn dedup() {
+        // Same sound on the same step is kept once, other sounds on that step are kept
+        let pattern = quantize(
+            &[(0.0, KICK), (1.0, KICK), (1.05, GUITAR), (0.95, KICK)],
+            4.0,
+        );
+        assert_eq!(
+            beats(&pattern),
+            vec![(0.0, KICK), (1.0, KICK), (1.0, GUITAR)]
+        );
+    }
+
+    #[test]
+    fn length_fits_last_tap() {
+        // Stopped shortly after the bar line, taps in the second bar are kept
+        let pattern = quantize(&[(0.0, KICK), (4.5, KICK), (5.0, GUITAR)], 5.5);
+        assert_eq!(pattern.beats, 8);
+        assert_eq!(
+            beats(&pattern),
+            vec![(0.0, KICK), (4.5, KICK), (5.0, GUITAR)]
+        );
+    }
+
+    #[test]
+    fn length_extended_to_end() {
+        let pattern = quantize(&[(0.0, KICK), (1.0, KICK)], 8.2);
+        assert_eq!(pattern.beats, 8);
+
+        // Single tap is at least a bar long
+        let pattern = quantize(&[(0.0, KICK)], 0.0);
+        assert_eq!(pattern.beats, 4);
+    }
+}
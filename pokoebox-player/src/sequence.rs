@@ -0,0 +1,149 @@
+//! Power sequences, to start up and shut down audio output without pops.
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use pokoebox_audio::volume::{Cmd, ControlHandle, Curve, Role};
+use signal_hook::iterator::Signals;
+
+use crate::amp::MUTE_DELAY;
+use crate::app::Core;
+use crate::soundeffecter::{Builtin, Sound};
+
+/// Sound to play at startup.
+const STARTUP_SOUND: Option<Sound> = Some(Sound::Builtin(Builtin::MustangStart));
+
+/// Time to fade in master volume at startup.
+const FADE_IN_DURATION: Duration = Duration::from_millis(1500);
+
+/// Time to fade out master volume at shutdown.
+const FADE_OUT_DURATION: Duration = Duration::from_millis(500);
+
+/// Whether the shutdown sequence has started.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Run startup sequence.
+///
+/// Mutes, powers on the amplifier, fades in master volume and plays the startup sound. This
+/// blocks until done.
+pub fn startup(core: &Core) {
+    info!("Running startup sequence");
+
+    // Mute, then turn master all the way down to fade in from
+    //
+    // Query master first, muting may ramp its volume down if it has no mute switch.
+    let master = master_volume(core);
+    core.amp.set_master_mute(&core.volume, true);
+    if let Some((control, min, _)) = &master {
+        send_cmd(core, Cmd::SetVolume(control.clone(), *min));
+        core.amp.forget_master_volume();
+    }
+
+    // Power amplifier, unmute and fade in
+    core.amp.set_enabled(true);
+    core.amp.set_master_mute(&core.volume, false);
+    if let Some((control, _, volume)) = master {
+        if let Err(err) =
+            core.volume
+                .fade_volume_blocking(&control, volume, FADE_IN_DURATION, Curve::EaseIn)
+        {
+            error!("Failed to fade in master volume: {:?}", err);
+        }
+    }
+
+    if let Some(sound) = STARTUP_SOUND {
+        if let Err(err) = core.effecter.play(&sound) {
+            error!("Failed to play startup sound: {:?}", err);
+        }
+    }
+}
+
+/// Run shutdown sequence, only runs once.
+///
+/// Fades out master volume, mutes and powers off the amplifier. The master volume and mute state
+/// are restored afterwards while the amplifier is off, to leave the mixer as the user set it. This
+/// blocks until done.
+pub fn shutdown(core: &Core) {
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("Running shutdown sequence");
+    core.sampler.stop();
+
+    // Fade out master volume
+    let master = master_volume(core);
+    if let Some((control, min, _)) = &master {
+        if let Err(err) =
+            core.volume
+                .fade_volume_blocking(control, *min, FADE_OUT_DURATION, Curve::EaseOut)
+        {
+            error!("Failed to fade out master volume: {:?}", err);
+        }
+    }
+
+    // Mute and power off amplifier
+    core.amp.set_master_mute(&core.volume, true);
+    thread::sleep(MUTE_DELAY);
+    core.amp.set_enabled(false);
+
+    // Restore master volume, wait for mixer to apply it
+    core.amp.set_master_mute(&core.volume, false);
+    if let Some((control, _, volume)) = master {
+        send_cmd(core, Cmd::SetVolume(control.clone(), volume));
+        match core.volume.query_volume(&control) {
+            Ok(Some(_)) => {}
+            Ok(None) => warn!("Mixer didn't restore master volume in time"),
+            Err(err) => error!("Failed to restore master volume: {:?}", err),
+        }
+    }
+}
+
+/// Whether the shutdown sequence has started.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Run shutdown sequence and exit the application.
+pub fn exit(core: &Core) -> ! {
+    shutdown(core);
+    info!("Exiting");
+    process::exit(0);
+}
+
+/// Run shutdown sequence and exit on SIGTERM or SIGINT.
+pub fn handle_signals(core: Arc<Core>) -> Result<(), std::io::Error> {
+    let signals = Signals::new(&[signal_hook::SIGTERM, signal_hook::SIGINT])?;
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("Received signal {}, shutting down", signal);
+            exit(&core);
+        }
+    });
+    Ok(())
+}
+
+/// Get master control with its minimum and current volume.
+fn master_volume(core: &Core) -> Option<(ControlHandle, i64, i64)> {
+    let (control, props) = core.volume.get_role_control(Role::Master)?;
+    let min = props.playback?.range.0;
+    match core.volume.query_volume(&control) {
+        Ok(Some(volume)) => Some((control, min, volume)),
+        Ok(None) => {
+            warn!("Mixer didn't report master volume in time");
+            None
+        }
+        Err(err) => {
+            error!("Failed to query master volume: {:?}", err);
+            None
+        }
+    }
+}
+
+fn send_cmd(core: &Core, cmd: Cmd) {
+    if let Err(err) = core.volume.send_cmd(cmd) {
+        error!("Failed to send volume command: {:?}", err);
+    }
+}
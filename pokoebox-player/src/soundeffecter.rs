@@ -1,49 +1,260 @@
-use std::io::Cursor;
-
-/// Sound to play at startup.
-const STARTUP_SOUND: Option<Sound> = Some(Sound::MustangStart);
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
+use pokoebox_audio::dsp::Dsp;
+use pokoebox_audio::meter::Tap;
+use pokoebox_audio::volume::SoftVolume;
+use pokoebox_common::pipe::Pipe;
 use rodio::{
     decoder::{Decoder, DecoderError},
+    source::Buffered,
     Device, Source,
 };
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::{
+    Control, ControlSource, DspSource, MeterSource, NotifySource, SoftVolumeSource,
+};
+
+/// Default maximum number of sound effects playing at the same time.
+pub const DEFAULT_MAX_VOICES: usize = 8;
+
+/// Decoded sound effect, cheap to clone, clones share decoded samples.
+type Buffer = Buffered<Decoder<Cursor<Cow<'static, [u8]>>>>;
 
 /// System to play sound effects.
 pub struct SoundEffecter {
+    /// Sound effecter events.
+    pub events: Pipe<Event>,
+
     /// Output device.
     device: Device,
+
+    /// Software volume to apply to effects.
+    soft_volume: SoftVolume,
+
+    /// DSP to apply to effects.
+    dsp: Dsp,
+
+    /// Level meter tap to feed effects into.
+    tap: Tap,
+
+    /// Maximum number of effects playing at the same time.
+    max_voices: usize,
+
+    /// Currently playing effects, oldest first.
+    voices: Arc<Mutex<Vec<SoundHandle>>>,
+
+    /// Identifier for next effect to play.
+    next_id: AtomicUsize,
+
+    /// Decoded sound effects by sound, so effects are only read and decoded once.
+    buffers: Mutex<HashMap<Sound, Buffer>>,
 }
 
 impl SoundEffecter {
     /// Construct new sound effecter.
-    pub fn new() -> Result<Self, Error> {
+    ///
+    /// At most `max_voices` effects play at the same time, the oldest is stopped to make room.
+    pub fn new(
+        soft_volume: SoftVolume,
+        dsp: Dsp,
+        tap: Tap,
+        max_voices: usize,
+    ) -> Result<Self, Error> {
         // Select output device, build effecter
-        let effecter = Self {
+        Ok(Self {
+            events: Pipe::default(),
             device: rodio::default_output_device().ok_or(Error::NoOutput)?,
+            soft_volume,
+            dsp,
+            tap,
+            max_voices: max_voices.max(1),
+            voices: Arc::new(Mutex::new(Vec::new())),
+            next_id: AtomicUsize::new(0),
+            buffers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Read and fully decode given sound effect ahead of playing it.
+    ///
+    /// Use this before playing a sound at a precise time, playing then doesn't touch the disk or
+    /// decoder.
+    pub fn preload(&self, sound: &Sound) -> Result<(), Error> {
+        self.buffer(sound)?.count();
+        Ok(())
+    }
+
+    /// Get decoded sound effect, reads the sound on first use.
+    fn buffer(&self, sound: &Sound) -> Result<Buffer, Error> {
+        let mut buffers = self
+            .buffers
+            .lock()
+            .expect("failed to lock sound effect buffers");
+        if let Some(buffer) = buffers.get(sound) {
+            return Ok(buffer.clone());
+        }
+        let buffer = sound_source(sound)?.buffered();
+        buffers.insert(sound.clone(), buffer.clone());
+        Ok(buffer)
+    }
+
+    /// Play given sound effect.
+    pub fn play(&self, sound: &Sound) -> Result<SoundHandle, Error> {
+        self.play_with(sound, 1.0, false)
+    }
+
+    /// Play given sound effect at given volume factor, optionally looping until stopped.
+    pub fn play_with(
+        &self,
+        sound: &Sound,
+        volume: f32,
+        looping: bool,
+    ) -> Result<SoundHandle, Error> {
+        let source = self.buffer(sound)?;
+        let handle = SoundHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            sound: sound.clone(),
+            control: Arc::new(Control::new(volume, looping)),
         };
 
-        // Play startup sound
-        if let Some(sound) = STARTUP_SOUND {
-            if let Err(err) = effecter.play(sound) {
-                error!("Failed to play startup sound: {:?}", err);
+        // Make room for new effect by stopping the oldest
+        {
+            let mut voices = self
+                .voices
+                .lock()
+                .expect("failed to lock sound effect voices");
+            voices.retain(|voice| !voice.control.is_stopped());
+            while voices.len() >= self.max_voices {
+                debug!("Sound effect voice limit reached, stopping oldest");
+                voices.remove(0).stop();
             }
+            voices.push(handle.clone());
         }
 
-        Ok(effecter)
-    }
+        // Announce effect before playing, listeners may prepare output for it
+        if let Err(err) = self.events.send_or_drop(Event::Play(sound.clone())) {
+            error!("Failed to send sound effecter event: {:?}", err);
+        }
 
-    /// Play given sound effect.
-    pub fn play(&self, sound: Sound) -> Result<(), Error> {
-        Ok(rodio::play_raw(
+        // Announce end of effect
+        let events = self.events.clone();
+        let voices = self.voices.clone();
+        let ended = handle.clone();
+        let on_end = move || {
+            ended.stop();
+            voices
+                .lock()
+                .expect("failed to lock sound effect voices")
+                .retain(|voice| voice.id != ended.id);
+            if let Err(err) = events.send_or_drop(Event::Finished(ended.sound)) {
+                error!("Failed to send sound effecter event: {:?}", err);
+            }
+        };
+
+        rodio::play_raw(
             &self.device,
-            sound_source(sound)?.convert_samples(),
-        ))
+            NotifySource::new(
+                MeterSource::new(
+                    SoftVolumeSource::new(
+                        DspSource::new(
+                            ControlSource::new(source, handle.control.clone()).convert_samples(),
+                            self.dsp.clone(),
+                        ),
+                        self.soft_volume.clone(),
+                    ),
+                    self.tap.clone(),
+                ),
+                on_end,
+            ),
+        );
+        Ok(handle)
+    }
+
+    /// Get handles for all currently playing effects, oldest first.
+    pub fn playing(&self) -> Vec<SoundHandle> {
+        self.voices
+            .lock()
+            .expect("failed to lock sound effect voices")
+            .iter()
+            .filter(|voice| voice.is_playing())
+            .cloned()
+            .collect()
+    }
+
+    /// Stop all playing effects.
+    pub fn stop_all(&self) {
+        self.playing().iter().for_each(SoundHandle::stop);
     }
 }
 
-/// Available sound effect types.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Handle to a playing sound effect.
+///
+/// Cheap to clone, all clones control the same playback.
+#[derive(Debug, Clone)]
+pub struct SoundHandle {
+    id: usize,
+    sound: Sound,
+    control: Arc<Control>,
+}
+
+impl SoundHandle {
+    /// Get the sound effect being played.
+    pub fn sound(&self) -> &Sound {
+        &self.sound
+    }
+
+    /// Whether the effect is still playing.
+    pub fn is_playing(&self) -> bool {
+        !self.control.is_stopped()
+    }
+
+    /// Stop the effect.
+    pub fn stop(&self) {
+        self.control.stop();
+    }
+
+    /// Set whether to loop the effect until stopped.
+    pub fn set_looping(&self, looping: bool) {
+        self.control.set_looping(looping);
+    }
+
+    /// Whether the effect loops until stopped.
+    pub fn is_looping(&self) -> bool {
+        self.control.is_looping()
+    }
+
+    /// Set volume factor of the effect.
+    pub fn set_volume(&self, volume: f32) {
+        self.control.set_volume(volume);
+    }
+
+    /// Get volume factor of the effect.
+    pub fn volume(&self) -> f32 {
+        self.control.volume()
+    }
+}
+
+/// A sound effect.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Sound {
+    /// Built-in sound effect.
+    Builtin(Builtin),
+
+    /// Sound effect loaded from file.
+    File(PathBuf),
+}
+
+/// Built-in sound effects.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Builtin {
     Kick,
     Guitar,
     Xp,
@@ -51,15 +262,54 @@ pub enum Sound {
     MustangStart,
 }
 
-/// Get a decoder for the given sound effect type.
-fn sound_source(sound: Sound) -> Result<Decoder<Cursor<&'static [u8]>>, Error> {
+impl Builtin {
+    /// All built-in sound effects.
+    pub const ALL: [Builtin; 5] = [
+        Builtin::Kick,
+        Builtin::Guitar,
+        Builtin::Xp,
+        Builtin::Jbl,
+        Builtin::MustangStart,
+    ];
+
+    /// Get display label.
+    pub fn label(self) -> &'static str {
+        match self {
+            Builtin::Kick => "Kick 30Hz",
+            Builtin::Guitar => "Guitar",
+            Builtin::Xp => "XP",
+            Builtin::Jbl => "JBL",
+            Builtin::MustangStart => "Mustang",
+        }
+    }
+}
+
+/// Sound effecter events.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Given sound effect is about to play.
+    Play(Sound),
+
+    /// Given sound effect finished playing.
+    Finished(Sound),
+}
+
+/// Get a decoder for the given sound effect.
+fn sound_source(sound: &Sound) -> Result<Decoder<Cursor<Cow<'static, [u8]>>>, Error> {
     // Select sound
-    let sound: &[u8] = match sound {
-        Sound::Kick => include_bytes!("../../res/sounds/kick_30hz.ogg"),
-        Sound::Guitar => include_bytes!("../../res/sounds/guitar.ogg"),
-        Sound::Xp => include_bytes!("../../res/sounds/xp.ogg"),
-        Sound::Jbl => include_bytes!("../../res/sounds/jbl.ogg"),
-        Sound::MustangStart => include_bytes!("../../res/sounds/mustang_start_long.ogg"),
+    let sound: Cow<'static, [u8]> = match sound {
+        Sound::Builtin(Builtin::Kick) => {
+            include_bytes!("../../res/sounds/kick_30hz.ogg")[..].into()
+        }
+        Sound::Builtin(Builtin::Guitar) => include_bytes!("../../res/sounds/guitar.ogg")[..].into(),
+        Sound::Builtin(Builtin::Xp) => include_bytes!("../../res/sounds/xp.ogg")[..].into(),
+        Sound::Builtin(Builtin::Jbl) => include_bytes!("../../res/sounds/jbl.ogg")[..].into(),
+        Sound::Builtin(Builtin::MustangStart) => {
+            include_bytes!("../../res/sounds/mustang_start_long.ogg")[..].into()
+        }
+        Sound::File(path) => fs::read(path)
+            .map_err(|err| Error::Read(path.clone(), err.kind()))?
+            .into(),
     };
 
     // Build source
@@ -72,6 +322,9 @@ pub enum Error {
     /// Could not select audio output device.
     NoOutput,
 
+    /// Failed to read sound effect file.
+    Read(PathBuf, io::ErrorKind),
+
     /// An error occurred while decoding audio.
     Decode(DecoderError),
 }
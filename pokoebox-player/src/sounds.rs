@@ -0,0 +1,211 @@
+//! Soundboard sounds, loaded from a user directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::CONFIG_DIR;
+use crate::soundeffecter::{Builtin, Sound, DEFAULT_MAX_VOICES};
+
+/// Default sounds directory name in the application config directory.
+const SOUNDS_DIR: &str = "sounds";
+
+/// Sound manifest file name in the sounds directory.
+const MANIFEST_FILE: &str = "sounds.toml";
+
+/// File extensions to pick up when the sounds directory has no manifest.
+const EXTENSIONS: [&str; 4] = ["ogg", "wav", "flac", "mp3"];
+
+/// Soundboard configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SoundboardConfig {
+    /// Directory to load sounds from, `sounds` in the application config directory if not set.
+    pub dir: Option<PathBuf>,
+
+    /// Maximum number of sound effects playing at the same time.
+    pub max_voices: usize,
+}
+
+impl Default for SoundboardConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_voices: DEFAULT_MAX_VOICES,
+        }
+    }
+}
+
+impl SoundboardConfig {
+    /// Get directory to load sounds from.
+    pub fn dir(&self) -> Option<PathBuf> {
+        self.dir
+            .clone()
+            .or_else(|| dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(SOUNDS_DIR)))
+    }
+}
+
+/// A soundboard pad.
+#[derive(Clone, Debug)]
+pub struct Pad {
+    /// Sound to play.
+    pub sound: Sound,
+
+    /// Button label.
+    pub label: String,
+
+    /// Button color, as CSS color.
+    pub color: Option<String>,
+
+    /// Volume factor to play sound at.
+    pub gain: f32,
+
+    /// Whether to loop the sound until tapped again.
+    pub looping: bool,
+}
+
+impl Pad {
+    /// Pad for built-in sound.
+    fn builtin(sound: Builtin) -> Self {
+        Self {
+            sound: Sound::Builtin(sound),
+            label: sound.label().into(),
+            color: None,
+            gain: 1.0,
+            looping: false,
+        }
+    }
+}
+
+/// Sounds directory manifest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    /// Sounds in soundboard order.
+    sounds: Vec<Entry>,
+}
+
+/// Sound in sounds directory manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    /// Sound file, relative to the sounds directory.
+    file: PathBuf,
+
+    /// Button label, file name if not set.
+    label: Option<String>,
+
+    /// Button color, as CSS color.
+    color: Option<String>,
+
+    /// Volume factor.
+    #[serde(default = "default_gain")]
+    gain: f32,
+
+    /// Whether to loop the sound until tapped again.
+    #[serde(default, rename = "loop")]
+    looping: bool,
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+/// Load soundboard pads from configured sounds directory.
+///
+/// Uses the manifest in the directory if there is one, otherwise all sound files in it in name
+/// order. Falls back to the built-in sounds if no sounds are found.
+pub fn load(config: &SoundboardConfig) -> Vec<Pad> {
+    let pads = match config.dir() {
+        Some(ref dir) if dir.is_dir() => {
+            let manifest = dir.join(MANIFEST_FILE);
+            if manifest.is_file() {
+                load_manifest(dir, &manifest)
+            } else {
+                load_dir(dir)
+            }
+        }
+        _ => Vec::new(),
+    };
+
+    if pads.is_empty() {
+        debug!("No soundboard sounds found, using built-in sounds");
+        return Builtin::ALL.iter().copied().map(Pad::builtin).collect();
+    }
+    pads
+}
+
+/// Load pads listed in manifest, skips missing sound files.
+fn load_manifest(dir: &Path, path: &Path) -> Vec<Pad> {
+    let manifest: Manifest = match fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|data| toml::from_str(&data).map_err(|err| err.to_string()))
+    {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            error!("Failed to load sound manifest {}: {}", path.display(), err);
+            return Vec::new();
+        }
+    };
+
+    manifest
+        .sounds
+        .into_iter()
+        .filter_map(|entry| {
+            let file = dir.join(&entry.file);
+            if !file.is_file() {
+                warn!("Sound file not found, skipping: {}", file.display());
+                return None;
+            }
+            Some(Pad {
+                label: entry.label.unwrap_or_else(|| file_label(&file)),
+                sound: Sound::File(file),
+                color: entry.color,
+                gain: entry.gain.max(0.0),
+                looping: entry.looping,
+            })
+        })
+        .collect()
+}
+
+/// Load pads for all sound files in directory, in name order.
+fn load_dir(dir: &Path) -> Vec<Pad> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("Failed to list sounds in {}: {}", dir.display(), err);
+            return Vec::new();
+        }
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                .unwrap_or(false)
+        })
+        .collect();
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| Pad {
+            label: file_label(&file),
+            sound: Sound::File(file),
+            color: None,
+            gain: 1.0,
+            looping: false,
+        })
+        .collect()
+}
+
+/// Button label for sound file, its name without extension.
+fn file_label(file: &Path) -> String {
+    file.file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,288 @@
+//! Auto-standby, powers the amplifier down after a period of silence.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pokoebox_audio::meter::{Config as MeterConfig, Event as MeterEvent, Levels, Source};
+use pokoebox_media::player::{player::Event as PlayerEvent, source::Event as SourceEvent};
+use serde::Deserialize;
+
+use crate::app::Core;
+use crate::sequence;
+use crate::soundeffecter::Event as EffecterEvent;
+
+/// Default time of silence before entering standby in seconds.
+const DEFAULT_TIMEOUT: u64 = 10 * 60;
+
+/// Output levels above this level in dB count as activity.
+const ACTIVITY_THRESHOLD_DB: f32 = -60.0;
+
+/// Interval to check for standby timeout at.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Standby configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct StandbyConfig {
+    /// Whether to enter standby on silence.
+    pub enabled: bool,
+
+    /// Time of silence before entering standby in seconds.
+    pub timeout: u64,
+
+    /// How to detect silence.
+    pub detect: Detect,
+}
+
+impl Default for StandbyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout: DEFAULT_TIMEOUT,
+            detect: Detect::default(),
+        }
+    }
+}
+
+/// How to detect silence.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detect {
+    /// Silent if output level meter reports silence.
+    ///
+    /// Requires the meter to capture from an Alsa device. The pipeline meter only sees audio
+    /// PokoeBox renders itself, not music from players, `Both` is used instead then.
+    Meter,
+
+    /// Silent if no player source is playing.
+    Playback,
+
+    /// Silent if both the level meter and player sources are silent.
+    #[default]
+    Both,
+}
+
+impl Detect {
+    /// Get detection method to use with given meter config.
+    ///
+    /// Falls back to `Both` if only the meter is used but it can't see all playback.
+    fn resolve(self, meter: &MeterConfig) -> Self {
+        let meters_all = meter.enabled && matches!(meter.source, Source::Alsa(_));
+        if self == Detect::Meter && !meters_all {
+            warn!("No Alsa meter source to detect silence by meter, also using playback");
+            return Detect::Both;
+        }
+        self
+    }
+
+    fn meter(self) -> bool {
+        self != Detect::Playback
+    }
+
+    fn playback(self) -> bool {
+        self != Detect::Meter
+    }
+}
+
+/// Auto-standby controller.
+///
+/// Sound effects always count as activity, and wake the amplifier as they start playing.
+///
+/// The amplifier is switched on the standby thread, so event callbacks never block on it.
+pub struct Standby {
+    config: StandbyConfig,
+    state: Mutex<State>,
+
+    /// Sender to request the standby thread to wake.
+    wake_tx: Mutex<Sender<()>>,
+
+    /// Receiver for wake requests, taken by the standby thread.
+    wake_rx: Mutex<Option<Receiver<()>>>,
+}
+
+impl Standby {
+    /// Construct standby controller.
+    ///
+    /// Call `setup` to start it.
+    pub fn new(config: StandbyConfig) -> Self {
+        let (wake_tx, wake_rx) = mpsc::channel();
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+            wake_tx: Mutex::new(wake_tx),
+            wake_rx: Mutex::new(Some(wake_rx)),
+        }
+    }
+
+    /// Start detecting silence and entering standby, if enabled.
+    pub fn setup(core: Arc<Core>) {
+        let config = &core.standby.config;
+        if !config.enabled {
+            return;
+        }
+        info!(
+            "Entering standby after {} seconds of silence",
+            config.timeout
+        );
+        let detect = config.detect.resolve(&core.config.meter);
+
+        // Output levels above threshold count as activity
+        if detect.meter() {
+            core.meter
+                .events
+                .register_callback(clone!(@weak core => move |event| {
+                    let MeterEvent::Levels(levels) = event;
+                    if Levels::to_db(levels.max_peak()) > ACTIVITY_THRESHOLD_DB {
+                        core.standby.wake();
+                    }
+                }));
+        }
+
+        // Playing sources count as activity for as long as they're playing
+        if detect.playback() {
+            core.player
+                .events
+                .register_callback(clone!(@weak core => move |event| {
+                    if let PlayerEvent::Source(SourceEvent::States(states)) = event {
+                        let playing = states.iter().any(|(_, state)| state.playing);
+                        core.standby.set_playing(playing);
+                        if playing {
+                            core.standby.wake();
+                        }
+                    }
+                }));
+        }
+
+        // Wake when sound effects play
+        core.effecter
+            .events
+            .register_callback(clone!(@weak core => move |event| {
+                if let EffecterEvent::Play(_) = event {
+                    core.standby.wake();
+                }
+            }));
+
+        // Wake on request, enter standby on timeout
+        let timeout = Duration::from_secs(config.timeout);
+        let wake_rx = core
+            .standby
+            .wake_rx
+            .lock()
+            .expect("failed to lock standby wake receiver")
+            .take()
+            .expect("standby already set up");
+        let core = Arc::downgrade(&core);
+        thread::spawn(move || loop {
+            let wake = match wake_rx.recv_timeout(CHECK_INTERVAL) {
+                Ok(()) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let core = match core.upgrade() {
+                Some(core) => core,
+                None => break,
+            };
+            if wake {
+                // Requests queued while waking are handled by this one
+                wake_rx.try_iter().for_each(drop);
+                core.standby.power_on(&core);
+            } else {
+                core.standby.check_timeout(&core, timeout);
+            }
+        });
+    }
+
+    /// Whether currently in standby.
+    pub fn is_standby(&self) -> bool {
+        self.state
+            .lock()
+            .expect("failed to lock standby state")
+            .standby
+    }
+
+    /// Register activity, requests the standby thread to power the amplifier on if in standby.
+    ///
+    /// This does not block while the amplifier powers on.
+    pub fn wake(&self) {
+        let mut state = self.state.lock().expect("failed to lock standby state");
+        state.last_activity = Instant::now();
+        if state.standby && !state.waking {
+            state.waking = true;
+            let _ = self
+                .wake_tx
+                .lock()
+                .expect("failed to lock standby wake sender")
+                .send(());
+        }
+    }
+
+    /// Power the amplifier on if in standby, called from the standby thread.
+    fn power_on(&self, core: &Core) {
+        let standby = {
+            let mut state = self.state.lock().expect("failed to lock standby state");
+            state.waking = false;
+            state.standby
+        };
+        if !standby || sequence::is_shutting_down() {
+            return;
+        }
+
+        // Don't hold the state lock while the amplifier settles
+        info!("Activity detected, leaving standby");
+        core.amp.set_powered(&core.volume, true);
+        let mut state = self.state.lock().expect("failed to lock standby state");
+        state.standby = false;
+        state.last_activity = Instant::now();
+    }
+
+    /// Set whether any source is playing.
+    fn set_playing(&self, playing: bool) {
+        let mut state = self.state.lock().expect("failed to lock standby state");
+        state.playing = playing;
+        state.last_activity = Instant::now();
+    }
+
+    /// Enter standby if silent for the given timeout.
+    fn check_timeout(&self, core: &Core, timeout: Duration) {
+        {
+            let mut state = self.state.lock().expect("failed to lock standby state");
+            if state.standby || state.playing || state.last_activity.elapsed() < timeout {
+                return;
+            }
+
+            // Mark as standby first, so activity while powering down requests a wake
+            state.standby = true;
+        }
+
+        info!("Silent for {} seconds, entering standby", timeout.as_secs());
+        core.amp.set_powered(&core.volume, false);
+    }
+}
+
+/// Standby state.
+struct State {
+    /// Whether in standby.
+    standby: bool,
+
+    /// Whether any source is playing.
+    playing: bool,
+
+    /// Whether a wake request is pending on the standby thread.
+    waking: bool,
+
+    /// Time of last activity.
+    last_activity: Instant,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            standby: false,
+            playing: false,
+            waking: false,
+            last_activity: Instant::now(),
+        }
+    }
+}
@@ -6,6 +6,7 @@ use gtk::{prelude::*, IconSize, ReliefStyle};
 use crate::action::actions::GotoPageAction;
 use crate::app::Core;
 use crate::pages::PageType;
+use crate::ui::gtk::widgets::VuMeter;
 
 /// Main UI header in the application.
 pub struct Header {
@@ -72,6 +73,12 @@ impl Header {
         let volume = gtk::VolumeButton::new();
         container.pack_end(&volume, false, false, 10);
 
+        // Create output level meter
+        if core.config.meter.enabled {
+            let vu_meter = VuMeter::new(core.clone());
+            container.pack_end(vu_meter.gtk_widget(), false, false, 0);
+        }
+
         // Create a time label
         let time_label = gtk::ButtonBuilder::new()
             .relief(gtk::ReliefStyle::None)
@@ -1,6 +1,7 @@
 pub mod main;
 pub mod page;
 pub mod pages;
+pub mod widgets;
 mod ui;
 mod window;
 
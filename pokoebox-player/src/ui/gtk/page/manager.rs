@@ -53,9 +53,11 @@ impl PageManager {
             #[cfg(feature = "bluetooth")]
             PageType::Bluetooth => Box::new(pages::Bluetooth::new(core)),
             PageType::Clock => Box::new(pages::Clock::new(core)),
+            PageType::Equalizer => Box::new(pages::Equalizer::new(core)),
             PageType::Launchpad => Box::new(pages::Launchpad::new(core)),
             PageType::Player => Box::new(pages::Player::new(core)),
             PageType::Power => Box::new(pages::Power::new(core)),
+            PageType::Settings => Box::new(pages::Settings::new(core)),
             PageType::Soundboard => Box::new(pages::Soundboard::new(core)),
             PageType::Test => Box::new(pages::Test::new(core)),
             PageType::Volume => Box::new(pages::Volume::new(core)),
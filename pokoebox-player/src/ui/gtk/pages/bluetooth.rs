@@ -1,11 +1,17 @@
+use std::cell::Cell;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Duration;
 
 use gtk::prelude::*;
-use pokoebox_bluetooth::device::DeviceList;
+use pokoebox_bluetooth::device::{Device, DeviceKind, DeviceList};
+use pokoebox_bluetooth::driver::DriverCmd;
 use pokoebox_bluetooth::manager::Event;
+use pokoebox_bluetooth::policy::Reason;
+use pokoebox_bluetooth::{util, Address};
 
 use crate::app::Core;
+use crate::message::{Confirm, Message};
 use crate::pages::PageType;
 
 use super::page::Helper;
@@ -15,6 +21,15 @@ const PAGE_TYPE: PageType = PageType::Bluetooth;
 const PAGE_NAME: &str = "Bluetooth";
 const SPACING: i32 = 8;
 
+/// Seconds to show connection policy notices for.
+const NOTICE_TIMEOUT: u32 = 5;
+
+/// Status shown while there is no bluetooth controller.
+const NO_CONTROLLER: &str = "No bluetooth adapter found, plug one in to use bluetooth";
+
+/// Seconds to extend discoverability by.
+const DISCOVERABLE_EXTEND: u64 = 60;
+
 /// Bluetooth page.
 pub struct Bluetooth {
     /// Page container
@@ -54,27 +69,57 @@ impl Page for Bluetooth {
         btns.set_layout(gtk::ButtonBoxStyle::Center);
         gbox.add(&btns);
 
-        let btn_power = gtk::Button::new_with_label("Power");
-        btn_power.set_sensitive(false);
+        let btn_power = gtk::ToggleButton::new_with_label("Power");
+        btn_power.set_active(true);
+        btn_power.connect_toggled(clone!(@weak core => move |btn| {
+            // Insensitive without controller, don't send commands for synced state
+            if !btn.get_sensitive() {
+                return;
+            }
+            send_cmd(&core, DriverCmd::Power(btn.get_active()));
+        }));
         btns.add(&btn_power);
 
+        // Start discoverability, or stop it when already discoverable
+        let discoverable = Rc::new(Cell::new(false));
         let btn_discoverable = gtk::Button::new_with_label("Connect");
-        let core_closure = core.clone();
-        btn_discoverable.connect_clicked(move |btn| {
+        btn_discoverable.connect_clicked(clone!(@weak core, @strong discoverable => move |btn| {
             btn.set_sensitive(false);
-            // TODO: handle result
-            core_closure
-                .bluetooth
-                .set_discoverable(true)
-                .expect("failed to set discoverable");
-        });
+            if let Err(err) = core.bluetooth.set_discoverable(!discoverable.get()) {
+                error!("Failed to set bluetooth discoverable: {:?}", err);
+            }
+        }));
         btns.add(&btn_discoverable);
 
+        let btn_extend = gtk::Button::new_with_label(&format!("+{} s", DISCOVERABLE_EXTEND));
+        btn_extend.set_sensitive(false);
+        btn_extend.connect_clicked(clone!(@weak core => move |_| {
+            send_cmd(
+                &core,
+                DriverCmd::ExtendDiscoverable(Duration::from_secs(DISCOVERABLE_EXTEND)),
+            );
+        }));
+        btns.add(&btn_extend);
+
+        let status = gtk::Label::new(None);
+        status.set_no_show_all(true);
+        gbox.add(&status);
+
         let scroll_view = gtk::ViewportBuilder::new().expand(true).build();
         gbox.add(&scroll_view);
         let (treeview, store) = build_list();
         scroll_view.add(&treeview);
 
+        // Actions for selected device
+        let actions = Rc::new(DeviceActions::build(&gbox));
+        actions.connect(core.clone(), &treeview);
+        actions.update(None);
+        treeview
+            .get_selection()
+            .connect_changed(clone!(@strong actions => move |selection| {
+                actions.update(selected(selection));
+            }));
+
         // Handle bluetooth manager events
         let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
         core.bluetooth.events.register_callback(move |event| {
@@ -82,11 +127,6 @@ impl Page for Bluetooth {
                 error!("Failed to send bluetooth manager event to Glib: {:?}", err);
             }
         });
-        let btn_discoverable = Rc::new(btn_discoverable);
-        let store = Rc::new(store);
-        rx.attach(None, move |event| {
-            handle_bluetooth_event(event, btn_discoverable.clone(), store.clone())
-        });
 
         // Request to emit current bluetooth state events
         if let Err(err) = core.bluetooth.emit_state() {
@@ -95,6 +135,20 @@ impl Page for Bluetooth {
                 err
             );
         }
+
+        let widgets = Widgets {
+            btn_power,
+            btn_discoverable,
+            btn_extend,
+            discoverable,
+            status,
+            treeview,
+            store,
+            actions,
+        };
+        rx.attach(None, move |event| {
+            handle_bluetooth_event(&core, event, &widgets)
+        });
     }
 
     fn gtk_widget(&self) -> &gtk::Grid {
@@ -102,24 +156,111 @@ impl Page for Bluetooth {
     }
 }
 
-fn handle_bluetooth_event(
-    event: Event,
-    btn_discoverable: Rc<gtk::Button>,
-    store: Rc<gtk::ListStore>,
-) -> glib::Continue {
+/// Page widgets updated on bluetooth events.
+struct Widgets {
+    btn_power: gtk::ToggleButton,
+    btn_discoverable: gtk::Button,
+    btn_extend: gtk::Button,
+    discoverable: Rc<Cell<bool>>,
+    status: gtk::Label,
+    treeview: gtk::TreeView,
+    store: gtk::ListStore,
+    actions: Rc<DeviceActions>,
+}
+
+fn handle_bluetooth_event(core: &Arc<Core>, event: Event, widgets: &Widgets) -> glib::Continue {
+    let Widgets {
+        btn_power,
+        btn_discoverable,
+        btn_extend,
+        discoverable,
+        status,
+        treeview,
+        store,
+        actions,
+    } = widgets;
+
     match event {
         Event::Discoverable(true) => {
-            btn_discoverable.set_label("Discoverable...");
-            btn_discoverable.set_sensitive(false);
+            discoverable.set(true);
+            btn_discoverable.set_label("Stop");
+            btn_discoverable.set_sensitive(true);
+            btn_extend.set_sensitive(true);
         }
         Event::Discoverable(false) => {
+            discoverable.set(false);
             btn_discoverable.set_label("Connect");
-            btn_discoverable.set_sensitive(true);
+            btn_discoverable.set_sensitive(btn_power.get_active());
+            btn_extend.set_sensitive(false);
+        }
+        Event::DiscoverableRemaining(remaining) => {
+            if discoverable.get() {
+                btn_discoverable.set_label(&format!("Stop ({} s)", remaining.as_secs()));
+            }
+        }
+        Event::Controller(available) => {
+            btn_power.set_sensitive(available);
+            if !available {
+                status.set_text(NO_CONTROLLER);
+                status.show();
+            } else if status
+                .get_text()
+                .map_or(false, |text| text.as_str() == NO_CONTROLLER)
+            {
+                status.hide();
+            }
+        }
+        Event::Power(power) => {
+            if btn_power.get_active() != power {
+                btn_power.set_active(power);
+            }
+            if !power {
+                btn_discoverable.set_sensitive(false);
+                btn_extend.set_sensitive(false);
+            }
         }
         Event::DeviceConnected(_, devices)
         | Event::DeviceDisconnected(_, devices)
         | Event::Devices(devices) => {
-            update_list(&store, devices);
+            update_list(store, devices);
+            actions.update(selected(&treeview.get_selection()));
+        }
+        Event::Reconnecting(address, attempt) => {
+            status.set_text(&format!(
+                "Reconnecting to {} (attempt {})...",
+                device_name(store, &util::address_hex(address)),
+                attempt
+            ));
+            status.show();
+        }
+        Event::ReconnectDone(_) => status.hide(),
+        Event::PolicyDisconnected(address, reason) => {
+            let name = device_name(store, &util::address_hex(address));
+            status.set_text(&match reason {
+                Reason::Denied => format!("{} is not allowed to connect", name),
+                Reason::Full => format!("{} rejected, another device is connected", name),
+                Reason::Replaced => format!("{} disconnected for new device", name),
+            });
+            status.show();
+            let status = status.clone();
+            gtk::timeout_add_seconds(NOTICE_TIMEOUT, move || {
+                status.hide();
+                glib::Continue(false)
+            });
+        }
+        Event::CmdFailed(cmd, err) => {
+            core.show_message(Message::Error(format!(
+                "Bluetooth command failed: {:?}\n\n{}",
+                cmd, err
+            )));
+
+            // Resync state shown on page
+            if let Err(err) = core.bluetooth.emit_state() {
+                error!(
+                    "Failed to invoke command to emit bluetooth state: {:?}",
+                    err
+                );
+            }
         }
         _ => {}
     }
@@ -127,17 +268,188 @@ fn handle_bluetooth_event(
     glib::Continue(true)
 }
 
+/// Buttons for actions on the selected device.
+struct DeviceActions {
+    disconnect: gtk::Button,
+    forget: gtk::Button,
+    block: gtk::Button,
+    alias: gtk::Entry,
+    rename: gtk::Button,
+
+    /// Address of device the actions are shown for.
+    address: Cell<Option<Address>>,
+}
+
+impl DeviceActions {
+    /// Build action buttons, add to given container.
+    fn build(container: &gtk::Box) -> Self {
+        let btns = gtk::Box::new(gtk::Orientation::Horizontal, SPACING);
+        container.add(&btns);
+
+        let actions = Self {
+            disconnect: gtk::Button::new_with_label("Disconnect"),
+            forget: gtk::Button::new_with_label("Forget"),
+            block: gtk::Button::new_with_label("Block"),
+            alias: gtk::EntryBuilder::new()
+                .placeholder_text("Custom name")
+                .hexpand(true)
+                .build(),
+            rename: gtk::Button::new_with_label("Rename"),
+            address: Cell::new(None),
+        };
+        btns.add(&actions.disconnect);
+        btns.add(&actions.forget);
+        btns.add(&actions.block);
+        btns.add(&actions.alias);
+        btns.add(&actions.rename);
+
+        actions
+    }
+
+    /// Connect action buttons to send driver commands for the selected device.
+    fn connect(&self, core: Arc<Core>, treeview: &gtk::TreeView) {
+        let selection = treeview.get_selection();
+
+        self.disconnect
+            .connect_clicked(clone!(@weak core, @strong selection => move |_| {
+                if let Some(device) = selected(&selection) {
+                    send_cmd(&core, DriverCmd::Disconnect(device.address));
+                }
+            }));
+        self.forget
+            .connect_clicked(clone!(@weak core, @strong selection => move |_| {
+                let device = match selected(&selection) {
+                    Some(device) => device,
+                    None => return,
+                };
+                let closure_core = Arc::downgrade(&core);
+                let text = format!("Forget {}? It must be paired again to connect.", device.name);
+                core.confirm(Confirm::new(text, move |accept| {
+                    if !accept {
+                        return;
+                    }
+                    if let Some(core) = closure_core.upgrade() {
+                        send_cmd(&core, DriverCmd::Forget(device.address));
+                    }
+                }));
+            }));
+        self.block
+            .connect_clicked(clone!(@weak core, @strong selection => move |_| {
+                if let Some(device) = selected(&selection) {
+                    send_cmd(
+                        &core,
+                        if device.blocked {
+                            DriverCmd::Unblock(device.address)
+                        } else {
+                            DriverCmd::Block(device.address)
+                        },
+                    );
+                }
+            }));
+        let alias = self.alias.clone();
+        self.rename
+            .connect_clicked(clone!(@weak core, @strong selection => move |_| {
+                if let Some(device) = selected(&selection) {
+                    let name = alias.get_text().map(|name| name.to_string());
+                    send_cmd(&core, DriverCmd::SetAlias(device.address, name));
+                }
+            }));
+    }
+
+    /// Update action buttons for selected device.
+    ///
+    /// The custom name is only reset when another device is selected, to not discard typed text
+    /// when the device list updates.
+    fn update(&self, device: Option<Selected>) {
+        let selected = device.is_some();
+        self.disconnect
+            .set_sensitive(device.as_ref().map(|d| d.connected).unwrap_or(false));
+        self.forget.set_sensitive(selected);
+        self.block.set_sensitive(selected);
+        self.block
+            .set_label(if device.as_ref().map(|d| d.blocked).unwrap_or(false) {
+                "Unblock"
+            } else {
+                "Block"
+            });
+        self.alias.set_sensitive(selected);
+        let address = device.as_ref().map(|d| d.address);
+        if self.address.replace(address) != address || address.is_none() {
+            self.alias
+                .set_text(device.as_ref().map(|d| d.alias.as_str()).unwrap_or(""));
+        }
+        self.rename.set_sensitive(selected);
+    }
+}
+
+/// Send bluetooth driver command.
+fn send_cmd(core: &Core, cmd: DriverCmd) {
+    if let Err(err) = core.bluetooth.send_cmd(cmd) {
+        error!("Failed to send bluetooth command: {:?}", err);
+    }
+}
+
+/// Selected device in the device list.
+struct Selected {
+    address: Address,
+    name: String,
+    connected: bool,
+    blocked: bool,
+    alias: String,
+}
+
+/// Get device selected in device list.
+fn selected(selection: &gtk::TreeSelection) -> Option<Selected> {
+    let (model, iter) = selection.get_selected()?;
+    let address = model
+        .get_value(&iter, Column::Address as i32)
+        .get::<String>()
+        .ok()
+        .flatten()?;
+    Some(Selected {
+        address: util::parse_address(&address)?,
+        name: model
+            .get_value(&iter, Column::Name as i32)
+            .get::<String>()
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| address.clone()),
+        connected: model
+            .get_value(&iter, Column::Connected as i32)
+            .get_some::<bool>()
+            .unwrap_or(false),
+        blocked: model
+            .get_value(&iter, Column::Blocked as i32)
+            .get_some::<bool>()
+            .unwrap_or(false),
+        alias: model
+            .get_value(&iter, Column::Alias as i32)
+            .get::<String>()
+            .ok()
+            .flatten()
+            .unwrap_or_default(),
+    })
+}
+
 #[repr(i32)]
 enum Column {
     Name = 0,
     Address,
     Connected,
+    Blocked,
+    Alias,
+    Icon,
+    Audio,
 }
 
-const COLUMNS: [u32; 3] = [
+const COLUMNS: [u32; 7] = [
     Column::Name as u32,
     Column::Address as u32,
     Column::Connected as u32,
+    Column::Blocked as u32,
+    Column::Alias as u32,
+    Column::Icon as u32,
+    Column::Audio as u32,
 ];
 
 /// Build device list.
@@ -146,6 +458,10 @@ fn build_list() -> (gtk::TreeView, gtk::ListStore) {
         glib::types::Type::String,
         glib::types::Type::String,
         glib::types::Type::Bool,
+        glib::types::Type::Bool,
+        glib::types::Type::String,
+        glib::types::Type::String,
+        glib::types::Type::Bool,
     ]);
 
     let treeview = gtk::TreeViewBuilder::new()
@@ -154,8 +470,11 @@ fn build_list() -> (gtk::TreeView, gtk::ListStore) {
         .build();
 
     {
+        let icon_renderer = gtk::CellRendererPixbuf::new();
         let renderer = gtk::CellRendererText::new();
         let column = gtk::TreeViewColumn::new();
+        column.pack_start(&icon_renderer, false);
+        column.add_attribute(&icon_renderer, "icon-name", Column::Icon as i32);
         column.pack_start(&renderer, true);
         column.set_title("Device name");
         column.add_attribute(&renderer, "text", Column::Name as i32);
@@ -188,21 +507,105 @@ fn build_list() -> (gtk::TreeView, gtk::ListStore) {
         treeview.append_column(&column);
     }
 
+    {
+        let renderer = gtk::CellRendererToggle::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Blocked");
+        column.add_attribute(&renderer, "active", Column::Blocked as i32);
+        treeview.append_column(&column);
+    }
+
+    {
+        let renderer = gtk::CellRendererToggle::new();
+        let column = gtk::TreeViewColumn::new();
+        column.pack_start(&renderer, true);
+        column.set_title("Audio");
+        column.add_attribute(&renderer, "active", Column::Audio as i32);
+        treeview.append_column(&column);
+    }
+
     (treeview, store)
 }
 
 /// Update device list with new device information.
-fn update_list(store: &Rc<gtk::ListStore>, devices: DeviceList) {
-    store.clear();
-    for device in devices.iter() {
-        store.set(
-            &store.append(),
-            &COLUMNS,
-            &[
-                &device.name.as_deref().unwrap_or("?").to_value(),
-                &device.address_string().to_value(),
-                &device.connected.to_value(),
-            ],
-        );
+///
+/// Rows are updated in place rather than rebuilt, so the selected device stays selected.
+fn update_list(store: &gtk::ListStore, devices: DeviceList) {
+    // Update rows of listed devices, remove the others
+    let mut listed = Vec::new();
+    if let Some(iter) = store.get_iter_first() {
+        loop {
+            let device = store
+                .get_value(&iter, Column::Address as i32)
+                .get::<String>()
+                .ok()
+                .flatten()
+                .and_then(|address| util::parse_address(&address))
+                .and_then(|address| devices.get(address));
+            let more = match device {
+                Some(device) => {
+                    set_row(store, &iter, device);
+                    listed.push(device.address);
+                    store.iter_next(&iter)
+                }
+                None => store.remove(&iter),
+            };
+            if !more {
+                break;
+            }
+        }
     }
+
+    // Add new devices
+    for device in devices.iter().filter(|d| !listed.contains(&d.address)) {
+        set_row(store, &store.append(), device);
+    }
+}
+
+/// Set device list row to given device.
+fn set_row(store: &gtk::ListStore, iter: &gtk::TreeIter, device: &Device) {
+    store.set(
+        iter,
+        &COLUMNS,
+        &[
+            &device.display_name().unwrap_or("?").to_value(),
+            &device.address_string().to_value(),
+            &device.connected.to_value(),
+            &device.blocked.to_value(),
+            &device.alias.as_deref().unwrap_or("").to_value(),
+            &device_icon(device.kind()).to_value(),
+            &device.supports_a2dp().unwrap_or(false).to_value(),
+        ],
+    );
+}
+
+/// Get icon name for device kind.
+fn device_icon(kind: DeviceKind) -> &'static str {
+    match kind {
+        DeviceKind::Phone => "phone",
+        DeviceKind::Computer => "computer",
+        DeviceKind::Headset => "audio-headset",
+        DeviceKind::Unknown => "bluetooth",
+    }
+}
+
+/// Get name of device in device list by address string, the address if unknown.
+fn device_name(store: &gtk::ListStore, address: &str) -> String {
+    let mut name = None;
+    store.foreach(|model, _, iter| {
+        let value = |column: Column| {
+            model
+                .get_value(iter, column as i32)
+                .get::<String>()
+                .ok()
+                .flatten()
+        };
+        if value(Column::Address).as_deref() == Some(address) {
+            name = value(Column::Name);
+            return true;
+        }
+        false
+    });
+    name.unwrap_or_else(|| address.into())
 }
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use gtk::{prelude::*, PositionType};
+
+use crate::app::Core;
+use crate::pages::PageType;
+
+use super::page::Helper;
+use super::page::Page;
+
+const PAGE_TYPE: PageType = PageType::Equalizer;
+const PAGE_NAME: &str = "Equalizer";
+const SPACING: i32 = 8;
+const CONTROL_SPACING: i32 = 16;
+
+/// Highest band boost or cut in dB.
+const BAND_RANGE: f64 = 12.0;
+
+/// Highest bass boost in dB.
+const BASS_BOOST_MAX: f64 = 12.0;
+
+/// Equalizer page.
+pub struct Equalizer {
+    /// Page container
+    container: gtk::Grid,
+}
+
+impl Equalizer {
+    /// Constructor.
+    pub fn new(core: Arc<Core>) -> Self {
+        // Create the page instance
+        let page = Self {
+            container: Helper::create_page_container(),
+        };
+
+        // Build the ui
+        page.build_page(core);
+
+        page
+    }
+}
+
+impl Page for Equalizer {
+    fn page_type(&self) -> PageType {
+        PAGE_TYPE
+    }
+
+    fn page_name(&self) -> &'static str {
+        &PAGE_NAME
+    }
+
+    fn build_page(&self, core: Arc<Core>) {
+        let settings = core.dsp.settings();
+
+        let gbox = gtk::BoxBuilder::new()
+            .expand(true)
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(SPACING)
+            .margin(SPACING)
+            .build();
+        self.container.add(&gbox);
+
+        // Preset selection and enable switch
+        let header = gtk::BoxBuilder::new()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(CONTROL_SPACING)
+            .build();
+        gbox.add(&header);
+
+        header.add(&gtk::Label::new(Some("Preset")));
+        let presets = gtk::ComboBoxText::new();
+        for name in core.config.dsp.presets().keys() {
+            presets.append(Some(name), name);
+        }
+        presets.set_active_id(Some(&settings.preset_name));
+        header.add(&presets);
+
+        let enabled = gtk::SwitchBuilder::new()
+            .active(settings.enabled)
+            .valign(gtk::Align::Center)
+            .build();
+        header.pack_end(&enabled, false, false, 0);
+        header.pack_end(&gtk::Label::new(Some("Enabled")), false, false, 0);
+
+        let core_enabled = core.clone();
+        enabled.connect_property_active_notify(move |switch| {
+            let active = switch.get_active();
+            core_enabled
+                .dsp
+                .update(|settings| settings.enabled = active);
+        });
+
+        // Band sliders, rebuilt when changing preset
+        let controls = gtk::BoxBuilder::new()
+            .expand(true)
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(CONTROL_SPACING)
+            .build();
+        gbox.add(&controls);
+        build_controls(&core, &controls);
+
+        presets.connect_changed(move |presets| {
+            let name = match presets.get_active_id() {
+                Some(name) => name.to_string(),
+                None => return,
+            };
+            let preset = match core.config.dsp.presets().remove(&name) {
+                Some(preset) => preset,
+                None => return,
+            };
+            core.dsp.update(|settings| {
+                settings.preset_name = name;
+                settings.preset = preset;
+            });
+
+            for child in controls.get_children() {
+                controls.remove(&child);
+            }
+            build_controls(&core, &controls);
+            controls.show_all();
+        });
+    }
+
+    fn gtk_widget(&self) -> &gtk::Grid {
+        &self.container
+    }
+}
+
+/// Build sliders for all bands and bass boost, and loudness switch.
+fn build_controls(core: &Arc<Core>, controls: &gtk::Box) {
+    let preset = core.dsp.settings().preset;
+
+    for (i, band) in preset.bands.iter().enumerate() {
+        let closure_core = core.clone();
+        controls.add(&build_slider(
+            &format_freq(band.freq),
+            -BAND_RANGE,
+            BAND_RANGE,
+            band.gain,
+            move |gain| {
+                closure_core.dsp.update(|settings| {
+                    if let Some(band) = settings.preset.bands.get_mut(i) {
+                        band.gain = gain;
+                    }
+                })
+            },
+        ));
+    }
+
+    let closure_core = core.clone();
+    controls.add(&build_slider(
+        "Bass",
+        0.0,
+        BASS_BOOST_MAX,
+        preset.bass_boost,
+        move |gain| {
+            closure_core
+                .dsp
+                .update(|settings| settings.preset.bass_boost = gain)
+        },
+    ));
+
+    // Loudness compensation switch
+    let gbox = gtk::BoxBuilder::new()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(SPACING)
+        .valign(gtk::Align::End)
+        .build();
+    let loudness = gtk::SwitchBuilder::new()
+        .active(preset.loudness)
+        .halign(gtk::Align::Center)
+        .build();
+    let closure_core = core.clone();
+    loudness.connect_property_active_notify(move |switch| {
+        let active = switch.get_active();
+        closure_core
+            .dsp
+            .update(|settings| settings.preset.loudness = active);
+    });
+    gbox.add(&loudness);
+    gbox.add(&gtk::Label::new(Some("Loudness")));
+    controls.pack_end(&gbox, false, false, 0);
+}
+
+/// Build vertical gain slider with label, invoking callback with gain in dB on change.
+fn build_slider<F>(label: &str, min: f64, max: f64, value: f32, f: F) -> gtk::Box
+where
+    F: Fn(f32) + 'static,
+{
+    let gbox = gtk::BoxBuilder::new()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(SPACING)
+        .build();
+
+    let slider = gtk::Scale::new_with_range(gtk::Orientation::Vertical, min, max, 0.5);
+    slider.set_value(value as f64);
+    slider.add_mark(0.0, PositionType::Right, None);
+    slider.set_vexpand(true);
+    slider.set_value_pos(PositionType::Bottom);
+    slider.set_inverted(true);
+    slider.connect_format_value(|_, value| format!("{:+.1}", value));
+    slider.connect_value_changed(move |slider| f(slider.get_value() as f32));
+    gbox.add(&slider);
+
+    gbox.add(&gtk::Label::new(Some(label)));
+
+    gbox
+}
+
+/// Format frequency for band label.
+fn format_freq(freq: f32) -> String {
+    if freq >= 1000.0 {
+        format!("{}k", freq / 1000.0)
+    } else {
+        format!("{}", freq)
+    }
+}
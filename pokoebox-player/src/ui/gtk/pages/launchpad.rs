@@ -79,13 +79,23 @@ impl Page for Launchpad {
         btn_bluetooth.set_sensitive(false);
         btns.attach(&btn_bluetooth, 1, 0, 1, 1);
 
-        let btn_c = gtk::Button::new_with_label("");
-        btn_c.set_sensitive(false);
-        btns.attach(&btn_c, 2, 0, 1, 1);
+        let btn_settings = gtk::Button::new_with_label("Settings");
+        btn_settings.connect_clicked(clone!(@weak core => move |_| {
+            core.actions.invoke(
+                GotoPageAction::new(PageType::Settings),
+                core.clone(),
+            );
+        }));
+        btns.attach(&btn_settings, 2, 0, 1, 1);
 
-        let btn_d = gtk::Button::new_with_label("");
-        btn_d.set_sensitive(false);
-        btns.attach(&btn_d, 0, 1, 1, 1);
+        let btn_equalizer = gtk::Button::new_with_label("Equalizer");
+        btn_equalizer.connect_clicked(clone!(@weak core => move |_| {
+            core.actions.invoke(
+                GotoPageAction::new(PageType::Equalizer),
+                core.clone(),
+            );
+        }));
+        btns.attach(&btn_equalizer, 0, 1, 1, 1);
 
         let btn_soundboard = gtk::Button::new_with_label("Soundboard");
         btn_soundboard.connect_clicked(clone!(@weak core => move |_| {
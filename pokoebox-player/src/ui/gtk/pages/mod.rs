@@ -2,9 +2,11 @@ pub mod about;
 #[cfg(feature = "bluetooth")]
 pub mod bluetooth;
 pub mod clock;
+pub mod equalizer;
 pub mod launchpad;
 pub mod player;
 pub mod power;
+pub mod settings;
 pub mod soundboard;
 pub mod test;
 pub mod volume;
@@ -15,9 +17,11 @@ pub use about::About;
 #[cfg(feature = "bluetooth")]
 pub use bluetooth::Bluetooth;
 pub use clock::Clock;
+pub use equalizer::Equalizer;
 pub use launchpad::Launchpad;
 pub use player::Player;
 pub use power::Power;
+pub use settings::Settings;
 pub use soundboard::Soundboard;
 pub use test::Test;
 pub use volume::Volume;
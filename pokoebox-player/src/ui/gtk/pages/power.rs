@@ -1,8 +1,11 @@
 use std::sync::Arc;
 
+use glib::clone;
 use gtk::prelude::*;
 
+use crate::action::actions::ShutdownAction;
 use crate::app::Core;
+use crate::message::Confirm;
 use crate::pages::PageType;
 
 use super::page::Helper;
@@ -120,6 +123,21 @@ impl Page for Power {
             .build();
         grid.attach(&label_current, 1, 4, 1, 1);
 
+        // Shutdown button, powers down audio output and exits after confirmation
+        let btn_shutdown = gtk::Button::new_with_label("Shut down");
+        btn_shutdown.connect_clicked(clone!(@weak core => move |_| {
+            let closure_core = Arc::downgrade(&core);
+            core.confirm(Confirm::new("Shut down PokoeBox?".into(), move |accept| {
+                if !accept {
+                    return;
+                }
+                if let Some(core) = closure_core.upgrade() {
+                    core.actions.invoke(ShutdownAction, core.clone());
+                }
+            }));
+        }));
+        grid.attach(&btn_shutdown, 0, 5, 2, 1);
+
         // Update labels on power events
         #[cfg(feature = "rpi")]
         {
@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use gtk::prelude::*;
+use pokoebox_audio::volume::{Cmd, ControlHandle, Event, SettingKind, SettingProps};
+
+use crate::app::Core;
+use crate::pages::PageType;
+
+use super::page::Helper;
+use super::page::Page;
+
+const PAGE_TYPE: PageType = PageType::Settings;
+const PAGE_NAME: &str = "Settings";
+const SPACING: u32 = 16;
+
+/// Setting widgets by setting, with their change signal handler.
+type Widgets = HashMap<ControlHandle, (SettingWidget, glib::signal::SignalHandlerId)>;
+
+/// Settings page.
+pub struct Settings {
+    /// Page container
+    container: gtk::Grid,
+}
+
+impl Settings {
+    /// Constructor.
+    pub fn new(core: Arc<Core>) -> Self {
+        // Create the page instance
+        let page = Self {
+            container: Helper::create_page_container(),
+        };
+
+        // Build the ui
+        page.build_page(core);
+
+        page
+    }
+}
+
+impl Page for Settings {
+    fn page_type(&self) -> PageType {
+        PAGE_TYPE
+    }
+
+    fn page_name(&self) -> &'static str {
+        &PAGE_NAME
+    }
+
+    fn build_page(&self, core: Arc<Core>) {
+        let scroll_window = gtk::ScrolledWindowBuilder::new().expand(true).build();
+
+        let gbox = gtk::BoxBuilder::new()
+            .expand(true)
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(SPACING as i32)
+            .margin(SPACING as i32)
+            .build();
+        scroll_window.add(&gbox);
+        self.container.add(&scroll_window);
+
+        // Audio settings section
+        let header = gtk::LabelBuilder::new()
+            .label("<b>Audio</b>")
+            .use_markup(true)
+            .halign(gtk::Align::Start)
+            .build();
+        gbox.add(&header);
+
+        let grid = gtk::GridBuilder::new()
+            .row_spacing(SPACING)
+            .column_spacing(SPACING)
+            .build();
+        gbox.add(&grid);
+
+        // Query list of mixer settings, show none if the mixer doesn't answer
+        let settings = match core.volume.query_settings() {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                warn!("Mixer didn't report audio settings in time");
+                HashMap::new()
+            }
+            Err(err) => {
+                error!("Failed to query audio settings: {:?}", err);
+                HashMap::new()
+            }
+        };
+        let widgets = Rc::new(RefCell::new(HashMap::new()));
+        build_audio_settings(&core, &grid, settings, &mut widgets.borrow_mut());
+
+        // Bluetooth settings section
+        #[cfg(feature = "bluetooth")]
+        build_bluetooth_settings(core.clone(), &gbox);
+
+        // Handle volume manager events
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        core.volume.mixer.events.register_callback(move |event| {
+            if let Err(err) = tx.send(event) {
+                error!("Failed to send volume manager event to Glib: {:?}", err);
+            }
+        });
+        rx.attach(None, move |event| {
+            handle_volume_event(&core, event, &grid, &widgets)
+        });
+    }
+
+    fn gtk_widget(&self) -> &gtk::Grid {
+        &self.container
+    }
+}
+
+fn handle_volume_event(
+    core: &Arc<Core>,
+    event: Event,
+    grid: &gtk::Grid,
+    widgets: &RefCell<Widgets>,
+) -> glib::Continue {
+    match event {
+        // Update widget on setting change
+        Event::Setting(setting, value) => {
+            if let Some((widget, change_handler)) = widgets.borrow().get(&setting) {
+                widget.block_signal(change_handler);
+                widget.set_value(value);
+                widget.unblock_signal(change_handler);
+            }
+        }
+        // Rebuild widgets if settings changed, such as when a sound card is plugged in
+        Event::Settings(settings) => {
+            let mut widgets = widgets.borrow_mut();
+            if settings.len() != widgets.len() || settings.keys().any(|s| !widgets.contains_key(s))
+            {
+                for child in grid.get_children() {
+                    grid.remove(&child);
+                }
+                widgets.clear();
+                build_audio_settings(core, grid, settings, &mut widgets);
+                grid.show_all();
+            }
+        }
+        _ => {}
+    }
+
+    glib::Continue(true)
+}
+
+/// Add a row with a label and widget for all given mixer settings.
+fn build_audio_settings(
+    core: &Arc<Core>,
+    grid: &gtk::Grid,
+    settings: HashMap<ControlHandle, SettingProps>,
+    widgets: &mut Widgets,
+) {
+    if settings.is_empty() {
+        let label = gtk::LabelBuilder::new()
+            .label("<i>Sound card has no settings</i>")
+            .use_markup(true)
+            .halign(gtk::Align::Start)
+            .build();
+        grid.attach(&label, 0, 0, 2, 1);
+        return;
+    }
+
+    let mut settings: Vec<_> = settings.into_iter().collect();
+    settings.sort_by_key(|(_, props)| props.name.clone());
+
+    for (row, (setting, props)) in settings.into_iter().enumerate() {
+        let label = gtk::LabelBuilder::new()
+            .label(props.name.as_deref().unwrap_or("?"))
+            .halign(gtk::Align::Start)
+            .build();
+        grid.attach(&label, 0, row as i32, 1, 1);
+
+        let widget = SettingWidget::build(&props);
+        grid.attach(widget.widget(), 1, row as i32, 1, 1);
+
+        // Update setting on widget change
+        let closure_core = core.clone();
+        let closure_setting = setting.clone();
+        let changed_handler = widget.connect_changed(move |value| {
+            if let Err(err) = closure_core
+                .volume
+                .send_cmd(Cmd::SetSetting(closure_setting.clone(), value))
+            {
+                error!("Failed to change audio setting: {:?}", err);
+            }
+        });
+
+        widgets.insert(setting, (widget, changed_handler));
+    }
+}
+
+/// Add section to change the name and class of device bluetooth presents itself with.
+#[cfg(feature = "bluetooth")]
+fn build_bluetooth_settings(core: Arc<Core>, gbox: &gtk::Box) {
+    use pokoebox_bluetooth::driver::DriverCmd;
+    use pokoebox_bluetooth::identity::{SpeakerClass, SHORT_NAME_MAX};
+    use pokoebox_bluetooth::manager::Event as BluetoothEvent;
+    use pokoebox_bluetooth::Identity;
+
+    use crate::message::Message;
+
+    let header = gtk::LabelBuilder::new()
+        .label("<b>Bluetooth</b>")
+        .use_markup(true)
+        .halign(gtk::Align::Start)
+        .build();
+    gbox.add(&header);
+
+    let grid = gtk::GridBuilder::new()
+        .row_spacing(SPACING)
+        .column_spacing(SPACING)
+        .build();
+    gbox.add(&grid);
+
+    let name = gtk::EntryBuilder::new().hexpand(true).build();
+    let short_name = gtk::EntryBuilder::new()
+        .placeholder_text(&format!("Same as name, at most {} bytes", SHORT_NAME_MAX))
+        .build();
+    let class = gtk::ComboBoxText::new();
+    for class_item in SpeakerClass::ALL.iter() {
+        class.append_text(class_item.name());
+    }
+    let rows: [(&str, &gtk::Widget); 3] = [
+        ("Name", name.upcast_ref()),
+        ("Short name", short_name.upcast_ref()),
+        ("Class", class.upcast_ref()),
+    ];
+    for (row, (label, widget)) in rows.iter().enumerate() {
+        let label = gtk::LabelBuilder::new()
+            .label(label)
+            .halign(gtk::Align::Start)
+            .build();
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(*widget, 1, row as i32, 1, 1);
+    }
+
+    // Apply changes without restarting, remembered by the bluetooth manager
+    let apply = gtk::ButtonBuilder::new()
+        .label("Apply")
+        .halign(gtk::Align::Start)
+        .build();
+    grid.attach(&apply, 1, rows.len() as i32, 1, 1);
+    apply.connect_clicked(
+        clone!(@weak core, @strong name, @strong short_name, @strong class => move |_| {
+            let identity = Identity {
+                name: name
+                    .get_text()
+                    .map(|name| name.trim().to_owned())
+                    .unwrap_or_default(),
+                short_name: short_name
+                    .get_text()
+                    .map(|name| name.trim().to_owned())
+                    .filter(|name| !name.is_empty()),
+                class: class
+                    .get_active()
+                    .and_then(|index| SpeakerClass::ALL.get(index as usize).copied())
+                    .unwrap_or_default(),
+            };
+
+            // The driver only reports invalid identities in its log, tell the user here
+            if let Err(err) = identity.validate() {
+                core.show_message(Message::Error(format!("Failed to apply: {}", err)));
+                return;
+            }
+
+            if let Err(err) = core.bluetooth.send_cmd(DriverCmd::SetIdentity(identity)) {
+                error!("Failed to send bluetooth identity command: {:?}", err);
+            }
+        }),
+    );
+
+    // Show current identity
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+    core.bluetooth.events.register_callback(move |event| {
+        if let BluetoothEvent::Identity(identity) = event {
+            if let Err(err) = tx.send(identity) {
+                error!("Failed to send bluetooth identity to Glib: {:?}", err);
+            }
+        }
+    });
+    rx.attach(None, move |identity| {
+        name.set_text(&identity.name);
+        short_name.set_text(identity.short_name.as_deref().unwrap_or(""));
+        class.set_active(
+            SpeakerClass::ALL
+                .iter()
+                .position(|class| *class == identity.class)
+                .map(|index| index as u32),
+        );
+        glib::Continue(true)
+    });
+    if let Err(err) = core.bluetooth.emit_state() {
+        error!(
+            "Failed to invoke command to emit bluetooth state: {:?}",
+            err
+        );
+    }
+}
+
+/// Widget to change a mixer setting.
+enum SettingWidget {
+    /// Switch for on/off settings.
+    Switch(gtk::Switch),
+
+    /// Combo box for enumerated settings.
+    Combo(gtk::ComboBoxText),
+}
+
+impl SettingWidget {
+    /// Build widget for given setting.
+    fn build(props: &SettingProps) -> Self {
+        let widget = match &props.kind {
+            SettingKind::Switch => {
+                SettingWidget::Switch(gtk::SwitchBuilder::new().halign(gtk::Align::Start).build())
+            }
+            SettingKind::Enum(items) => {
+                let combo = gtk::ComboBoxText::new();
+                for item in items {
+                    combo.append_text(item);
+                }
+                SettingWidget::Combo(combo)
+            }
+        };
+        widget.set_value(props.init_value);
+        widget
+    }
+
+    /// Get GTK widget.
+    fn widget(&self) -> &gtk::Widget {
+        match self {
+            SettingWidget::Switch(switch) => switch.upcast_ref(),
+            SettingWidget::Combo(combo) => combo.upcast_ref(),
+        }
+    }
+
+    /// Set displayed value.
+    fn set_value(&self, value: u32) {
+        match self {
+            SettingWidget::Switch(switch) => switch.set_active(value > 0),
+            SettingWidget::Combo(combo) => combo.set_active(Some(value)),
+        }
+    }
+
+    /// Connect handler invoked with new value when changed by the user.
+    fn connect_changed<F>(&self, f: F) -> glib::signal::SignalHandlerId
+    where
+        F: Fn(u32) + 'static,
+    {
+        match self {
+            SettingWidget::Switch(switch) => {
+                switch.connect_property_active_notify(move |switch| f(switch.get_active() as u32))
+            }
+            SettingWidget::Combo(combo) => combo.connect_changed(move |combo| {
+                if let Some(value) = combo.get_active() {
+                    f(value);
+                }
+            }),
+        }
+    }
+
+    fn block_signal(&self, handler: &glib::signal::SignalHandlerId) {
+        match self {
+            SettingWidget::Switch(switch) => switch.block_signal(handler),
+            SettingWidget::Combo(combo) => combo.block_signal(handler),
+        }
+    }
+
+    fn unblock_signal(&self, handler: &glib::signal::SignalHandlerId) {
+        match self {
+            SettingWidget::Switch(switch) => switch.unblock_signal(handler),
+            SettingWidget::Combo(combo) => combo.unblock_signal(handler),
+        }
+    }
+}
@@ -1,10 +1,14 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
+use glib::clone;
 use gtk::prelude::*;
 
 use crate::app::Core;
 use crate::pages::PageType;
-use crate::soundeffecter::Sound;
+use crate::sampler::{Event as SamplerEvent, ACTION_BUTTONS};
+use crate::soundeffecter::{Sound, SoundHandle};
+use crate::sounds::{self, Pad};
 
 use super::page::Helper;
 use super::page::Page;
@@ -13,6 +17,15 @@ const PAGE_TYPE: PageType = PageType::Soundboard;
 const PAGE_NAME: &str = "Soundboard";
 const BUTTON_SPACING: u32 = 16;
 const BUTTON_GRID_SIZE: (i32, i32) = (450, 260);
+const BUTTON_HEIGHT: i32 = 122;
+const BUTTON_COLUMNS: usize = 3;
+const BUTTON_ROWS: usize = 2;
+
+/// Tempo range for sampler recordings in beats per minute.
+const BPM_RANGE: (f64, f64) = (40.0, 240.0);
+
+/// Style class for pads that are playing.
+const PLAYING_CLASS: &str = "playing";
 
 /// Soundboard.
 pub struct Soundboard {
@@ -48,39 +61,93 @@ impl Page for Soundboard {
         self.container.set_halign(gtk::Align::Center);
         self.container.set_valign(gtk::Align::Center);
 
-        // Create a button grid
+        // Create a button grid, scrollable if there are many sounds
+        let scroll_window = gtk::ScrolledWindowBuilder::new()
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .build();
+        scroll_window.set_size_request(BUTTON_GRID_SIZE.0, BUTTON_GRID_SIZE.1);
+        self.container.add(&scroll_window);
+
         let btns = gtk::Grid::new();
         btns.set_row_spacing(BUTTON_SPACING);
         btns.set_column_spacing(BUTTON_SPACING);
         btns.set_row_homogeneous(true);
         btns.set_column_homogeneous(true);
-        btns.set_size_request(BUTTON_GRID_SIZE.0, BUTTON_GRID_SIZE.1);
-        self.container.add(&btns);
+        btns.set_vexpand(true);
+        scroll_window.add(&btns);
 
-        // Add some buttons
-        let btn_kick = gtk::Button::new_with_label("Kick 30Hz");
-        btn_kick.connect_clicked(play(core.clone(), Sound::Kick));
-        btns.attach(&btn_kick, 0, 0, 1, 1);
+        // Playback controls
+        let controls = gtk::BoxBuilder::new()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(BUTTON_SPACING as i32)
+            .margin_top(BUTTON_SPACING as i32)
+            .build();
+        self.container.attach(&controls, 0, 1, 1, 1);
 
-        let btn_guitar = gtk::Button::new_with_label("Guitar");
-        btn_guitar.connect_clicked(play(core.clone(), Sound::Guitar));
-        btns.attach(&btn_guitar, 1, 0, 1, 1);
+        let loop_btn = gtk::ToggleButton::new_with_label("Loop");
+        controls.pack_start(&loop_btn, true, true, 0);
 
-        let btn_xp = gtk::Button::new_with_label("XP");
-        btn_xp.connect_clicked(play(core.clone(), Sound::Xp));
-        btns.attach(&btn_xp, 2, 0, 1, 1);
+        let stop_btn = gtk::Button::new_with_label("Stop all");
+        stop_btn.connect_clicked(clone!(@weak core => move |_| {
+            // Stop looping pattern first, it would start pads again
+            core.sampler.stop();
+            core.effecter.stop_all();
+        }));
+        controls.pack_start(&stop_btn, true, true, 0);
 
-        let btn_jbl = gtk::Button::new_with_label("JBL");
-        btn_jbl.connect_clicked(play(core.clone(), Sound::Jbl));
-        btns.attach(&btn_jbl, 0, 1, 1, 1);
+        // Sampler controls
+        let sampler = SamplerControls::build(&core);
+        self.container.attach(&sampler.container, 0, 2, 1, 1);
 
-        let btn_car = gtk::Button::new_with_label("Mustang");
-        btn_car.connect_clicked(play(core.clone(), Sound::MustangStart));
-        btns.attach(&btn_car, 1, 1, 1, 1);
+        // Add button for each sound, fill up last row with empty buttons
+        let pads = sounds::load(&core.config.soundboard);
+        let mut pad_btns = Vec::new();
+        let slots = pads.len().max(BUTTON_ROWS * BUTTON_COLUMNS);
+        let slots = slots.div_ceil(BUTTON_COLUMNS) * BUTTON_COLUMNS;
+        for i in 0..slots {
+            let btn = match pads.get(i) {
+                Some(pad) => {
+                    let btn = gtk::Button::new_with_label(&pad.label);
+                    style_button(&btn, pad.color.as_deref());
+                    btn.connect_clicked(
+                        clone!(@weak core, @strong pad, @weak loop_btn => move |_| {
+                            play(&core, &pad, &loop_btn);
+                        }),
+                    );
+                    pad_btns.push((pad.sound.clone(), btn.clone()));
+                    btn
+                }
+                None => {
+                    let btn = gtk::Button::new_with_label("");
+                    btn.set_sensitive(false);
+                    btn
+                }
+            };
+            btn.set_size_request(-1, BUTTON_HEIGHT);
+            btns.attach(
+                &btn,
+                (i % BUTTON_COLUMNS) as i32,
+                (i / BUTTON_COLUMNS) as i32,
+                1,
+                1,
+            );
+        }
 
-        let btn_f = gtk::Button::new_with_label("");
-        btn_f.set_sensitive(false);
-        btns.attach(&btn_f, 2, 1, 1, 1);
+        // Highlight pads that are playing
+        update_playing(&core, &pad_btns);
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        core.effecter.events.register_callback(move |event| {
+            if let Err(err) = tx.send(event) {
+                error!("Failed to send sound effecter event to Glib: {:?}", err);
+            }
+        });
+        rx.attach(
+            None,
+            clone!(@weak core => @default-return glib::Continue(false), move |_| {
+                update_playing(&core, &pad_btns);
+                glib::Continue(true)
+            }),
+        );
     }
 
     fn gtk_widget(&self) -> &gtk::Grid {
@@ -88,11 +155,266 @@ impl Page for Soundboard {
     }
 }
 
-/// Helper to easily play sounds from buttons.
-fn play(core: Arc<Core>, sound: Sound) -> impl Fn(&gtk::Button) + 'static {
-    move |_| {
-        if let Err(err) = core.effecter.play(sound) {
-            error!("Failed to play soundboard sound: {:?}", err);
+/// Play pad sound, for pad buttons.
+///
+/// Looping sounds are stopped instead if already playing.
+fn play(core: &Core, pad: &Pad, loop_btn: &gtk::ToggleButton) {
+    let looping = pad.looping || loop_btn.get_active();
+    if looping {
+        let playing: Vec<_> = core
+            .effecter
+            .playing()
+            .into_iter()
+            .filter(|handle| handle.sound() == &pad.sound && handle.is_looping())
+            .collect();
+        if !playing.is_empty() {
+            playing.iter().for_each(SoundHandle::stop);
+            return;
+        }
+    }
+
+    match core.effecter.play_with(&pad.sound, pad.gain, looping) {
+        Ok(_) => core.sampler.record(&pad.sound, pad.gain),
+        Err(err) => error!("Failed to play soundboard sound: {:?}", err),
+    }
+}
+
+/// Mark pad buttons of currently playing sounds.
+fn update_playing(core: &Core, pad_btns: &[(Sound, gtk::Button)]) {
+    let playing: HashSet<Sound> = core
+        .effecter
+        .playing()
+        .into_iter()
+        .map(|handle| handle.sound().clone())
+        .collect();
+    for (sound, btn) in pad_btns {
+        let context = btn.get_style_context();
+        if playing.contains(sound) {
+            context.add_class(PLAYING_CLASS);
+        } else {
+            context.remove_class(PLAYING_CLASS);
+        }
+    }
+}
+
+/// Style pad button, with optional CSS background color.
+fn style_button(btn: &gtk::Button, color: Option<&str>) {
+    let mut css = format!(
+        "button.{} {{ box-shadow: inset 0 0 0 4px @theme_selected_bg_color; }}",
+        PLAYING_CLASS
+    );
+    if let Some(color) = color {
+        css += &format!(
+            "button {{ background-image: none; background-color: {}; }}",
+            color
+        );
+    }
+
+    let provider = gtk::CssProvider::new();
+    if let Err(err) = provider.load_from_data(css.as_bytes()) {
+        error!("Invalid soundboard button style '{}': {}", css, err);
+        return;
+    }
+    btn.get_style_context()
+        .add_provider(&provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+}
+
+/// Sampler controls, to record, loop and bind patterns.
+#[derive(Clone)]
+struct SamplerControls {
+    container: gtk::Box,
+    record: gtk::ToggleButton,
+    bpm: gtk::SpinButton,
+    patterns: gtk::ComboBoxText,
+    play: gtk::ToggleButton,
+    binding: gtk::ComboBoxText,
+    delete: gtk::Button,
+}
+
+impl SamplerControls {
+    /// Build sampler controls, kept in sync with the sampler.
+    fn build(core: &Arc<Core>) -> Self {
+        let container = gtk::BoxBuilder::new()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(BUTTON_SPACING as i32)
+            .margin_top(BUTTON_SPACING as i32)
+            .build();
+
+        let record = gtk::ToggleButton::new_with_label("Record");
+        container.add(&record);
+
+        let bpm = gtk::SpinButton::new_with_range(BPM_RANGE.0, BPM_RANGE.1, 1.0);
+        bpm.set_value(core.sampler.default_bpm());
+        container.add(&bpm);
+
+        let patterns = gtk::ComboBoxText::new();
+        patterns.set_hexpand(true);
+        container.add(&patterns);
+
+        let play = gtk::ToggleButton::new_with_label("Play");
+        container.add(&play);
+
+        let binding = gtk::ComboBoxText::new();
+        binding.append(Some("none"), "No button");
+        for button in 0..ACTION_BUTTONS {
+            binding.append(Some(&button.to_string()), &format!("Button {}", button + 1));
         }
+        container.add(&binding);
+
+        let delete = gtk::Button::new_with_label("Delete");
+        container.add(&delete);
+
+        let controls = Self {
+            container,
+            record,
+            bpm,
+            patterns,
+            play,
+            binding,
+            delete,
+        };
+        controls.update_patterns(core, None);
+        controls.connect(core.clone());
+        controls
     }
+
+    /// Connect control signals and sampler events.
+    fn connect(&self, core: Arc<Core>) {
+        let controls = self;
+        self.record
+            .connect_toggled(clone!(@weak core, @strong controls => move |record| {
+                let sampler = &core.sampler;
+                if record.get_active() && !sampler.is_recording() {
+                    sampler.start_recording();
+                } else if !record.get_active() && sampler.is_recording() {
+                    let name = sampler.stop_recording(controls.bpm.get_value());
+                    controls.update_patterns(&core, name.as_deref());
+                }
+            }));
+
+        self.patterns
+            .connect_changed(clone!(@weak core, @strong controls => move |_| {
+                controls.update_selected(&core)
+            }));
+
+        self.play
+            .connect_toggled(clone!(@weak core, @strong controls => move |play| {
+                let name = match controls.selected() {
+                    Some(name) => name,
+                    None => return,
+                };
+                let looping = core.sampler.looping().as_deref() == Some(&name);
+                if play.get_active() && !looping {
+                    core.sampler.play(&core, &name);
+                } else if !play.get_active() && looping {
+                    core.sampler.stop();
+                }
+            }));
+
+        self.binding
+            .connect_changed(clone!(@weak core, @strong controls => move |binding| {
+                let name = match controls.selected() {
+                    Some(name) => name,
+                    None => return,
+                };
+                let button = binding
+                    .get_active_id()
+                    .and_then(|id| id.as_str().parse::<usize>().ok());
+                if button == bound_button(&core, &name) {
+                    return;
+                }
+
+                // Pattern is bound to at most one button
+                for other in 0..ACTION_BUTTONS {
+                    if core.sampler.binding(other).as_deref() == Some(&name) {
+                        core.sampler.bind(other, None);
+                    }
+                }
+                if let Some(button) = button {
+                    core.sampler.bind(button, Some(name));
+                }
+            }));
+
+        self.delete
+            .connect_clicked(clone!(@weak core, @strong controls => move |_| {
+                if let Some(name) = controls.selected() {
+                    core.sampler.delete(&name);
+                }
+            }));
+
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        core.sampler.events.register_callback(move |event| {
+            if let Err(err) = tx.send(event) {
+                error!("Failed to send sampler event to Glib: {:?}", err);
+            }
+        });
+        rx.attach(
+            None,
+            clone!(@weak core, @strong controls => @default-return glib::Continue(false), move |event| {
+                match event {
+                    SamplerEvent::Recording(recording) => controls.record.set_active(recording),
+                    SamplerEvent::Looping(_) => controls.update_selected(&core),
+                    SamplerEvent::Patterns => {
+                        let selected = controls.selected();
+                        controls.update_patterns(&core, selected.as_deref());
+                    }
+                }
+                glib::Continue(true)
+            }),
+        );
+    }
+
+    /// Get name of selected pattern.
+    fn selected(&self) -> Option<String> {
+        self.patterns.get_active_id().map(|id| id.to_string())
+    }
+
+    /// Rebuild list of patterns, selecting given pattern or the first one.
+    fn update_patterns(&self, core: &Core, select: Option<&str>) {
+        let names = core.sampler.patterns();
+        self.patterns.remove_all();
+        for name in &names {
+            self.patterns.append(Some(name), name);
+        }
+        match select.filter(|select| names.iter().any(|name| name == select)) {
+            Some(select) => self.patterns.set_active_id(Some(select)),
+            None => self
+                .patterns
+                .set_active(if names.is_empty() { None } else { Some(0) }),
+        };
+        self.update_selected(core);
+    }
+
+    /// Update play state and binding for selected pattern.
+    fn update_selected(&self, core: &Core) {
+        let selected = self.selected();
+        for widget in &[
+            self.play.upcast_ref::<gtk::Widget>(),
+            self.binding.upcast_ref(),
+            self.delete.upcast_ref(),
+        ] {
+            widget.set_sensitive(selected.is_some());
+        }
+
+        let name = match selected {
+            Some(name) => name,
+            None => {
+                self.play.set_active(false);
+                return;
+            }
+        };
+        self.play
+            .set_active(core.sampler.looping().as_deref() == Some(&name));
+        let button = bound_button(core, &name);
+        self.binding.set_active_id(Some(
+            &button
+                .map(|button| button.to_string())
+                .unwrap_or_else(|| "none".into()),
+        ));
+    }
+}
+
+/// Get action button given pattern is bound to.
+fn bound_button(core: &Core, name: &str) -> Option<usize> {
+    (0..ACTION_BUTTONS).find(|button| core.sampler.binding(*button).as_deref() == Some(name))
 }
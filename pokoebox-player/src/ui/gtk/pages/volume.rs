@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use std::sync::Arc;
 
 use gtk::{prelude::*, PositionType};
-use pokoebox_audio::volume::{Cmd, ControlHandle, ControlProps, Event};
+use pokoebox_audio::volume::{Cmd, ControlHandle, ControlProps, Direction, Event, Role};
 
 use crate::app::Core;
 use crate::pages::PageType;
@@ -15,6 +17,9 @@ const PAGE_NAME: &str = "Volume";
 const SPACING: i32 = 8;
 const CONTROL_SPACING: i32 = 32;
 
+/// Volume sliders by control and direction, with their change signal handler.
+type Sliders = HashMap<(ControlHandle, Direction), (gtk::Scale, glib::signal::SignalHandlerId)>;
+
 /// Volume page.
 pub struct Volume {
     /// Page container
@@ -54,24 +59,16 @@ impl Page for Volume {
 
         let scroll_window = gtk::ScrolledWindowBuilder::new().expand(true).build();
 
+        // Vertical container for output and input sections
         let gbox = gtk::BoxBuilder::new()
             .expand(true)
-            .orientation(gtk::Orientation::Horizontal)
-            .spacing(CONTROL_SPACING)
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(SPACING)
             .margin(SPACING)
             .build();
 
-        let mut sliders = HashMap::new();
-
-        // Add a volume slider
-        for (control, props) in controls {
-            gbox.add(&build_volume_control(
-                core.clone(),
-                control,
-                props,
-                &mut sliders,
-            ));
-        }
+        let sliders = Rc::new(RefCell::new(HashMap::new()));
+        build_volume_sections(&core, &gbox, controls, &mut sliders.borrow_mut());
 
         scroll_window.add(&gbox);
         self.container.add(&scroll_window);
@@ -83,7 +80,9 @@ impl Page for Volume {
                 error!("Failed to send volume manager event to Glib: {:?}", err);
             }
         });
-        rx.attach(None, move |event| handle_volume_event(event, &sliders));
+        rx.attach(None, move |event| {
+            handle_volume_event(&core, event, &gbox, &sliders)
+        });
     }
 
     fn gtk_widget(&self) -> &gtk::Grid {
@@ -92,16 +91,38 @@ impl Page for Volume {
 }
 
 fn handle_volume_event(
+    core: &Arc<Core>,
     event: Event,
-    sliders: &HashMap<ControlHandle, (gtk::Scale, glib::signal::SignalHandlerId)>,
+    gbox: &gtk::Box,
+    sliders: &RefCell<Sliders>,
 ) -> glib::Continue {
     match event {
         // Update volume slider on volume change
         Event::Volume(control, volume) => {
-            if let Some((slider, change_handler)) = sliders.get(&control) {
-                slider.block_signal(change_handler);
-                slider.set_value(volume as f64);
-                slider.unblock_signal(change_handler);
+            update_slider(&sliders.borrow(), control, Direction::Playback, volume)
+        }
+        Event::CaptureVolume(control, volume) => {
+            update_slider(&sliders.borrow(), control, Direction::Capture, volume)
+        }
+        // Rebuild sliders if controls changed, such as when a sound card is plugged in
+        Event::Controls(controls) => {
+            let mut sliders = sliders.borrow_mut();
+            let keys: HashSet<_> = controls
+                .iter()
+                .flat_map(|(control, props)| {
+                    Direction::ALL
+                        .iter()
+                        .filter(move |d| props.volume(**d).is_some())
+                        .map(move |d| (control.clone(), *d))
+                })
+                .collect();
+            if keys.len() != sliders.len() || keys.iter().any(|k| !sliders.contains_key(k)) {
+                for child in gbox.get_children() {
+                    gbox.remove(&child);
+                }
+                sliders.clear();
+                build_volume_sections(core, gbox, controls, &mut sliders);
+                gbox.show_all();
             }
         }
         _ => {}
@@ -110,12 +131,86 @@ fn handle_volume_event(
     glib::Continue(true)
 }
 
+/// Set slider value for control, without invoking its change handler.
+fn update_slider(sliders: &Sliders, control: ControlHandle, direction: Direction, volume: i64) {
+    if let Some((slider, change_handler)) = sliders.get(&(control, direction)) {
+        slider.block_signal(change_handler);
+        slider.set_value(volume as f64);
+        slider.unblock_signal(change_handler);
+    }
+}
+
+/// Add output and input sections with volume sliders for all given controls.
+///
+/// The input section is omitted if there are no capture controls.
+fn build_volume_sections(
+    core: &Arc<Core>,
+    gbox: &gtk::Box,
+    controls: HashMap<ControlHandle, ControlProps>,
+    sliders: &mut Sliders,
+) {
+    // Show controls assigned to a role first, in role order
+    let mut controls: Vec<_> = controls
+        .into_iter()
+        .map(|(control, props)| (core.volume.get_control_role(&control), control, props))
+        .collect();
+    controls.sort_by_key(|(role, _, props)| (role.is_none(), *role, props.name.clone()));
+
+    for (direction, title) in &[
+        (Direction::Playback, "Output"),
+        (Direction::Capture, "Input"),
+    ] {
+        let direction = *direction;
+        let section: Vec<_> = controls
+            .iter()
+            .filter(|(_, _, props)| props.volume(direction).is_some())
+            .collect();
+        if section.is_empty() && direction == Direction::Capture {
+            continue;
+        }
+
+        let label = gtk::LabelBuilder::new()
+            .label(title)
+            .halign(gtk::Align::Start)
+            .build();
+        gbox.add(&label);
+
+        // Add a volume slider
+        let sliders_box = gtk::BoxBuilder::new()
+            .expand(true)
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(CONTROL_SPACING)
+            .build();
+        for (role, control, props) in section {
+            sliders_box.add(&build_volume_control(
+                core.clone(),
+                control.clone(),
+                props,
+                direction,
+                if direction == Direction::Playback {
+                    *role
+                } else {
+                    None
+                },
+                sliders,
+            ));
+        }
+        gbox.add(&sliders_box);
+    }
+}
+
 fn build_volume_control(
     core: Arc<Core>,
     control: ControlHandle,
-    props: ControlProps,
-    sliders: &mut HashMap<ControlHandle, (gtk::Scale, glib::signal::SignalHandlerId)>,
+    props: &ControlProps,
+    direction: Direction,
+    role: Option<Role>,
+    sliders: &mut Sliders,
 ) -> gtk::Box {
+    let volume = props
+        .volume(direction)
+        .expect("control has no volume in direction");
+
     let gbox = gtk::BoxBuilder::new()
         .orientation(gtk::Orientation::Vertical)
         .spacing(SPACING)
@@ -124,11 +219,11 @@ fn build_volume_control(
 
     let slider = gtk::Scale::new_with_range(
         gtk::Orientation::Vertical,
-        props.range.0 as f64,
-        props.range.1 as f64,
+        volume.range.0 as f64,
+        volume.range.1 as f64,
         1f64,
     );
-    slider.set_value(props.init_value as f64);
+    slider.set_value(volume.init_value as f64);
     slider.add_mark(20f64, PositionType::Right, Some("*"));
     slider.set_vexpand(true);
     slider.set_value_pos(PositionType::Bottom);
@@ -139,16 +234,19 @@ fn build_volume_control(
     // TODO: do not clone here, use cow in control?
     let closure_control = control.clone();
     let changed_handler = slider.connect_value_changed(move |slider| {
-        if let Err(err) = core.volume.send_cmd(Cmd::SetVolume(
-            closure_control.clone(),
-            slider.get_value() as i64,
-        )) {
+        let control = closure_control.clone();
+        let value = slider.get_value() as i64;
+        let cmd = match direction {
+            Direction::Playback => Cmd::SetVolume(control, value),
+            Direction::Capture => Cmd::SetCaptureVolume(control, value),
+        };
+        if let Err(err) = core.volume.send_cmd(cmd) {
             error!("Failed to set volume: {:?}", err);
         }
     });
 
     // Nicly format slider label
-    let (range_min, range_max) = (props.range.0 as f64, props.range.1 as f64);
+    let (range_min, range_max) = (volume.range.0 as f64, volume.range.1 as f64);
     slider.connect_format_value(move |_, value| {
         let diff = range_max - range_min;
 
@@ -166,11 +264,16 @@ fn build_volume_control(
         format!("{}%", value as i64)
     });
 
-    sliders.insert(control, (slider, changed_handler));
+    sliders.insert((control, direction), (slider, changed_handler));
 
-    // Add slider name label
+    // Add slider name label, prefixed with role
+    let name = props.name.as_deref().unwrap_or("?");
+    let label = match role {
+        Some(role) => format!("{}\n{}", role.name(), name),
+        None => name.into(),
+    };
     let label = gtk::LabelBuilder::new()
-        .label(props.name.as_deref().unwrap_or("?"))
+        .label(&label)
         .justify(gtk::Justification::Center)
         .single_line_mode(false)
         .wrap(true)
@@ -0,0 +1,3 @@
+mod vu_meter;
+
+pub use vu_meter::VuMeter;
@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use gtk::prelude::*;
+use pokoebox_audio::meter::{Event, Levels};
+
+use crate::app::Core;
+
+/// Lowest level shown on the meter in dB.
+const FLOOR_DB: f32 = -60.0;
+
+/// Spacing between channel bars.
+const SPACING: i32 = 2;
+
+/// VU meter showing output peak level per channel.
+pub struct VuMeter {
+    container: gtk::Box,
+}
+
+impl VuMeter {
+    /// Construct a new VU meter, updated through level meter events.
+    pub fn new(core: Arc<Core>) -> Self {
+        let container = gtk::BoxBuilder::new()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(SPACING)
+            .build();
+
+        // Update bars on level events
+        let bars = Rc::new(RefCell::new(Vec::new()));
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        core.meter.events.register_callback(move |event| {
+            if let Err(err) = tx.send(event) {
+                error!("Failed to send level meter event to Glib: {:?}", err);
+            }
+        });
+        let closure_container = container.clone();
+        rx.attach(None, move |event| {
+            let Event::Levels(levels) = event;
+            update_bars(&closure_container, &mut bars.borrow_mut(), &levels);
+            glib::Continue(true)
+        });
+
+        Self { container }
+    }
+
+    /// Get the GTK widget for this meter.
+    pub fn gtk_widget(&self) -> &gtk::Box {
+        &self.container
+    }
+}
+
+/// Update bars to given levels, rebuilding them if the number of channels changed.
+fn update_bars(container: &gtk::Box, bars: &mut Vec<gtk::LevelBar>, levels: &Levels) {
+    // Keep bars when going silent, levels are empty then
+    if levels.peak.is_empty() {
+        bars.iter().for_each(|bar| bar.set_value(0.0));
+        return;
+    }
+
+    if levels.peak.len() != bars.len() {
+        for bar in bars.drain(..) {
+            container.remove(&bar);
+        }
+        for _ in &levels.peak {
+            let bar = build_bar();
+            container.add(&bar);
+            bars.push(bar);
+        }
+        container.show_all();
+    }
+
+    for (bar, peak) in bars.iter().zip(&levels.peak) {
+        bar.set_value(level_to_value(*peak));
+    }
+}
+
+/// Build level bar for a single channel.
+fn build_bar() -> gtk::LevelBar {
+    let bar = gtk::LevelBarBuilder::new()
+        .orientation(gtk::Orientation::Vertical)
+        .inverted(true)
+        .min_value(0.0)
+        .max_value(1.0)
+        .width_request(6)
+        .build();
+
+    // Remove default offsets, these style low levels as a warning
+    bar.remove_offset_value(Some("low"));
+    bar.remove_offset_value(Some("high"));
+
+    bar
+}
+
+/// Map linear level to bar value, in dB between `FLOOR_DB` and 0.
+fn level_to_value(level: f32) -> f64 {
+    ((Levels::to_db(level) - FLOOR_DB) / -FLOOR_DB)
+        .max(0.0)
+        .min(1.0) as f64
+}
@@ -4,13 +4,17 @@ use glib::clone;
 use gtk::{prelude::*, Inhibit};
 
 use super::main::App;
+use crate::action::actions::ShutdownAction;
 use crate::app::Core;
-use crate::message::Message;
+use crate::message::{Confirm, Message};
 
 const TITLE: &str = "PokoeBox Player";
 const WIDTH: i32 = 1024;
 const HEIGHT: i32 = 600;
 
+/// Interval in milliseconds to check whether a confirmation dialog was answered elsewhere.
+const CONFIRM_POLL_INTERVAL: u32 = 250;
+
 /// The main window.
 pub struct Window {
     window: gtk::Window,
@@ -23,7 +27,8 @@ impl Window {
         let window = Self {
             window: Self::build_window(),
         };
-        window.setup_message_handler(core);
+        window.setup_message_handler(core.clone());
+        window.setup_delete_handler(core);
         window
     }
 
@@ -38,16 +43,17 @@ impl Window {
         window.set_position(gtk::WindowPosition::Center);
         window.set_default_size(WIDTH, HEIGHT);
 
-        // Connect the window delete event
-        window.connect_delete_event(|_, _| {
-            // Close the application
-            gtk::main_quit();
+        window
+    }
 
-            // Leave window destruction to GTK
-            Inhibit(false)
+    /// Set up the window delete handler.
+    ///
+    /// This shuts down the application, keeping the window until it exits.
+    fn setup_delete_handler(&self, core: Arc<Core>) {
+        self.window.connect_delete_event(move |_, _| {
+            core.actions.invoke(ShutdownAction, core.clone());
+            Inhibit(true)
         });
-
-        window
     }
 
     /// Set up the message pipe handler.
@@ -64,19 +70,10 @@ impl Window {
         rx.attach(
             None,
             clone!(@strong self.window as window => move |msg| {
-                // Build message dialog
-                let Message::Error(msg) = msg;
-                let dialog = gtk::MessageDialog::new(
-                    Some(&window),
-                    gtk::DialogFlags::MODAL,
-                    gtk::MessageType::Error,
-                    gtk::ButtonsType::Close,
-                    &msg,
-                );
-
-                // Show dialog, destroy on close
-                dialog.run();
-                dialog.destroy();
+                match msg {
+                    Message::Error(msg) => show_error(&window, &msg),
+                    Message::Confirm(confirm) => show_confirm(&window, confirm),
+                }
 
                 glib::Continue(true)
             }),
@@ -95,3 +92,53 @@ impl Window {
         self.window.show_all();
     }
 }
+
+/// Show error dialog, blocks until closed.
+fn show_error(window: &gtk::Window, msg: &str) {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Close,
+        msg,
+    );
+
+    // Show dialog, destroy on close
+    dialog.run();
+    dialog.destroy();
+}
+
+/// Show confirmation dialog.
+///
+/// Does not block, so the question can be answered or dismissed elsewhere in the meantime. The
+/// dialog closes when that happens.
+fn show_confirm(window: &gtk::Window, confirm: Confirm) {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        &confirm.text,
+    );
+    dialog.add_button("Reject", gtk::ResponseType::Reject);
+    dialog.add_button("Accept", gtk::ResponseType::Accept);
+
+    let closure_confirm = confirm.clone();
+    dialog.connect_response(move |dialog, response| {
+        closure_confirm.answer(response == gtk::ResponseType::Accept);
+        dialog.destroy();
+    });
+
+    // Close dialog when answered or dismissed elsewhere
+    let dialog_ref = dialog.downgrade();
+    gtk::timeout_add(CONFIRM_POLL_INTERVAL, move || match dialog_ref.upgrade() {
+        Some(dialog) if confirm.is_done() => {
+            dialog.destroy();
+            glib::Continue(false)
+        }
+        Some(_) => glib::Continue(true),
+        None => glib::Continue(false),
+    });
+
+    dialog.show_all();
+}
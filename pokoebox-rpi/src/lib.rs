@@ -3,6 +3,7 @@ extern crate log;
 
 pub mod button;
 pub mod led;
+pub mod output;
 pub mod power;
 pub mod rpi;
 mod util;
@@ -0,0 +1,25 @@
+use super::{Error, Output, OutputConfig};
+
+/// A NOP adapter, fakes communication and debugs it in the console.
+#[derive(Default)]
+pub struct Adapter;
+
+impl super::Adapter for Adapter {
+    fn setup_output(
+        &self,
+        output: Output,
+        config: OutputConfig,
+        active: bool,
+    ) -> Result<(), Error> {
+        debug!(
+            "NOP adapter set up output: {:?} ({:?}, active: {})",
+            output, config, active
+        );
+        Ok(())
+    }
+
+    fn output_set(&self, output: Output, active: bool) -> Result<(), Error> {
+        trace!("NOP adapter sets output: {:?} (active: {})", output, active);
+        Ok(())
+    }
+}
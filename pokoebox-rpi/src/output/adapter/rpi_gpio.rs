@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rppal::gpio::{Gpio, Level, OutputPin};
+
+use super::{Error, Output, OutputConfig};
+
+/// Adapter. Talks to outputs over GPIO.
+pub struct Adapter {
+    /// GPIO interface to access outputs.
+    gpio: Gpio,
+
+    /// Set up output pins, with whether they're active low.
+    pins: Mutex<HashMap<Output, (OutputPin, bool)>>,
+}
+
+impl Adapter {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            gpio: Gpio::new().map_err(|_| Error::Adapter)?,
+            pins: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl super::Adapter for Adapter {
+    fn setup_output(
+        &self,
+        output: Output,
+        config: OutputConfig,
+        active: bool,
+    ) -> Result<(), Error> {
+        let OutputConfig::Gpio { pin, active_low } = config;
+
+        // Keep output state when dropped, to not power down on exit unless asked to
+        let mut pin = self
+            .gpio
+            .get(pin)
+            .map_err(|_| Error::Adapter)?
+            .into_output();
+        pin.set_reset_on_drop(false);
+        pin.write(level(active, active_low));
+
+        self.pins
+            .lock()
+            .expect("failed to lock output pins")
+            .insert(output, (pin, active_low));
+        Ok(())
+    }
+
+    fn output_set(&self, output: Output, active: bool) -> Result<(), Error> {
+        let mut pins = self.pins.lock().expect("failed to lock output pins");
+        let (pin, active_low) = pins.get_mut(&output).ok_or(Error::NotSetUp(output))?;
+        pin.write(level(active, *active_low));
+        Ok(())
+    }
+}
+
+/// Get pin level for given output state.
+fn level(active: bool, active_low: bool) -> Level {
+    if active != active_low {
+        Level::High
+    } else {
+        Level::Low
+    }
+}
@@ -0,0 +1,11 @@
+use super::{Error, Output, OutputConfig};
+
+/// A generic output adapter.
+pub trait Adapter: Send + Sync {
+    /// Set-up the given output with its initial state.
+    fn setup_output(&self, output: Output, config: OutputConfig, active: bool)
+        -> Result<(), Error>;
+
+    /// Set whether the given output is active.
+    fn output_set(&self, output: Output, active: bool) -> Result<(), Error>;
+}
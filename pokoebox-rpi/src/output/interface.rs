@@ -0,0 +1,42 @@
+use super::adapter::{self, Adapter};
+use super::{Output, OutputConfig};
+
+/// Output interface.
+pub struct Interface {
+    /// Adapter to access outputs.
+    adapter: Box<dyn Adapter>,
+}
+
+impl Interface {
+    /// Construct new interface.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            adapter: adapter::select_adapter().map_err(Error::Adapter)?,
+        })
+    }
+
+    /// Set up the given output with its initial state.
+    pub fn setup_output(
+        &self,
+        output: Output,
+        config: OutputConfig,
+        active: bool,
+    ) -> Result<(), Error> {
+        self.adapter
+            .setup_output(output, config, active)
+            .map_err(Error::Adapter)
+    }
+
+    /// Set whether the given output is active.
+    pub fn output_set(&self, output: Output, active: bool) -> Result<(), Error> {
+        self.adapter
+            .output_set(output, active)
+            .map_err(Error::Adapter)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Adapter error.
+    Adapter(adapter::Error),
+}
@@ -0,0 +1,18 @@
+mod adapter;
+mod interface;
+
+pub use interface::{Error, Interface};
+
+/// List of available outputs.
+#[derive(Debug, Hash, Copy, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// Amplifier enable.
+    Amp,
+}
+
+/// Output configuration.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputConfig {
+    /// A GPIO pin, optionally active low.
+    Gpio { pin: u8, active_low: bool },
+}